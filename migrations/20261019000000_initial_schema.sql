@@ -0,0 +1,47 @@
+-- Tables as they existed before migrations were introduced. IF NOT EXISTS keeps
+-- this a no-op on databases that were created by hand.
+CREATE TABLE IF NOT EXISTS Vehicle (
+    license_plate VARCHAR(8) NOT NULL PRIMARY KEY,
+    vin VARCHAR(17),
+    make VARCHAR(255),
+    model VARCHAR(255),
+    registration_year VARCHAR(4),
+    engine_code VARCHAR(255),
+    circulation_from DATE,
+    circulation_to DATE,
+    description VARCHAR(255),
+    fuel VARCHAR(255),
+    vehicle_type VARCHAR(255)
+);
+
+CREATE TABLE IF NOT EXISTS Quote (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    license_plate VARCHAR(8),
+    monthly_price DECIMAL(12, 2),
+    client_email VARCHAR(255),
+    fuel_consumption DECIMAL(12, 2),
+    creation_timestamp DATETIME,
+    client_name VARCHAR(255),
+    labour_coverage DECIMAL(12, 2)
+);
+
+CREATE TABLE IF NOT EXISTS Sign (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    sign_link VARCHAR(255),
+    sign_method TINYINT NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    verified BOOLEAN NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS Plan (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    quote_id VARCHAR(36) NOT NULL,
+    client_email VARCHAR(255) NOT NULL,
+    vehicle VARCHAR(8) NOT NULL,
+    sign VARCHAR(36) NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    active BOOLEAN NOT NULL,
+    reveniu_id VARCHAR(255),
+    payment_link VARCHAR(255),
+    payment_method SMALLINT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Field level audit trail for changes made to Vehicle after it was created.
+CREATE TABLE VehicleHistory (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    license_plate VARCHAR(8) NOT NULL,
+    field VARCHAR(64) NOT NULL,
+    old_value VARCHAR(255),
+    new_value VARCHAR(255),
+    source VARCHAR(16) NOT NULL,
+    changed_by VARCHAR(255) NOT NULL,
+    changed_at DATETIME NOT NULL,
+    INDEX idx_vehicle_history_plate (license_plate, changed_at)
+);
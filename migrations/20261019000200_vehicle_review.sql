@@ -0,0 +1,4 @@
+-- Where the vehicle data came from and whether staff reviewed it. Existing
+-- rows all came from RegCheck.
+ALTER TABLE Vehicle ADD COLUMN source VARCHAR(16) NOT NULL DEFAULT 'provider';
+ALTER TABLE Vehicle ADD COLUMN review_status VARCHAR(16) NOT NULL DEFAULT 'approved';
@@ -0,0 +1,36 @@
+-- Canonical vehicle categories used for pricing, and the mapping from the
+-- vehicle_type strings RegCheck returns to them. Multipliers start at what
+-- quotes were already charged: station wagons 1.10 and everything else 1.00.
+CREATE TABLE VehicleCategory (
+    code VARCHAR(32) NOT NULL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    price_multiplier DECIMAL(4, 2) NOT NULL
+);
+
+INSERT INTO VehicleCategory (code, name, price_multiplier) VALUES
+    ('city_car', 'City car', 1.00),
+    ('sedan', 'Sedan', 1.00),
+    ('hatchback', 'Hatchback', 1.00),
+    ('station_wagon', 'Station wagon', 1.10),
+    ('suv', 'SUV', 1.00),
+    ('pickup', 'Pickup', 1.00),
+    ('van', 'Van', 1.00),
+    ('truck', 'Truck', 1.00),
+    ('motorcycle', 'Motorcycle', 1.00);
+
+CREATE TABLE VehicleTypeMapping (
+    provider_type VARCHAR(255) NOT NULL PRIMARY KEY,
+    category VARCHAR(32) NOT NULL,
+    updated_by VARCHAR(255) NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (category) REFERENCES VehicleCategory (code)
+);
+
+INSERT INTO VehicleTypeMapping (provider_type, category, updated_by, updated_at) VALUES
+    ('AUTOMOVIL', 'sedan', 'migration', UTC_TIMESTAMP()),
+    ('STATION WAGON', 'station_wagon', 'migration', UTC_TIMESTAMP()),
+    ('CAMIONETA', 'pickup', 'migration', UTC_TIMESTAMP()),
+    ('JEEP', 'suv', 'migration', UTC_TIMESTAMP()),
+    ('FURGON', 'van', 'migration', UTC_TIMESTAMP()),
+    ('CAMION', 'truck', 'migration', UTC_TIMESTAMP()),
+    ('MOTO', 'motorcycle', 'migration', UTC_TIMESTAMP());
@@ -0,0 +1,17 @@
+-- Known makes and models, used for autocomplete and to canonicalise manual
+-- input. `normalized` is the upper-case alphanumeric form used for matching.
+CREATE TABLE CatalogueMake (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    normalized VARCHAR(255) NOT NULL,
+    UNIQUE KEY uq_catalogue_make_normalized (normalized)
+);
+
+CREATE TABLE CatalogueModel (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    make_id BIGINT NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    normalized VARCHAR(255) NOT NULL,
+    UNIQUE KEY uq_catalogue_model_normalized (make_id, normalized),
+    FOREIGN KEY (make_id) REFERENCES CatalogueMake (id)
+);
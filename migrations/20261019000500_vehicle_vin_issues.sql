@@ -0,0 +1,3 @@
+-- Mismatches between the decoded VIN and the stored make/year, for staff to
+-- look at. NULL when the VIN is consistent or missing.
+ALTER TABLE Vehicle ADD COLUMN vin_issues VARCHAR(1024);
@@ -0,0 +1,12 @@
+-- Raw provider responses, one row per lookup, so vehicle fields can be
+-- derived again when the parser improves.
+CREATE TABLE VehicleLookup (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    license_plate VARCHAR(8) NOT NULL,
+    provider VARCHAR(32) NOT NULL,
+    payload MEDIUMTEXT NOT NULL,
+    fetched_at DATETIME NOT NULL,
+    INDEX idx_vehicle_lookup_plate (license_plate, fetched_at)
+);
+
+ALTER TABLE Vehicle ADD COLUMN image_url VARCHAR(512);
@@ -0,0 +1,24 @@
+-- Outbox of client notifications. Messages are rendered when queued and kept
+-- after sending, so every email we sent (or failed to send) can be audited.
+CREATE TABLE Notification (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    recipient VARCHAR(255) NOT NULL,
+    template VARCHAR(32) NOT NULL,
+    reference_id VARCHAR(36),
+    subject VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    creation_timestamp DATETIME NOT NULL,
+    next_attempt_at DATETIME NOT NULL,
+    sent_at DATETIME,
+    INDEX idx_notification_due (status, next_attempt_at),
+    INDEX idx_notification_recipient (recipient)
+);
+
+-- Clients who asked not to receive notifications.
+CREATE TABLE NotificationOptOut (
+    email VARCHAR(255) NOT NULL PRIMARY KEY,
+    creation_timestamp DATETIME NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Notifications can go out by WhatsApp or SMS; `recipient` is then the
+-- client's phone number in E.164 form.
+ALTER TABLE Notification ADD COLUMN channel VARCHAR(16) NOT NULL DEFAULT 'email';
+
+ALTER TABLE Quote ADD COLUMN client_phone VARCHAR(16);
+
+-- How each client (by email) wants to be notified.
+CREATE TABLE NotificationPreference (
+    email VARCHAR(255) NOT NULL PRIMARY KEY,
+    phone VARCHAR(16),
+    channel VARCHAR(16) NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,31 @@
+-- Background jobs. Rows are kept after they finish so runs can be inspected;
+-- the prune-jobs job deletes old successful ones.
+CREATE TABLE Job (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    kind VARCHAR(32) NOT NULL,
+    payload TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL,
+    last_error TEXT,
+    creation_timestamp DATETIME NOT NULL,
+    run_at DATETIME NOT NULL,
+    -- While running, other workers leave the job alone until this passes.
+    locked_until DATETIME,
+    started_at DATETIME,
+    finished_at DATETIME,
+    INDEX idx_job_due (status, run_at),
+    INDEX idx_job_kind (kind, creation_timestamp)
+);
+
+-- Next run of each scheduled job, shared by every instance so a schedule
+-- fires once even with several of them running.
+CREATE TABLE JobSchedule (
+    name VARCHAR(32) NOT NULL PRIMARY KEY,
+    schedule VARCHAR(64) NOT NULL,
+    next_run_at DATETIME NOT NULL,
+    last_run_at DATETIME
+);
+
+-- Quotes not turned into a plan in time can't be used anymore.
+ALTER TABLE Quote ADD COLUMN expired_at DATETIME;
@@ -0,0 +1,41 @@
+-- Workshops where plan maintenance is done. Appointments start on a slot
+-- boundary (opens_at + n * slot_minutes) on one of the open weekdays, and
+-- each slot takes up to `capacity` vehicles. Times are local (Chile).
+CREATE TABLE Workshop (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    address VARCHAR(255) NOT NULL,
+    comuna VARCHAR(64) NOT NULL,
+    region VARCHAR(64) NOT NULL,
+    latitude DECIMAL(9, 6),
+    longitude DECIMAL(9, 6),
+    capacity INT NOT NULL,
+    opens_at TIME NOT NULL,
+    closes_at TIME NOT NULL,
+    slot_minutes INT NOT NULL,
+    -- ISO weekday numbers, e.g. "1,2,3,4,5" for Monday to Friday.
+    weekdays VARCHAR(16) NOT NULL,
+    active BOOLEAN NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    INDEX idx_workshop_location (region, comuna)
+);
+
+CREATE TABLE WorkshopService (
+    workshop_id VARCHAR(36) NOT NULL,
+    service VARCHAR(32) NOT NULL,
+    PRIMARY KEY (workshop_id, service)
+);
+
+CREATE TABLE Appointment (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    plan_id VARCHAR(36) NOT NULL,
+    workshop_id VARCHAR(36) NOT NULL,
+    license_plate VARCHAR(8) NOT NULL,
+    service VARCHAR(32) NOT NULL,
+    starts_at DATETIME NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    INDEX idx_appointment_slot (workshop_id, starts_at),
+    INDEX idx_appointment_plan (plan_id, starts_at)
+);
@@ -0,0 +1,54 @@
+-- Manufacturer service intervals. NULL criteria match any vehicle, and for
+-- each service the most specific matching row wins. `engine_code` is a
+-- prefix, since RegCheck returns the full engine number. A NULL
+-- `every_months` means the service isn't due for matching vehicles.
+CREATE TABLE MaintenanceInterval (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    make VARCHAR(255),
+    model VARCHAR(255),
+    fuel VARCHAR(32),
+    engine_code VARCHAR(32),
+    year_from INT,
+    year_to INT,
+    service VARCHAR(32) NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    every_months INT
+);
+
+INSERT INTO MaintenanceInterval (make, model, fuel, engine_code, year_from, year_to, service, description, every_months) VALUES
+    (NULL, NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Periodic maintenance', 6),
+    (NULL, NULL, NULL, NULL, NULL, NULL, 'oil_change', 'Engine oil and filter change', 6),
+    (NULL, NULL, NULL, NULL, NULL, NULL, 'brakes', 'Brake pads and fluid check', 12),
+    (NULL, NULL, NULL, NULL, NULL, NULL, 'tires', 'Tire rotation and alignment', 6),
+    (NULL, NULL, NULL, NULL, NULL, NULL, 'inspection', 'Revisión técnica', 12),
+    (NULL, NULL, 'DIESEL', NULL, NULL, NULL, 'oil_change', 'Engine oil and filter change', 4),
+    (NULL, NULL, 'ELECTRICO', NULL, NULL, NULL, 'oil_change', 'Engine oil and filter change', NULL),
+    (NULL, NULL, 'ELECTRICO', NULL, NULL, NULL, 'electrical', 'Battery and charging system check', 12),
+    (NULL, NULL, 'HIBRIDO', NULL, NULL, NULL, 'electrical', 'Hybrid battery check', 12),
+    ('TOYOTA', NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Toyota periodic maintenance', 6),
+    ('TOYOTA', NULL, NULL, '1KD', NULL, NULL, 'oil_change', 'Engine oil and filter change', 3),
+    ('HYUNDAI', NULL, NULL, NULL, 2015, NULL, 'maintenance', 'Hyundai periodic maintenance', 12),
+    ('HYUNDAI', NULL, NULL, NULL, 2015, NULL, 'oil_change', 'Engine oil and filter change', 12),
+    ('KIA', NULL, NULL, NULL, 2015, NULL, 'maintenance', 'Kia periodic maintenance', 12),
+    ('KIA', NULL, NULL, NULL, 2015, NULL, 'oil_change', 'Engine oil and filter change', 12),
+    ('CHEVROLET', NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Chevrolet periodic maintenance', 6),
+    ('NISSAN', NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Nissan periodic maintenance', 6),
+    ('SUZUKI', NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Suzuki periodic maintenance', 6),
+    ('MERCEDES-BENZ', NULL, NULL, NULL, NULL, NULL, 'maintenance', 'Mercedes-Benz service A/B', 12),
+    ('MERCEDES-BENZ', NULL, NULL, NULL, NULL, NULL, 'oil_change', 'Engine oil and filter change', 12);
+
+-- Services due over a plan's term, generated from the intervals the first
+-- time the schedule is read. Completing an appointment marks the earliest
+-- pending item for its service as done.
+CREATE TABLE MaintenanceItem (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    plan_id VARCHAR(36) NOT NULL,
+    service VARCHAR(32) NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    cycle INT NOT NULL,
+    due_date DATE NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    appointment_id VARCHAR(36),
+    done_at DATETIME,
+    INDEX idx_maintenance_item_plan (plan_id, due_date)
+);
@@ -0,0 +1,39 @@
+-- Labour claims workshops submit against a plan's coverage. Rejected claims
+-- are kept with the codes of the rules they broke.
+CREATE TABLE CoverageClaim (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    plan_id VARCHAR(36) NOT NULL,
+    workshop_id VARCHAR(36) NOT NULL,
+    appointment_id VARCHAR(36),
+    status VARCHAR(16) NOT NULL,
+    labour_hours DECIMAL(8, 2) NOT NULL,
+    amount DECIMAL(12, 2) NOT NULL,
+    -- Comma-separated rejection codes, NULL when approved.
+    rejection_reasons VARCHAR(255),
+    submitted_by VARCHAR(255) NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    INDEX idx_coverage_claim_plan (plan_id, creation_timestamp),
+    INDEX idx_coverage_claim_appointment (appointment_id)
+);
+
+CREATE TABLE CoverageClaimLine (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    claim_id VARCHAR(36) NOT NULL,
+    service VARCHAR(32) NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    labour_hours DECIMAL(8, 2) NOT NULL,
+    amount DECIMAL(12, 2) NOT NULL,
+    INDEX idx_coverage_claim_line_claim (claim_id)
+);
+
+-- Coverage consumed by each approved claim, with what was left after it.
+-- A plan's used coverage is the sum of its entries.
+CREATE TABLE CoverageLedger (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    plan_id VARCHAR(36) NOT NULL,
+    claim_id VARCHAR(36) NOT NULL,
+    amount DECIMAL(12, 2) NOT NULL,
+    balance DECIMAL(12, 2) NOT NULL,
+    creation_timestamp DATETIME NOT NULL,
+    INDEX idx_coverage_ledger_plan (plan_id)
+);
@@ -0,0 +1,4 @@
+-- Channel asked for with the quote. It and the quote's phone are only used
+-- for notifications about that quote and its plan, never stored as the
+-- client's preference: anyone can create a quote for any email.
+ALTER TABLE Quote ADD COLUMN notification_channel VARCHAR(16);
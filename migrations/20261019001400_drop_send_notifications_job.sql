@@ -0,0 +1,4 @@
+-- Due notifications are now sent by the job worker's poll loop instead of a
+-- send-notifications job, so its schedule and past runs are removed.
+DELETE FROM Job WHERE kind = 'send-notifications';
+DELETE FROM JobSchedule WHERE name = 'send-notifications';
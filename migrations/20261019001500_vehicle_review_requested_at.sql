@@ -0,0 +1,4 @@
+-- When a vehicle last entered the review queue, so staff see the oldest
+-- first. Vehicles already waiting are stamped with the migration time.
+ALTER TABLE Vehicle ADD COLUMN review_requested_at DATETIME;
+UPDATE Vehicle SET review_requested_at = UTC_TIMESTAMP() WHERE review_status = 'pending';
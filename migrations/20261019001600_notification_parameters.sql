@@ -0,0 +1,4 @@
+-- Values of a WhatsApp or SMS notification's placeholders, in order, as a
+-- JSON array. WhatsApp sends them with the approved template of the same
+-- name; SMS sends the rendered body.
+ALTER TABLE Notification ADD COLUMN parameters TEXT;
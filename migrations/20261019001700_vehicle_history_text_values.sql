@@ -0,0 +1,5 @@
+-- History values mirror Vehicle columns, some of which (image_url) are longer
+-- than 255 characters.
+ALTER TABLE VehicleHistory
+    MODIFY old_value TEXT,
+    MODIFY new_value TEXT;
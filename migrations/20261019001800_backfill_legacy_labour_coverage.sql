@@ -0,0 +1,4 @@
+-- Quotes imported from the legacy Redis flow were stored without labour
+-- coverage. Quotes cover 15 times the monthly price before IVA.
+UPDATE Quote SET labour_coverage = TRUNCATE(monthly_price / 1.19 * 15, 2)
+WHERE labour_coverage IS NULL AND monthly_price IS NOT NULL;
@@ -0,0 +1,106 @@
+use axum::Json;
+use chrono::Utc;
+use http::StatusCode;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::OnceLock;
+
+use crate::auth::{Principal, Role};
+
+// Client links stay valid for 30 days unless CLIENT_TOKEN_TTL_SECONDS says otherwise.
+const DEFAULT_TTL_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+// HS256 keys shorter than the hash output make tokens easy to brute force.
+const MIN_SECRET_LEN: usize = 32;
+
+static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Quote,
+    Plan,
+    // Notification preferences of the email address in `sub`.
+    Notifications,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AccessClaims {
+    sub: String,
+    resource: Resource,
+    exp: i64,
+}
+
+// CLIENT_TOKEN_SECRET signs client tokens. It is read once; an unset, empty
+// or short secret stops the API instead of issuing guessable links.
+pub fn secret() -> &'static [u8] {
+    SECRET.get_or_init(|| {
+        let secret = env::var("CLIENT_TOKEN_SECRET").unwrap_or_default();
+        if secret.len() < MIN_SECRET_LEN {
+            panic!(
+                "CLIENT_TOKEN_SECRET must be at least {} bytes long",
+                MIN_SECRET_LEN
+            );
+        }
+        secret.into_bytes()
+    })
+}
+
+// Issues the token a client uses to view a quote or plan (or manage their
+// notifications) through its link.
+pub fn issue_access_token(resource: Resource, id: &str) -> String {
+    let ttl = env::var("CLIENT_TOKEN_TTL_SECONDS")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(DEFAULT_TTL_SECONDS);
+
+    let claims = AccessClaims {
+        sub: id.to_string(),
+        resource,
+        exp: Utc::now().timestamp() + ttl,
+    };
+
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(secret()),
+    )
+    .unwrap()
+}
+
+pub fn verify_access_token(resource: Resource, id: &str, token: &str) -> bool {
+    let claims = decode::<AccessClaims>(
+        token,
+        &DecodingKey::from_secret(secret()),
+        &Validation::new(Algorithm::HS256),
+    );
+
+    match claims {
+        Ok(data) => data.claims.resource == resource && data.claims.sub == id,
+        Err(_) => false,
+    }
+}
+
+// Lets the request through if it carries a valid client token for the
+// resource, or comes from staff.
+pub fn authorize_client_access(
+    resource: Resource,
+    id: &str,
+    token: Option<&str>,
+    principal: Option<&Principal>,
+) -> Result<(), (StatusCode, Json<String>)> {
+    if let Some(principal) = principal {
+        if principal.has_any_role(&[Role::Sales, Role::Support]) {
+            return Ok(());
+        }
+    }
+
+    match token {
+        Some(token) if verify_access_token(resource, id, token) => Ok(()),
+        _ => Err((
+            StatusCode::FORBIDDEN,
+            Json(String::from("Missing or invalid access token")),
+        )),
+    }
+}
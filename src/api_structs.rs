@@ -2,42 +2,174 @@
 
 use serde::{Deserialize, Serialize};
 use sqlx::types::time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-use crate::helper_structs::{PaymentMethod, SignMethod, VehicleDescription};
+use crate::coverage::Coverage;
+use crate::license_plate::LicensePlate;
+use crate::messaging::Channel;
+use crate::phone::PhoneNumber;
+use crate::vehicle_history::{ChangeSource, ReviewStatus};
+use crate::vin::describe_vin_issues;
+use crate::validation::{
+    validate_date, validate_uuid, validate_vin, validate_year,
+};
+use crate::helper_structs::{
+    PaymentMethod, PaymentMethodName, SignMethod, SignMethodName, VehicleDescription,
+};
 
 // Query Params
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GetVehicleQP {
     pub license_plate: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccessTokenQP {
+    pub access_token: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationsQP {
+    pub recipient: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobsQP {
+    // "pending", "running", "succeeded" or "dead"
+    pub status: Option<String>,
+    // e.g. "refresh-vehicle" or "create-reveniu-plan"
+    pub kind: Option<String>,
+    // Defaults to 100.
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CatalogueQP {
+    // Prefix or approximate name; everything is listed when missing.
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
 // Post Params
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct CreateQuoteBody {
+    // km/l
+    #[validate(range(min = 1.0, max = 50.0, message = "must be between 1 and 50 km/l"))]
     pub fuel_consumption: f32,
+    #[validate(email(message = "must be a valid email address"))]
     pub email: String,
-    pub license_plate: String,
+    pub license_plate: LicensePlate,
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
     pub client_name: String,
+    pub phone: Option<PhoneNumber>,
+    // Defaults to email; WhatsApp and SMS need a phone. Only used for this
+    // quote and its plan, PUT /notifications/preferences sets the client's.
+    pub notification_channel: Option<Channel>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema, Validate)]
 pub struct CreatePlanBody {
+    #[validate(custom = "validate_uuid")]
     pub quote_id: String,
     pub payment_method: PaymentMethod,
     pub sign_method: SignMethod,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct CreatePlanBodyV2 {
+    #[validate(custom = "validate_uuid")]
+    pub quote_id: String,
+    pub payment_method: PaymentMethodName,
+    pub sign_method: SignMethodName,
+}
+
+impl From<CreatePlanBodyV2> for CreatePlanBody {
+    fn from(body: CreatePlanBodyV2) -> Self {
+        CreatePlanBody {
+            quote_id: body.quote_id,
+            payment_method: body.payment_method.0,
+            sign_method: body.sign_method.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, ToSchema, Validate)]
 pub struct ManualVehicleCreation{
+    #[validate(length(min = 1, max = 50, message = "must be between 1 and 50 characters"))]
     pub make: String,
+    #[validate(length(min = 1, max = 50, message = "must be between 1 and 50 characters"))]
     pub model: String,
+    #[validate(custom = "validate_year")]
     pub year: String,
-    pub license_plate: String,
+    pub license_plate: LicensePlate,
+    // Category code or a known provider vehicle type, checked by the handler.
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
     pub vehicle_type: String,
 }
 
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct VehicleUpdate {
+    #[validate(custom = "validate_vin")]
+    pub vin: Option<String>,
+    #[validate(length(min = 1, max = 50, message = "must be between 1 and 50 characters"))]
+    pub make: Option<String>,
+    #[validate(length(min = 1, max = 50, message = "must be between 1 and 50 characters"))]
+    pub model: Option<String>,
+    #[validate(custom = "validate_year")]
+    pub year: Option<String>,
+    pub engine_code: Option<String>,
+    // YYYY-MM-DD
+    #[validate(custom = "validate_date")]
+    pub circulation_from: Option<String>,
+    // YYYY-MM-DD
+    #[validate(custom = "validate_date")]
+    pub circulation_to: Option<String>,
+    pub description: Option<String>,
+    pub fuel: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub vehicle_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct VehicleReview {
+    pub approved: bool,
+}
+
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct NotificationOptOut {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct NotificationPreferenceUpdate {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    // Required for WhatsApp and SMS unless already known.
+    pub phone: Option<PhoneNumber>,
+    pub channel: Channel,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentOutcome {
+    Received,
+    Failed,
+}
+
+#[derive(Deserialize, Debug, ToSchema, Validate)]
+pub struct PaymentEvent {
+    pub outcome: PaymentOutcome,
+}
+
 // Responses
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub struct Vehicle {
     pub license_plate: String,
     pub vehicle_type: Option<String>,
@@ -50,6 +182,15 @@ pub struct Vehicle {
     pub engine_code: Option<String>,
     pub fuel: Option<String>,
     pub year: Option<String>,
+    // "provider" or "manual"
+    pub source: String,
+    // "pending", "approved" or "rejected"; manual entries start as pending.
+    pub review_status: String,
+    // Notes about the VIN. A VIN that doesn't match the make or year also
+    // puts the vehicle up for review; one that can't be decoded doesn't.
+    pub vin_issues: Option<String>,
+    // Picture of the make/model from the provider, if it sent one.
+    pub image_url: Option<String>,
 }
 
 impl Vehicle {
@@ -57,6 +198,16 @@ impl Vehicle {
         vehicle_data: VehicleDescription,
         license_plate: String,
     ) -> Self {
+        let vin_issues = describe_vin_issues(
+            Some(&vehicle_data.vin),
+            Some(&vehicle_data.make_description.current_text_value),
+            Some(&vehicle_data.registration_year),
+        );
+        let review_status = match &vin_issues {
+            Some(issues) if issues.needs_review => ReviewStatus::Pending,
+            _ => ReviewStatus::Approved,
+        };
+
         Vehicle {
             license_plate: license_plate,
             vin: Some(vehicle_data.vin),
@@ -69,21 +220,119 @@ impl Vehicle {
             description: Some(vehicle_data.description),
             year: Some(vehicle_data.registration_year),
             vehicle_type: Some(vehicle_data.vehicle_type),
+            source: ChangeSource::Provider.as_str().to_string(),
+            review_status: review_status.as_str().to_string(),
+            vin_issues: vin_issues.map(|issues| issues.notes),
+            image_url: Some(vehicle_data.image_url).filter(|url| !url.is_empty()),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VehicleChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    // "manual" or "provider"
+    pub source: String,
+    pub changed_by: String,
+    pub changed_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VehicleUpdateResult {
+    pub vehicle: Vehicle,
+    pub changes: Vec<VehicleChange>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Quote {
     pub id: String,
     pub labour_coverage: f32,
     pub monthly_cost: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Plan {
     pub id: String,
     pub payment_link: Option<String>,
     pub payment_method: PaymentMethod,
     pub sign_method: SignMethod,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    // Labour coverage used and left. Only when fetching the plan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<Coverage>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlanV2 {
+    pub id: String,
+    pub payment_link: Option<String>,
+    pub payment_method: PaymentMethodName,
+    pub sign_method: SignMethodName,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    // Labour coverage used and left. Only when fetching the plan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<Coverage>,
+}
+
+impl From<Plan> for PlanV2 {
+    fn from(plan: Plan) -> Self {
+        PlanV2 {
+            id: plan.id,
+            payment_link: plan.payment_link,
+            payment_method: PaymentMethodName(plan.payment_method),
+            sign_method: SignMethodName(plan.sign_method),
+            access_token: plan.access_token,
+            coverage: plan.coverage,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NotificationPreference {
+    pub email: String,
+    pub phone: Option<PhoneNumber>,
+    pub channel: Channel,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Notification {
+    pub id: String,
+    // "email", "whatsapp" or "sms"
+    pub channel: String,
+    // Email address, or phone number for WhatsApp and SMS.
+    pub recipient: String,
+    pub template: String,
+    pub reference_id: Option<String>,
+    pub subject: String,
+    // "pending", "sent", "failed" or "skipped" (recipient opted out)
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct JobRun {
+    pub id: String,
+    pub kind: String,
+    // The job and its arguments, e.g. {"kind": "refresh-vehicle", "license_plate": "GKSB78"}
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    // "pending", "running", "succeeded" or "dead" (gave up after max_attempts)
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    // When the job runs next, while pending.
+    pub run_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
 }
@@ -0,0 +1,514 @@
+use axum::{
+    extract::{Path, Query},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlConnection;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::access_token::{authorize_client_access, Resource};
+use crate::api_structs::AccessTokenQP;
+use crate::auth::Principal;
+use crate::maintenance::{ensure_schedule, mark_done};
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::validation::{validate_uuid, ValidatedJson, ValidationRejection};
+use crate::workshops::{count_booked, get_workshop, local_now, Service, DATETIME_FORMAT};
+
+pub const BOOKED: &str = "booked";
+const CANCELLED: &str = "cancelled";
+pub const COMPLETED: &str = "completed";
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct AppointmentBody {
+    #[validate(custom = "validate_uuid")]
+    pub workshop_id: String,
+    pub service: Service,
+    // Start of one of the workshop's slots, YYYY-MM-DDTHH:MM:SS local time.
+    pub starts_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Appointment {
+    pub id: String,
+    pub plan_id: String,
+    pub workshop_id: String,
+    pub workshop_name: String,
+    pub workshop_address: String,
+    pub license_plate: String,
+    // One of the services in Workshop.services
+    pub service: String,
+    // YYYY-MM-DDTHH:MM:SS, local time
+    pub starts_at: String,
+    // "booked", "cancelled" or "completed"
+    pub status: String,
+    pub created_at: String,
+}
+
+fn conflict(message: &str) -> Response {
+    (StatusCode::CONFLICT, Json(message.to_string())).into_response()
+}
+
+fn not_found(message: &str) -> Response {
+    (StatusCode::NOT_FOUND, Json(message.to_string())).into_response()
+}
+
+// The vehicle on the plan, if the plan can book appointments. The plan row
+// stays locked until the transaction ends, so concurrent bookings for the
+// same plan (even at different workshops) see each other's appointments.
+pub async fn get_active_plan_vehicle(
+    conn: &mut MySqlConnection,
+    plan_id: &str,
+) -> Result<String, Response> {
+    db_breadcrumb("select Plan vehicle for update");
+
+    let plan = sqlx::query!(r#"select vehicle, active as "active: bool" from Plan where id = ? for update"#, plan_id)
+        .fetch_optional(conn)
+        .await
+        .unwrap();
+
+    match plan {
+        Some(plan) if plan.active => Ok(plan.vehicle),
+        Some(_) => Err(conflict(
+            "The plan isn't active yet, appointments can be booked once it's paid",
+        )),
+        None => Err(not_found("Plan not found")),
+    }
+}
+
+async fn get_appointment(
+    conn: &mut MySqlConnection,
+    plan_id: &str,
+    appointment_id: &str,
+) -> Option<Appointment> {
+    db_breadcrumb("select Appointment by id");
+
+    sqlx::query_as!(
+        Appointment,
+        r#"select A.id, A.plan_id, A.workshop_id, W.name as workshop_name, W.address as workshop_address,
+        A.license_plate, A.service,
+        DATE_FORMAT(A.starts_at, '%Y-%m-%dT%H:%i:%s') as "starts_at!", A.status,
+        DATE_FORMAT(A.creation_timestamp, '%Y-%m-%dT%TZ') as "created_at!"
+        from Appointment A join Workshop W on A.workshop_id = W.id
+        where A.id = ? and A.plan_id = ?"#,
+        appointment_id,
+        plan_id
+    )
+    .fetch_optional(conn)
+    .await
+    .unwrap()
+}
+
+// Checks that the appointment can take the slot, locking the workshop so
+// concurrent bookings can't overfill it. `appointment_id` is the appointment
+// being rescheduled, which doesn't count against the capacity.
+async fn check_slot(
+    conn: &mut MySqlConnection,
+    body: &AppointmentBody,
+    appointment_id: Option<&str>,
+) -> Result<NaiveDateTime, Response> {
+    let invalid_start = || {
+        ValidationRejection::field(
+            "starts_at",
+            "slot",
+            "must be the start of one of the workshop's upcoming slots",
+        )
+        .into_response()
+    };
+
+    let starts_at = NaiveDateTime::parse_from_str(&body.starts_at, DATETIME_FORMAT)
+        .map_err(|_| invalid_start())?;
+
+    let workshop = match get_workshop(conn, &body.workshop_id, true).await {
+        Some(workshop) if workshop.active => workshop,
+        _ => {
+            return Err(ValidationRejection::field(
+                "workshop_id",
+                "workshop",
+                "must be an active workshop",
+            )
+            .into_response())
+        }
+    };
+
+    if !workshop.services.contains(&body.service) {
+        return Err(ValidationRejection::field(
+            "service",
+            "service",
+            "is not offered by the workshop",
+        )
+        .into_response());
+    }
+
+    if starts_at <= local_now() || !workshop.opening_hours().is_slot(starts_at) {
+        return Err(invalid_start());
+    }
+
+    let booked = count_booked(
+        conn,
+        &workshop.id,
+        starts_at,
+        starts_at + Duration::seconds(1),
+        appointment_id,
+    )
+    .await
+    .into_iter()
+    .map(|(_, count)| count)
+    .sum::<i64>();
+
+    if booked >= workshop.capacity as i64 {
+        return Err(conflict("The slot is full, please pick another one"));
+    }
+
+    Ok(starts_at)
+}
+
+#[utoipa::path(
+    get,
+    path = "/plan/{plan_id}/appointments",
+    tag = "appointments",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Appointments of the plan, latest first", body = [Appointment]),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_appointments(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Appointments by plan");
+
+    let appointments = sqlx::query_as!(
+        Appointment,
+        r#"select A.id, A.plan_id, A.workshop_id, W.name as workshop_name, W.address as workshop_address,
+        A.license_plate, A.service,
+        DATE_FORMAT(A.starts_at, '%Y-%m-%dT%H:%i:%s') as "starts_at!", A.status,
+        DATE_FORMAT(A.creation_timestamp, '%Y-%m-%dT%TZ') as "created_at!"
+        from Appointment A join Workshop W on A.workshop_id = W.id
+        where A.plan_id = ? order by A.starts_at desc"#,
+        plan_id
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap();
+
+    (StatusCode::OK, Json(appointments)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/plan/{plan_id}/appointments",
+    tag = "appointments",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    request_body = AppointmentBody,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 201, description = "Appointment booked for the plan's vehicle", body = Appointment),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+        (status = 404, description = "Plan not found", body = String),
+        (status = 409, description = "Plan not active, already has an upcoming appointment, or the slot is full", body = String),
+        (status = 422, description = "Invalid request body, or the workshop can't take it", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn book_appointment(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(body): ValidatedJson<AppointmentBody>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+
+    let license_plate = match get_active_plan_vehicle(&mut tx, &plan_id).await {
+        Ok(license_plate) => license_plate,
+        Err(res) => return res,
+    };
+
+    // One upcoming appointment at a time; changing it is a reschedule.
+    db_breadcrumb("select upcoming Appointment by plan");
+    let upcoming = sqlx::query!(
+        r#"select id from Appointment where plan_id = ? and status = ?
+        and starts_at > STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')"#,
+        plan_id,
+        BOOKED,
+        local_now().format(DATETIME_FORMAT).to_string()
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .unwrap();
+    if upcoming.is_some() {
+        return conflict("The plan already has an upcoming appointment, reschedule it instead");
+    }
+
+    let starts_at = match check_slot(&mut tx, &body, None).await {
+        Ok(starts_at) => starts_at,
+        Err(res) => return res,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    db_breadcrumb("insert Appointment");
+    sqlx::query!(
+        r#"insert into Appointment(id, plan_id, workshop_id, license_plate, service, starts_at, status,
+        creation_timestamp, updated_at)
+        values (?, ?, ?, ?, ?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), ?,
+        STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        id,
+        plan_id,
+        body.workshop_id,
+        license_plate,
+        body.service.as_str(),
+        starts_at.format(DATETIME_FORMAT).to_string(),
+        BOOKED,
+        timestamp,
+        timestamp
+    )
+    .execute(&mut tx)
+    .await
+    .unwrap();
+
+    let appointment = get_appointment(&mut tx, &plan_id, &id).await.unwrap();
+    tx.commit().await.unwrap();
+
+    (StatusCode::CREATED, Json(appointment)).into_response()
+}
+
+#[utoipa::path(
+    put,
+    path = "/plan/{plan_id}/appointments/{appointment_id}",
+    tag = "appointments",
+    params(
+        ("plan_id" = String, Path, description = "Plan id"),
+        ("appointment_id" = String, Path, description = "Appointment id"),
+        AccessTokenQP,
+    ),
+    request_body = AppointmentBody,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Appointment moved to the new slot, workshop or service", body = Appointment),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+        (status = 404, description = "Plan or appointment not found", body = String),
+        (status = 409, description = "Plan not active, appointment cancelled or past, or the slot is full", body = String),
+        (status = 422, description = "Invalid request body, or the workshop can't take it", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn reschedule_appointment(
+    Path((plan_id, appointment_id)): Path<(String, String)>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(body): ValidatedJson<AppointmentBody>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+
+    if let Err(res) = get_active_plan_vehicle(&mut tx, &plan_id).await {
+        return res;
+    }
+    if let Err(res) = check_changeable(&mut tx, &plan_id, &appointment_id).await {
+        return res;
+    }
+
+    let starts_at = match check_slot(&mut tx, &body, Some(&appointment_id)).await {
+        Ok(starts_at) => starts_at,
+        Err(res) => return res,
+    };
+
+    db_breadcrumb("update Appointment slot");
+    sqlx::query!(
+        r#"update Appointment set workshop_id = ?, service = ?,
+        starts_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), updated_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        where id = ?"#,
+        body.workshop_id,
+        body.service.as_str(),
+        starts_at.format(DATETIME_FORMAT).to_string(),
+        Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        appointment_id
+    )
+    .execute(&mut tx)
+    .await
+    .unwrap();
+
+    let appointment = get_appointment(&mut tx, &plan_id, &appointment_id)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    (StatusCode::OK, Json(appointment)).into_response()
+}
+
+// Only booked appointments that haven't started can be moved or cancelled.
+async fn check_changeable(
+    conn: &mut MySqlConnection,
+    plan_id: &str,
+    appointment_id: &str,
+) -> Result<Appointment, Response> {
+    let appointment = get_appointment(conn, plan_id, appointment_id)
+        .await
+        .ok_or_else(|| not_found("Appointment not found"))?;
+
+    let starts_at = NaiveDateTime::parse_from_str(&appointment.starts_at, DATETIME_FORMAT).unwrap();
+    if appointment.status != BOOKED || starts_at <= local_now() {
+        return Err(conflict("Only upcoming booked appointments can be changed"));
+    }
+
+    Ok(appointment)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/plan/{plan_id}/appointments/{appointment_id}",
+    tag = "appointments",
+    params(
+        ("plan_id" = String, Path, description = "Plan id"),
+        ("appointment_id" = String, Path, description = "Appointment id"),
+        AccessTokenQP,
+    ),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Appointment cancelled, freeing its slot", body = Appointment),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+        (status = 404, description = "Appointment not found", body = String),
+        (status = 409, description = "Appointment already cancelled or past", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn cancel_appointment(
+    Path((plan_id, appointment_id)): Path<(String, String)>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let appointment = match check_changeable(&mut conn, &plan_id, &appointment_id).await {
+        Ok(appointment) => appointment,
+        Err(res) => return res,
+    };
+
+    db_breadcrumb("update Appointment cancelled");
+    sqlx::query!(
+        r#"update Appointment set status = ?, updated_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        where id = ?"#,
+        CANCELLED,
+        Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        appointment_id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    (
+        StatusCode::OK,
+        Json(Appointment {
+            status: CANCELLED.to_string(),
+            ..appointment
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/plan/{plan_id}/appointments/{appointment_id}/complete",
+    tag = "appointments",
+    params(
+        ("plan_id" = String, Path, description = "Plan id"),
+        ("appointment_id" = String, Path, description = "Appointment id"),
+    ),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Appointment completed, and the service marked done in the plan's schedule", body = Appointment),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to complete appointments", body = String),
+        (status = 404, description = "Plan or appointment not found", body = String),
+        (status = 409, description = "Plan not active, or appointment not booked", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn complete_appointment(
+    Path((plan_id, appointment_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+
+    let license_plate = match get_active_plan_vehicle(&mut tx, &plan_id).await {
+        Ok(license_plate) => license_plate,
+        Err(res) => return res,
+    };
+    let appointment = match get_appointment(&mut tx, &plan_id, &appointment_id).await {
+        Some(appointment) if appointment.status == BOOKED => appointment,
+        Some(_) => return conflict("Only booked appointments can be completed"),
+        None => return not_found("Appointment not found"),
+    };
+
+    db_breadcrumb("update Appointment completed");
+    sqlx::query!(
+        r#"update Appointment set status = ?, updated_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        where id = ?"#,
+        COMPLETED,
+        Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        appointment_id
+    )
+    .execute(&mut tx)
+    .await
+    .unwrap();
+
+    ensure_schedule(&mut tx, &plan_id, &license_plate).await;
+    mark_done(&mut tx, &plan_id, &appointment.service, &appointment_id).await;
+    tx.commit().await.unwrap();
+
+    (
+        StatusCode::OK,
+        Json(Appointment {
+            status: COMPLETED.to_string(),
+            ..appointment
+        }),
+    )
+        .into_response()
+}
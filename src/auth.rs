@@ -0,0 +1,276 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header::AUTHORIZATION, request::Parts, Request, StatusCode};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::OnceLock;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Sales,
+    Support,
+    Admin,
+}
+
+impl Role {
+    fn parse(value: &str) -> Option<Role> {
+        match value.trim() {
+            "sales" => Some(Role::Sales),
+            "support" => Some(Role::Support),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrincipalKind {
+    ApiKey,
+    Staff,
+}
+
+// The caller of an authenticated request. Handlers behind `require_role` can
+// take it as an extractor to record who performed an action.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Principal {
+    pub subject: String,
+    pub kind: PrincipalKind,
+    pub roles: Vec<Role>,
+}
+
+impl Principal {
+    // Admins are allowed through every role guard.
+    pub fn has_any_role(&self, roles: &[Role]) -> bool {
+        self.roles
+            .iter()
+            .any(|role| *role == Role::Admin || roles.contains(role))
+    }
+
+    // Identifier used when recording who performed a change.
+    pub fn audit_name(&self) -> String {
+        match self.kind {
+            PrincipalKind::ApiKey => format!("api-key:{}", self.subject),
+            PrincipalKind::Staff => format!("staff:{}", self.subject),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StaffClaims {
+    sub: String,
+    #[serde(default)]
+    roles: Vec<Role>,
+}
+
+struct ApiKey {
+    name: String,
+    key: String,
+    roles: Vec<Role>,
+}
+
+enum JwtKeys {
+    Secret(DecodingKey),
+    Jwks(JwkSet),
+    None,
+}
+
+struct AuthConfig {
+    api_keys: Vec<ApiKey>,
+    jwt_keys: JwtKeys,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+static CONFIG: OnceLock<AuthConfig> = OnceLock::new();
+
+// API_KEYS holds comma separated `name:key:role|role` entries. Staff tokens
+// are verified with JWT_JWKS_PATH (a JWKS file) or JWT_SECRET (HS256).
+fn load_config() -> AuthConfig {
+    let api_keys = env::var("API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().splitn(3, ':');
+            let name = parts.next()?.to_string();
+            let key = parts.next()?.to_string();
+            let roles = parts
+                .next()
+                .unwrap_or_default()
+                .split('|')
+                .filter_map(Role::parse)
+                .collect();
+
+            if name.is_empty() || key.is_empty() {
+                return None;
+            }
+
+            Some(ApiKey { name, key, roles })
+        })
+        .collect();
+
+    // Empty values are treated as unset so an empty secret never validates tokens.
+    let non_empty = |var: &str| env::var(var).ok().filter(|value| !value.is_empty());
+
+    let jwt_keys = if let Some(path) = non_empty("JWT_JWKS_PATH") {
+        let jwks = std::fs::read_to_string(&path).unwrap();
+        JwtKeys::Jwks(serde_json::from_str(&jwks).unwrap())
+    } else if let Some(secret) = non_empty("JWT_SECRET") {
+        JwtKeys::Secret(DecodingKey::from_secret(secret.as_bytes()))
+    } else {
+        JwtKeys::None
+    };
+
+    AuthConfig {
+        api_keys,
+        jwt_keys,
+        issuer: non_empty("JWT_ISSUER"),
+        audience: non_empty("JWT_AUDIENCE"),
+    }
+}
+
+fn config() -> &'static AuthConfig {
+    CONFIG.get_or_init(load_config)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn authenticate_api_key(key: &str) -> Option<Principal> {
+    config()
+        .api_keys
+        .iter()
+        .find(|api_key| constant_time_eq(api_key.key.as_bytes(), key.as_bytes()))
+        .map(|api_key| Principal {
+            subject: api_key.name.clone(),
+            kind: PrincipalKind::ApiKey,
+            roles: api_key.roles.clone(),
+        })
+}
+
+fn authenticate_jwt(token: &str) -> Option<Principal> {
+    let config = config();
+    let header = decode_header(token).ok()?;
+
+    let (key, algorithm) = match &config.jwt_keys {
+        JwtKeys::Secret(key) => (key.clone(), Algorithm::HS256),
+        JwtKeys::Jwks(jwks) => {
+            let jwk = jwks.find(header.kid.as_ref()?)?;
+            let algorithm = jwk.common.algorithm.unwrap_or(header.alg);
+            (DecodingKey::from_jwk(jwk).ok()?, algorithm)
+        }
+        JwtKeys::None => return None,
+    };
+
+    let mut validation = Validation::new(algorithm);
+    if let Some(issuer) = &config.issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    if let Some(audience) = &config.audience {
+        validation.set_audience(&[audience]);
+    }
+
+    let claims = decode::<StaffClaims>(token, &key, &validation).ok()?.claims;
+
+    Some(Principal {
+        subject: claims.sub,
+        kind: PrincipalKind::Staff,
+        roles: claims.roles,
+    })
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(String::from("Missing or invalid credentials")),
+    )
+        .into_response()
+}
+
+fn authenticate(parts: &Parts) -> Option<Principal> {
+    if let Some(key) = parts.headers.get("x-api-key") {
+        return authenticate_api_key(key.to_str().ok()?);
+    }
+
+    let token = parts
+        .headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?;
+
+    authenticate_jwt(token.trim())
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Principal
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(principal) = parts.extensions.get::<Principal>() {
+            return Ok(principal.clone());
+        }
+
+        authenticate(parts).ok_or_else(unauthorized)
+    }
+}
+
+// Guards a route so only callers holding one of the given roles get through.
+// Use with `middleware::from_fn_with_state(&[Role::Support], require_role)`.
+pub async fn require_role<B>(
+    State(roles): State<&'static [Role]>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let (mut parts, body) = req.into_parts();
+
+    let principal = match authenticate(&parts) {
+        Some(principal) => principal,
+        None => return unauthorized(),
+    };
+
+    if !principal.has_any_role(roles) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(String::from("Not allowed to access this resource")),
+        )
+            .into_response();
+    }
+
+    sentry::configure_scope(|scope| {
+        scope.set_user(Some(sentry::User {
+            id: Some(principal.audit_name()),
+            ..Default::default()
+        }))
+    });
+
+    parts.extensions.insert(principal);
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    tag = "auth",
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Authenticated caller", body = Principal),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Caller has no staff role", body = String),
+    )
+)]
+pub async fn get_current_principal(principal: Principal) -> impl IntoResponse {
+    (StatusCode::OK, Json(principal)).into_response()
+}
@@ -0,0 +1,347 @@
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use http::StatusCode;
+
+use crate::api_structs::CatalogueQP;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+struct Entry {
+    id: i64,
+    name: String,
+    normalized: String,
+}
+
+// Upper-case alphanumeric form used to match names regardless of casing,
+// accents, spaces or punctuation ("Mercedes-Benz" == "MERCEDES BENZ").
+pub fn normalize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'á' | 'Á' => 'A',
+            'é' | 'É' => 'E',
+            'í' | 'Í' => 'I',
+            'ó' | 'Ó' => 'O',
+            'ú' | 'Ú' | 'ü' | 'Ü' => 'U',
+            'ñ' | 'Ñ' => 'N',
+            c => c.to_ascii_uppercase(),
+        })
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// Prefix matches first, then names containing the query, then names within a
+// small edit distance of it (typos like "TOYTA").
+fn rank(entries: Vec<Entry>, query: &str, limit: usize) -> Vec<String> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return entries.into_iter().take(limit).map(|e| e.name).collect();
+    }
+
+    let max_distance = (query.len() / 4).max(1);
+    let mut scored: Vec<(usize, usize, Entry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            if entry.normalized.starts_with(&query) {
+                return Some((0, 0, entry));
+            }
+            if entry.normalized.contains(&query) {
+                return Some((1, 0, entry));
+            }
+            // Compare against the start of the name so partial input still matches.
+            let head: String = entry.normalized.chars().take(query.len()).collect();
+            let distance = levenshtein(&query, &head).min(levenshtein(&query, &entry.normalized));
+            (distance <= max_distance).then_some((2, distance, entry))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| (a.0, a.1, &a.2.name).cmp(&(b.0, b.1, &b.2.name)));
+    scored.into_iter().take(limit).map(|s| s.2.name).collect()
+}
+
+async fn get_makes() -> Vec<Entry> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select CatalogueMake");
+
+    sqlx::query_as!(
+        Entry,
+        "select id, name, normalized from CatalogueMake order by name"
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
+}
+
+async fn get_make(make: &str) -> Option<Entry> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select CatalogueMake by normalized");
+
+    sqlx::query_as!(
+        Entry,
+        "select id, name, normalized from CatalogueMake where normalized = ?",
+        normalize(make)
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+}
+
+async fn get_models(make_id: i64) -> Vec<Entry> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select CatalogueModel by make");
+
+    sqlx::query_as!(
+        Entry,
+        "select id, name, normalized from CatalogueModel where make_id = ? order by name",
+        make_id
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
+}
+
+// Adds a make and model to the catalogue unless an equivalent spelling is
+// already there.
+pub async fn add_to_catalogue(make: &str, model: &str) {
+    let (make, model) = (make.trim(), model.trim());
+    if normalize(make).is_empty() {
+        return;
+    }
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert CatalogueMake");
+
+    sqlx::query!(
+        "insert ignore into CatalogueMake(name, normalized) values (?,?)",
+        make,
+        normalize(make)
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    if normalize(model).is_empty() {
+        return;
+    }
+
+    let make_id = get_make(make).await.unwrap().id;
+    db_breadcrumb("insert CatalogueModel");
+
+    sqlx::query!(
+        "insert ignore into CatalogueModel(make_id, name, normalized) values (?,?,?)",
+        make_id,
+        model,
+        normalize(model)
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+// Replaces the make and model with their catalogue spelling when the
+// catalogue knows them; unknown names are kept as entered.
+pub async fn canonicalize(make: &str, model: &str) -> (String, String) {
+    let make_entry = match get_make(make).await {
+        Some(entry) => entry,
+        None => return (make.trim().to_string(), model.trim().to_string()),
+    };
+
+    let model_name = get_models(make_entry.id)
+        .await
+        .into_iter()
+        .find(|entry| entry.normalized == normalize(model))
+        .map(|entry| entry.name)
+        .unwrap_or_else(|| model.trim().to_string());
+
+    (make_entry.name, model_name)
+}
+
+// Seeds the catalogue from the makes and models already stored on vehicles.
+pub async fn seed_from_vehicles() -> usize {
+    struct Row {
+        make: Option<String>,
+        model: Option<String>,
+    }
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select distinct Vehicle make and model");
+
+    let rows = sqlx::query_as!(Row, "select distinct make, model from Vehicle")
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+
+    let mut seeded = 0;
+    for row in rows {
+        if let Some(make) = row.make {
+            add_to_catalogue(&make, row.model.as_deref().unwrap_or_default()).await;
+            seeded += 1;
+        }
+    }
+
+    seeded
+}
+
+#[utoipa::path(
+    get,
+    path = "/catalogue/makes",
+    tag = "catalogue",
+    params(CatalogueQP),
+    responses((status = 200, description = "Makes matching the query, best first", body = [String]))
+)]
+#[axum_macros::debug_handler]
+pub async fn get_catalogue_makes(Query(query): Query<CatalogueQP>) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let makes = rank(
+        get_makes().await,
+        query.q.as_deref().unwrap_or_default(),
+        limit,
+    );
+
+    (StatusCode::OK, Json(makes)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/catalogue/makes/{make}/models",
+    tag = "catalogue",
+    params(("make" = String, Path, description = "Make, in any spelling the catalogue knows"), CatalogueQP),
+    responses(
+        (status = 200, description = "Models of the make matching the query, best first", body = [String]),
+        (status = 404, description = "Unknown make", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_catalogue_models(
+    Path(make): Path<String>,
+    Query(query): Query<CatalogueQP>,
+) -> impl IntoResponse {
+    let make = match get_make(&make).await {
+        Some(make) => make,
+        None => return (StatusCode::NOT_FOUND, Json(String::from("Unknown make"))).into_response(),
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let models = rank(
+        get_models(make.id).await,
+        query.q.as_deref().unwrap_or_default(),
+        limit,
+    );
+
+    (StatusCode::OK, Json(models)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{levenshtein, normalize, rank, Entry};
+
+    fn entries(names: &[&str]) -> Vec<Entry> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| Entry {
+                id: id as i64,
+                name: name.to_string(),
+                normalized: normalize(name),
+            })
+            .collect()
+    }
+
+    const MAKES: &[&str] = &[
+        "Chevrolet",
+        "Isuzu",
+        "Kia",
+        "Mazda",
+        "Mercedes-Benz",
+        "Mitsubishi",
+        "Suzuki",
+        "Tata",
+        "Toyota",
+    ];
+
+    #[test]
+    fn normalizes_names() {
+        let cases = [
+            ("Mercedes-Benz", "MERCEDESBENZ"),
+            ("mercedes benz", "MERCEDESBENZ"),
+            ("Alfa Romeo", "ALFAROMEO"),
+            ("CAMIONETA ÑANDÚ", "CAMIONETANANDU"),
+            ("Güeña Ísola Óvalo Éxito Ámbar", "GUENAISOLAOVALOEXITOAMBAR"),
+            ("cx-5 (2.0)", "CX520"),
+            ("", ""),
+        ];
+
+        for (name, normalized) in cases {
+            assert_eq!(normalize(name), normalized, "{}", name);
+        }
+    }
+
+    #[test]
+    fn counts_edits() {
+        let cases = [
+            ("TOYOTA", "TOYOTA", 0),
+            ("TOYOTA", "TOYTA", 1),
+            ("MAZDA", "MASDA", 1),
+            ("KITTEN", "SITTING", 3),
+            ("SUZUKI", "ISUZU", 3),
+            ("", "KIA", 3),
+            ("KIA", "", 3),
+        ];
+
+        for (a, b, distance) in cases {
+            assert_eq!(levenshtein(a, b), distance, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn ranks_matches() {
+        let cases: [(&str, &[&str]); 9] = [
+            ("toy", &["Toyota"]),
+            ("TOYTA", &["Toyota"]),
+            ("masda", &["Mazda"]),
+            ("MÁZDA", &["Mazda"]),
+            ("chevrolt", &["Chevrolet"]),
+            ("mercedes benz", &["Mercedes-Benz"]),
+            // Prefix matches, then names containing the query, then typos.
+            ("suzu", &["Suzuki", "Isuzu"]),
+            ("ta", &["Tata", "Toyota", "Mazda"]),
+            ("xyz", &[]),
+        ];
+
+        for (query, names) in cases {
+            assert_eq!(rank(entries(MAKES), query, 10), names, "{}", query);
+        }
+    }
+
+    #[test]
+    fn limits_results() {
+        assert_eq!(rank(entries(MAKES), "ta", 1), vec!["Tata"]);
+        assert_eq!(rank(entries(MAKES), "ta", 2), vec!["Tata", "Toyota"]);
+        // Without a query the entries come back in their stored order.
+        assert_eq!(
+            rank(entries(MAKES), " - ", 3),
+            vec!["Chevrolet", "Isuzu", "Kia"]
+        );
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Months, NaiveDate, Utc};
+use http::StatusCode;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use sqlx::types::BigDecimal;
+use sqlx::MySqlConnection;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::access_token::{authorize_client_access, Resource};
+use crate::api_structs::AccessTokenQP;
+use crate::appointments::COMPLETED;
+use crate::auth::Principal;
+use crate::plan_handlers::PLAN_CYCLES;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::validation::{validate_uuid, ValidatedJson, ValidationRejection};
+use crate::workshops::{get_workshop, Service};
+
+const APPROVED: &str = "approved";
+const REJECTED: &str = "rejected";
+const MAX_LINES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionCode {
+    PlanInactive,
+    PlanExpired,
+    ServiceNotOffered,
+    AppointmentMismatch,
+    DuplicateClaim,
+    ExceedsCoverage,
+}
+
+impl RejectionCode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RejectionCode::PlanInactive => "plan_inactive",
+            RejectionCode::PlanExpired => "plan_expired",
+            RejectionCode::ServiceNotOffered => "service_not_offered",
+            RejectionCode::AppointmentMismatch => "appointment_mismatch",
+            RejectionCode::DuplicateClaim => "duplicate_claim",
+            RejectionCode::ExceedsCoverage => "exceeds_coverage",
+        }
+    }
+
+    fn parse(code: &str) -> Option<RejectionCode> {
+        match code {
+            "plan_inactive" => Some(RejectionCode::PlanInactive),
+            "plan_expired" => Some(RejectionCode::PlanExpired),
+            "service_not_offered" => Some(RejectionCode::ServiceNotOffered),
+            "appointment_mismatch" => Some(RejectionCode::AppointmentMismatch),
+            "duplicate_claim" => Some(RejectionCode::DuplicateClaim),
+            "exceeds_coverage" => Some(RejectionCode::ExceedsCoverage),
+            _ => None,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            RejectionCode::PlanInactive => "The plan isn't active",
+            RejectionCode::PlanExpired => "The plan's term is over",
+            RejectionCode::ServiceNotOffered => {
+                "The workshop doesn't offer one of the claimed services"
+            }
+            RejectionCode::AppointmentMismatch => {
+                "The appointment isn't a completed one of this plan at this workshop"
+            }
+            RejectionCode::DuplicateClaim => "The appointment was already claimed",
+            RejectionCode::ExceedsCoverage => "The claim is over the plan's remaining coverage",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClaimReason {
+    pub code: RejectionCode,
+    pub message: String,
+}
+
+impl From<RejectionCode> for ClaimReason {
+    fn from(code: RejectionCode) -> Self {
+        ClaimReason {
+            code,
+            message: code.message().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct ClaimLine {
+    pub service: Service,
+    pub description: String,
+    pub labour_hours: f32,
+    // Labour charged for the line, in whole CLP.
+    pub amount: i64,
+}
+
+fn validate_claim_lines(lines: &[ClaimLine]) -> Result<(), ValidationError> {
+    let invalid = |message: String| {
+        let mut error = ValidationError::new("lines");
+        error.message = Some(message.into());
+        Err(error)
+    };
+
+    if lines.is_empty() || lines.len() > MAX_LINES {
+        return invalid(format!("must list between 1 and {} lines", MAX_LINES));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        if line.description.trim().is_empty() || line.description.chars().count() > 255 {
+            return invalid(format!(
+                "line {}: description must be between 1 and 255 characters",
+                i + 1
+            ));
+        }
+        if !(line.labour_hours > 0.0 && line.labour_hours <= 100.0) {
+            return invalid(format!(
+                "line {}: labour_hours must be more than 0 and at most 100",
+                i + 1
+            ));
+        }
+        if !(line.amount > 0 && line.amount < 1_000_000_000) {
+            return invalid(format!("line {}: amount must be a positive amount", i + 1));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ClaimBody {
+    #[validate(custom = "validate_uuid")]
+    pub workshop_id: String,
+    // The completed appointment the work was done in, when there was one.
+    #[validate(custom = "validate_uuid")]
+    pub appointment_id: Option<String>,
+    #[validate(custom = "validate_claim_lines")]
+    pub lines: Vec<ClaimLine>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Claim {
+    pub id: String,
+    pub plan_id: String,
+    pub workshop_id: String,
+    pub appointment_id: Option<String>,
+    // "approved" or "rejected"
+    pub status: String,
+    pub lines: Vec<ClaimLine>,
+    pub labour_hours: f32,
+    pub amount: i64,
+    // Why the claim was rejected, empty when approved.
+    pub reasons: Vec<ClaimReason>,
+    pub submitted_by: String,
+    pub created_at: String,
+}
+
+// Labour coverage of a plan, in whole CLP. Amounts are kept in integer pesos
+// so the ledger adds up exactly.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Coverage {
+    // The quote's labour coverage, without its cents.
+    pub total: i64,
+    // Consumed by approved claims.
+    pub used: i64,
+    pub remaining: i64,
+}
+
+fn pesos(amount: &BigDecimal) -> i64 {
+    amount.with_scale(0).to_i64().unwrap_or_default()
+}
+
+// What a claim is checked against.
+struct ClaimFacts {
+    plan_active: bool,
+    plan_ends_on: NaiveDate,
+    today: NaiveDate,
+    services_offered: Vec<Service>,
+    // None when the claim isn't for an appointment.
+    appointment_matches: Option<bool>,
+    already_claimed: bool,
+    remaining: i64,
+}
+
+// Every rule the claim breaks; none means it's approved.
+fn review_claim(facts: &ClaimFacts, lines: &[ClaimLine]) -> Vec<RejectionCode> {
+    let mut reasons = vec![];
+
+    if !facts.plan_active {
+        reasons.push(RejectionCode::PlanInactive);
+    }
+    if facts.today > facts.plan_ends_on {
+        reasons.push(RejectionCode::PlanExpired);
+    }
+    if lines
+        .iter()
+        .any(|line| !facts.services_offered.contains(&line.service))
+    {
+        reasons.push(RejectionCode::ServiceNotOffered);
+    }
+    if facts.appointment_matches == Some(false) {
+        reasons.push(RejectionCode::AppointmentMismatch);
+    }
+    if facts.already_claimed {
+        reasons.push(RejectionCode::DuplicateClaim);
+    }
+    if lines.iter().map(|line| line.amount).sum::<i64>() > facts.remaining {
+        reasons.push(RejectionCode::ExceedsCoverage);
+    }
+
+    reasons
+}
+
+pub async fn get_coverage(conn: &mut MySqlConnection, plan_id: &str) -> Option<Coverage> {
+    db_breadcrumb("select Plan labour coverage");
+    let plan = sqlx::query!(
+        "select Q.labour_coverage from Plan P join Quote Q on Q.id = P.quote_id where P.id = ?",
+        plan_id
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .unwrap()?;
+
+    db_breadcrumb("select CoverageLedger used");
+    let ledger = sqlx::query!(
+        "select SUM(amount) as used from CoverageLedger where plan_id = ?",
+        plan_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .unwrap();
+
+    let total = plan.labour_coverage.as_ref().map(pesos).unwrap_or_default();
+    let used = ledger.used.as_ref().map(pesos).unwrap_or_default();
+
+    Some(Coverage {
+        total,
+        used,
+        remaining: (total - used).max(0),
+    })
+}
+
+async fn get_claims(conn: &mut MySqlConnection, plan_id: &str) -> Vec<Claim> {
+    db_breadcrumb("select CoverageClaimLines by plan");
+    let mut lines: HashMap<String, Vec<ClaimLine>> = HashMap::new();
+    let rows = sqlx::query!(
+        r#"select L.claim_id, L.service, L.description, L.labour_hours, L.amount
+        from CoverageClaimLine L join CoverageClaim C on C.id = L.claim_id
+        where C.plan_id = ? order by L.id"#,
+        plan_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .unwrap();
+    for row in rows {
+        if let Some(service) = Service::parse(&row.service) {
+            lines.entry(row.claim_id).or_default().push(ClaimLine {
+                service,
+                description: row.description,
+                labour_hours: row.labour_hours.to_f32().unwrap_or_default(),
+                amount: pesos(&row.amount),
+            });
+        }
+    }
+
+    db_breadcrumb("select CoverageClaims by plan");
+    sqlx::query!(
+        r#"select id, plan_id, workshop_id, appointment_id, status, labour_hours, amount,
+        rejection_reasons, submitted_by, DATE_FORMAT(creation_timestamp, '%Y-%m-%dT%TZ') as "created_at!"
+        from CoverageClaim where plan_id = ? order by creation_timestamp desc"#,
+        plan_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|claim| Claim {
+        lines: lines.remove(&claim.id).unwrap_or_default(),
+        id: claim.id,
+        plan_id: claim.plan_id,
+        workshop_id: claim.workshop_id,
+        appointment_id: claim.appointment_id,
+        status: claim.status,
+        labour_hours: claim.labour_hours.to_f32().unwrap_or_default(),
+        amount: pesos(&claim.amount),
+        reasons: claim
+            .rejection_reasons
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(RejectionCode::parse)
+            .map(ClaimReason::from)
+            .collect(),
+        submitted_by: claim.submitted_by,
+        created_at: claim.created_at,
+    })
+    .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/plan/{plan_id}/claims",
+    tag = "coverage",
+    params(("plan_id" = String, Path, description = "Plan id")),
+    request_body = ClaimBody,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 201, description = "Claim recorded. Approved claims are taken from the plan's coverage, rejected ones list the reasons", body = Claim),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to submit claims", body = String),
+        (status = 404, description = "Plan not found", body = String),
+        (status = 422, description = "Invalid request body, or the workshop isn't active", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn submit_claim(
+    Path(plan_id): Path<String>,
+    principal: Principal,
+    ValidatedJson(body): ValidatedJson<ClaimBody>,
+) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+
+    // Locks the plan so concurrent claims can't spend the same coverage.
+    db_breadcrumb("select Plan for claim");
+    let plan = sqlx::query!(
+        r#"select active as "active: bool", DATE_FORMAT(creation_timestamp, '%Y-%m-%d') as "starts_on!"
+        from Plan where id = ? for update"#,
+        plan_id
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .unwrap();
+    let plan = match plan {
+        Some(plan) => plan,
+        None => {
+            return (StatusCode::NOT_FOUND, Json(String::from("Plan not found"))).into_response()
+        }
+    };
+
+    let workshop = match get_workshop(&mut tx, &body.workshop_id, false).await {
+        Some(workshop) if workshop.active => workshop,
+        _ => {
+            return ValidationRejection::field(
+                "workshop_id",
+                "workshop",
+                "must be an active workshop",
+            )
+            .into_response()
+        }
+    };
+
+    let (appointment_matches, already_claimed) = match &body.appointment_id {
+        Some(appointment_id) => {
+            db_breadcrumb("select Appointment for claim");
+            let appointment = sqlx::query!(
+                "select plan_id, workshop_id, status from Appointment where id = ?",
+                appointment_id
+            )
+            .fetch_optional(&mut tx)
+            .await
+            .unwrap();
+
+            db_breadcrumb("select approved CoverageClaim by appointment");
+            let claimed = sqlx::query!(
+                "select id from CoverageClaim where appointment_id = ? and status = ?",
+                appointment_id,
+                APPROVED
+            )
+            .fetch_optional(&mut tx)
+            .await
+            .unwrap();
+
+            let matches = appointment.is_some_and(|appointment| {
+                appointment.plan_id == plan_id
+                    && appointment.workshop_id == body.workshop_id
+                    && appointment.status == COMPLETED
+            });
+            (Some(matches), claimed.is_some())
+        }
+        None => (None, false),
+    };
+
+    let coverage = get_coverage(&mut tx, &plan_id).await.unwrap();
+    let starts_on = NaiveDate::parse_from_str(&plan.starts_on, "%Y-%m-%d").unwrap();
+    let facts = ClaimFacts {
+        plan_active: plan.active,
+        plan_ends_on: starts_on + Months::new(PLAN_CYCLES),
+        today: Utc::now().date_naive(),
+        services_offered: workshop.services,
+        appointment_matches,
+        already_claimed,
+        remaining: coverage.remaining,
+    };
+    let reasons = review_claim(&facts, &body.lines);
+    let status = match reasons.is_empty() {
+        true => APPROVED,
+        false => REJECTED,
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let labour_hours: f32 = body.lines.iter().map(|line| line.labour_hours).sum();
+    let amount: i64 = body.lines.iter().map(|line| line.amount).sum();
+    let rejection_reasons = (!reasons.is_empty()).then(|| {
+        reasons
+            .iter()
+            .map(|code| code.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+
+    db_breadcrumb("insert CoverageClaim");
+    sqlx::query!(
+        r#"insert into CoverageClaim(id, plan_id, workshop_id, appointment_id, status, labour_hours, amount,
+        rejection_reasons, submitted_by, creation_timestamp)
+        values (?, ?, ?, ?, ?, ?, ?, ?, ?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        id,
+        plan_id,
+        body.workshop_id,
+        body.appointment_id,
+        status,
+        labour_hours,
+        amount,
+        rejection_reasons,
+        principal.subject,
+        timestamp
+    )
+    .execute(&mut tx)
+    .await
+    .unwrap();
+
+    for line in &body.lines {
+        db_breadcrumb("insert CoverageClaimLine");
+        sqlx::query!(
+            r#"insert into CoverageClaimLine(claim_id, service, description, labour_hours, amount)
+            values (?, ?, ?, ?, ?)"#,
+            id,
+            line.service.as_str(),
+            line.description,
+            line.labour_hours,
+            line.amount
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+    }
+
+    if reasons.is_empty() {
+        db_breadcrumb("insert CoverageLedger");
+        sqlx::query!(
+            r#"insert into CoverageLedger(plan_id, claim_id, amount, balance, creation_timestamp)
+            values (?, ?, ?, ?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+            plan_id,
+            id,
+            amount,
+            coverage.remaining - amount,
+            timestamp
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+    }
+
+    tx.commit().await.unwrap();
+
+    let claim = Claim {
+        id,
+        plan_id,
+        workshop_id: body.workshop_id,
+        appointment_id: body.appointment_id,
+        status: status.to_string(),
+        lines: body.lines,
+        labour_hours,
+        amount,
+        reasons: reasons.into_iter().map(ClaimReason::from).collect(),
+        submitted_by: principal.subject,
+        created_at: format!("{}Z", timestamp),
+    };
+
+    (StatusCode::CREATED, Json(claim)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/plan/{plan_id}/claims",
+    tag = "coverage",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Claims against the plan's coverage, latest first", body = [Claim]),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_plan_claims(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let claims = get_claims(&mut conn, &plan_id).await;
+
+    (StatusCode::OK, Json(claims)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{review_claim, validate_claim_lines, ClaimFacts, ClaimLine, RejectionCode};
+    use crate::workshops::Service;
+    use chrono::NaiveDate;
+
+    fn line(service: Service, amount: i64) -> ClaimLine {
+        ClaimLine {
+            service,
+            description: String::from("Cambio de pastillas"),
+            labour_hours: 1.5,
+            amount,
+        }
+    }
+
+    fn facts() -> ClaimFacts {
+        ClaimFacts {
+            plan_active: true,
+            plan_ends_on: NaiveDate::from_ymd_opt(2027, 10, 19).unwrap(),
+            today: NaiveDate::from_ymd_opt(2026, 12, 1).unwrap(),
+            services_offered: vec![Service::Maintenance, Service::Brakes],
+            appointment_matches: None,
+            already_claimed: false,
+            remaining: 100_000,
+        }
+    }
+
+    #[test]
+    fn approves_claims_within_coverage() {
+        let lines = [
+            line(Service::Brakes, 40_000),
+            line(Service::Maintenance, 60_000),
+        ];
+        assert!(review_claim(&facts(), &lines).is_empty());
+
+        let lines = [
+            line(Service::Brakes, 40_000),
+            line(Service::Maintenance, 60_001),
+        ];
+        assert_eq!(
+            review_claim(&facts(), &lines),
+            [RejectionCode::ExceedsCoverage]
+        );
+
+        // Large balances still compare to the peso.
+        let facts = ClaimFacts {
+            remaining: 123_456_789,
+            ..facts()
+        };
+        assert!(review_claim(&facts, &[line(Service::Brakes, 123_456_789)]).is_empty());
+        assert_eq!(
+            review_claim(&facts, &[line(Service::Brakes, 123_456_790)]),
+            [RejectionCode::ExceedsCoverage]
+        );
+    }
+
+    #[test]
+    fn lists_every_broken_rule() {
+        let facts = ClaimFacts {
+            plan_active: false,
+            today: NaiveDate::from_ymd_opt(2027, 10, 20).unwrap(),
+            appointment_matches: Some(false),
+            already_claimed: true,
+            ..facts()
+        };
+        assert_eq!(
+            review_claim(&facts, &[line(Service::Tires, 10_000)]),
+            [
+                RejectionCode::PlanInactive,
+                RejectionCode::PlanExpired,
+                RejectionCode::ServiceNotOffered,
+                RejectionCode::AppointmentMismatch,
+                RejectionCode::DuplicateClaim,
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_lines() {
+        assert!(validate_claim_lines(&[line(Service::Brakes, 1)]).is_ok());
+        assert!(validate_claim_lines(&[]).is_err());
+        assert!(validate_claim_lines(&[line(Service::Brakes, 0)]).is_err());
+
+        let error = validate_claim_lines(&[
+            line(Service::Brakes, 1),
+            ClaimLine {
+                labour_hours: 0.0,
+                ..line(Service::Brakes, 1)
+            },
+        ])
+        .unwrap_err();
+        assert!(error.message.unwrap().starts_with("line 2:"));
+    }
+}
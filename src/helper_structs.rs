@@ -1,8 +1,10 @@
 use axum::response::IntoResponse;
 use serde::{Deserialize, Serialize};
 use sqlx::types::BigDecimal;
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct VehicleDescription {
     #[serde(rename = "Description")]
     pub description: String,
@@ -32,7 +34,7 @@ pub struct VehicleDescription {
     pub fuel: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TextValue {
     #[serde(rename = "CurrentTextValue")]
     pub current_text_value: String,
@@ -59,6 +61,17 @@ impl PaymentMethod {
     pub fn from_u8(val: u8) -> Result<PaymentMethod, &'static str> {
         PaymentMethod::try_from(val)
     }
+
+    pub const NAMES: &'static [&'static str] = &["cash", "wiring", "credit_card", "debit_card"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.value() as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<PaymentMethod> {
+        let position = Self::NAMES.iter().position(|n| *n == name)?;
+        PaymentMethod::try_from(position as u8).ok()
+    }
 }
 
 impl TryFrom<u8> for PaymentMethod {
@@ -75,6 +88,19 @@ impl TryFrom<u8> for PaymentMethod {
     }
 }
 
+impl<'s> ToSchema<'s> for PaymentMethod {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "PaymentMethod",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Integer)
+                .enum_values(Some([0, 1, 2, 3]))
+                .description(Some("0 = Cash, 1 = Wiring, 2 = CreditCard, 3 = DebitCard"))
+                .into(),
+        )
+    }
+}
+
 impl<'se> serde::Serialize for PaymentMethod {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -110,6 +136,10 @@ impl<'de> serde::Deserialize<'de> for PaymentMethod {
                     _ => return Err(E::invalid_value(serde::de::Unexpected::Unsigned(n), &self)),
                 })
             }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<PaymentMethod, E> {
+                PaymentMethod::from_name(v).ok_or_else(|| E::unknown_variant(v, PaymentMethod::NAMES))
+            }
         }
 
         deserializer.deserialize_any(PaymentVisitor)
@@ -132,6 +162,17 @@ impl SignMethod {
     pub fn from_u8(val: u8) -> Result<SignMethod, &'static str> {
         SignMethod::try_from(val)
     }
+
+    pub const NAMES: &'static [&'static str] = &["deferred", "digital"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.value() as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<SignMethod> {
+        let position = Self::NAMES.iter().position(|n| *n == name)?;
+        SignMethod::try_from(position as u8).ok()
+    }
 }
 
 impl TryFrom<u8> for SignMethod {
@@ -146,6 +187,19 @@ impl TryFrom<u8> for SignMethod {
     }
 }
 
+impl<'s> ToSchema<'s> for SignMethod {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "SignMethod",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Integer)
+                .enum_values(Some([0, 1]))
+                .description(Some("0 = Deferred, 1 = Digital"))
+                .into(),
+        )
+    }
+}
+
 impl<'se> serde::Serialize for SignMethod {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -176,12 +230,70 @@ impl<'de> serde::Deserialize<'de> for SignMethod {
                     _ => return Err(E::invalid_value(serde::de::Unexpected::Unsigned(n), &self)),
                 })
             }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<SignMethod, E> {
+                SignMethod::from_name(v).ok_or_else(|| E::unknown_variant(v, SignMethod::NAMES))
+            }
         }
 
         deserializer.deserialize_any(SignVisitor)
     }
 }
 
+// Used by v2 of the API, which names payment and sign methods instead of
+// using their numeric values. Both forms are still accepted on input.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct PaymentMethodName(pub PaymentMethod);
+
+impl Serialize for PaymentMethodName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.name())
+    }
+}
+
+impl<'s> ToSchema<'s> for PaymentMethodName {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "PaymentMethodName",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .enum_values(Some(PaymentMethod::NAMES.iter().copied()))
+                .description(Some("Numeric values (see PaymentMethod) are also accepted on input"))
+                .into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct SignMethodName(pub SignMethod);
+
+impl Serialize for SignMethodName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.name())
+    }
+}
+
+impl<'s> ToSchema<'s> for SignMethodName {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "SignMethodName",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .enum_values(Some(SignMethod::NAMES.iter().copied()))
+                .description(Some("Numeric values (see SignMethod) are also accepted on input"))
+                .into(),
+        )
+    }
+}
+
 // SQL
 
 #[derive(Debug, Clone)]
@@ -193,6 +305,7 @@ pub struct QuoteData {
     pub fuel_consumption: Option<BigDecimal>,
     pub creation_timestamp: Option<String>,
     pub client_name: Option<String>,
+    pub expired_at: Option<String>,
 }
 
 #[derive(Debug)]
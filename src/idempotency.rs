@@ -0,0 +1,218 @@
+use axum::{
+    body::{boxed, Body, Full},
+    extract::State,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header::CONTENT_TYPE, HeaderValue, Request, StatusCode};
+use redis::{AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::redis_store::RedisStore;
+
+// Stored responses are replayed for a day; a request still being processed
+// holds its key for at most a minute.
+const RESPONSE_TTL_SECONDS: usize = 24 * 60 * 60;
+const PENDING_TTL_SECONDS: usize = 60;
+
+// Entries keep a digest of the request body, so a key reused for a different
+// request is refused instead of replaying the first one's response.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum StoredRequest {
+    Pending {
+        body_digest: String,
+    },
+    Done {
+        body_digest: String,
+        status: u16,
+        content_type: Option<String>,
+        body: String,
+    },
+}
+
+// Keys are scoped to the endpoint and the caller's credentials, so a key
+// reused by another client (or guessed) never replays someone else's response.
+fn storage_key<B>(redis: &RedisStore, req: &Request<B>, idempotency_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        req.method().as_str(),
+        req.uri().path(),
+        req.uri().query().unwrap_or_default(),
+        header(req, "authorization"),
+        header(req, "x-api-key"),
+        idempotency_key,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    redis.key(format!("idempotency:{}", hex_digest(hasher)))
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn header<'a, B>(req: &'a Request<B>, name: &str) -> &'a str {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+fn replay(status: u16, content_type: Option<String>, body: String) -> Response {
+    let mut res = Response::new(boxed(Full::from(body)));
+    *res.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+    if let Some(content_type) = content_type.and_then(|value| value.parse().ok()) {
+        res.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    res.headers_mut()
+        .insert("idempotent-replayed", HeaderValue::from_static("true"));
+    res
+}
+
+// Requests sent with an `Idempotency-Key` header are handled once: retries
+// with the same key get the stored response instead of e.g. creating a second
+// plan and Reveniu subscription. Only successful responses are stored, so a
+// failed request can be retried with the same key, and reusing a key with a
+// different body is answered with 422. Without Redis requests go through as
+// if no key had been sent.
+pub async fn idempotency(
+    State(redis): State<RedisStore>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let idempotency_key = header(&req, "idempotency-key").to_string();
+    if idempotency_key.is_empty() {
+        return next.run(req).await;
+    }
+
+    let key = storage_key(&redis, &req, &idempotency_key);
+
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            println!("Failed to read request for idempotency key: {}", err);
+            return (StatusCode::BAD_REQUEST, Json(String::new())).into_response();
+        }
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(&body);
+    let body_digest = hex_digest(hasher);
+    let req = Request::from_parts(parts, Body::from(body));
+    let mut con = match redis.connection().await {
+        Ok(con) => con,
+        Err(err) => {
+            println!("Failed to check idempotency key: {}", err);
+            return next.run(req).await;
+        }
+    };
+
+    let claimed: RedisResult<bool> = async {
+        let claimed: bool = con
+            .set_nx(
+                &key,
+                serde_json::to_string(&StoredRequest::Pending {
+                    body_digest: body_digest.clone(),
+                })
+                .unwrap(),
+            )
+            .await?;
+        if claimed {
+            con.expire::<_, ()>(&key, PENDING_TTL_SECONDS).await?;
+        }
+        Ok(claimed)
+    }
+    .await;
+
+    match claimed {
+        Ok(true) => (),
+        Ok(false) => {
+            let stored: Option<String> = con.get(&key).await.ok().flatten();
+            return match stored.and_then(|stored| serde_json::from_str(&stored).ok()) {
+                Some(
+                    StoredRequest::Pending {
+                        body_digest: stored_digest,
+                    }
+                    | StoredRequest::Done {
+                        body_digest: stored_digest,
+                        ..
+                    },
+                ) if stored_digest != body_digest => (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(String::from(
+                        "This Idempotency-Key was already used with a different request body",
+                    )),
+                )
+                    .into_response(),
+                Some(StoredRequest::Done {
+                    status,
+                    content_type,
+                    body,
+                    ..
+                }) => replay(status, content_type, body),
+                _ => (
+                    StatusCode::CONFLICT,
+                    Json(String::from(
+                        "A request with this Idempotency-Key is still being processed",
+                    )),
+                )
+                    .into_response(),
+            };
+        }
+        Err(err) => {
+            println!("Failed to check idempotency key: {}", err);
+            return next.run(req).await;
+        }
+    }
+
+    let res = next.run(req).await;
+
+    if !res.status().is_success() {
+        if let Err(err) = con.del::<_, ()>(&key).await {
+            println!("Failed to release idempotency key: {}", err);
+        }
+        return res;
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            println!("Failed to read response for idempotency key: {}", err);
+            let _: RedisResult<()> = con.del(&key).await;
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(String::new())).into_response();
+        }
+    };
+
+    let stored = StoredRequest::Done {
+        body_digest,
+        status: parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        body: String::from_utf8_lossy(&body).to_string(),
+    };
+    let res: RedisResult<()> = con
+        .set_ex(
+            &key,
+            serde_json::to_string(&stored).unwrap(),
+            RESPONSE_TTL_SECONDS,
+        )
+        .await;
+    if let Err(err) = res {
+        println!("Failed to store response for idempotency key: {}", err);
+    }
+
+    Response::from_parts(parts, boxed(Full::from(body)))
+}
@@ -0,0 +1,591 @@
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use cron::Schedule;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration as StdDuration;
+use uuid::Uuid;
+
+use crate::api_structs::{JobRun, JobsQP};
+use crate::email::EmailTransport;
+use crate::notifications::send_due_notifications;
+use crate::plan_handlers::retry_reveniu_plan;
+use crate::quote_handlers::expire_quotes;
+use crate::redis_store::RedisStore;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::vehicle_handler::{get_vehicles_to_refresh, refresh_vehicle_from_provider};
+use crate::AppState;
+
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(5);
+// Jobs run per poll, so scheduled jobs keep being queued under load.
+const BATCH_SIZE: usize = 10;
+// A running job is handed to another worker if it hasn't finished this long
+// after it started, e.g. because the instance running it was stopped.
+const LOCK_SECONDS: i64 = 10 * 60;
+// Failed jobs are retried after 30s, 1m, 2m, ... up to 6 hours.
+const BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 6 * 60 * 60;
+
+const PENDING: &str = "pending";
+const RUNNING: &str = "running";
+const SUCCEEDED: &str = "succeeded";
+const DEAD: &str = "dead";
+
+// Recorded as the author of vehicle changes made by jobs.
+const JOB_AUDIT_NAME: &str = "job:refresh-vehicle";
+
+// Work done outside of requests. The payload stored for a job is its JSON
+// form, e.g. {"kind": "refresh-vehicle", "license_plate": "GKSB78"}.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Job {
+    ExpireQuotes,
+    // Queues a refresh-vehicle job for each vehicle with outdated data.
+    RefreshVehicles,
+    RefreshVehicle { license_plate: String },
+    CreateReveniuPlan { plan_id: String, quote_id: String },
+    PruneJobs,
+}
+
+impl Job {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::ExpireQuotes => "expire-quotes",
+            Job::RefreshVehicles => "refresh-vehicles",
+            Job::RefreshVehicle { .. } => "refresh-vehicle",
+            Job::CreateReveniuPlan { .. } => "create-reveniu-plan",
+            Job::PruneJobs => "prune-jobs",
+        }
+    }
+
+    // Scheduled jobs aren't retried, their next run does the same work.
+    fn max_attempts(&self) -> i32 {
+        match self {
+            Job::ExpireQuotes | Job::RefreshVehicles | Job::PruneJobs => 1,
+            Job::RefreshVehicle { .. } => 3,
+            // About a day with the backoff.
+            Job::CreateReveniuPlan { .. } => 12,
+        }
+    }
+
+    async fn run(&self, ctx: &JobContext) -> Result<(), String> {
+        match self {
+            Job::ExpireQuotes => {
+                expire_quotes(env_number("QUOTE_VALIDITY_DAYS", 30))
+                    .await
+                    .map_err(|err| err.to_string())?;
+                Ok(())
+            }
+            Job::RefreshVehicles => {
+                let plates = get_vehicles_to_refresh(
+                    env_number("VEHICLE_REFRESH_MIN_DAYS", 30),
+                    env_number("VEHICLE_REFRESH_BATCH", 50),
+                )
+                .await
+                .map_err(|err| err.to_string())?;
+                for license_plate in plates {
+                    enqueue_job(&Job::RefreshVehicle { license_plate })
+                        .await
+                        .map_err(|err| err.to_string())?;
+                }
+                Ok(())
+            }
+            Job::RefreshVehicle { license_plate } => {
+                refresh_vehicle_from_provider(&ctx.redis, license_plate, JOB_AUDIT_NAME)
+                    .await
+                    .map(|_| ())
+            }
+            Job::CreateReveniuPlan { plan_id, quote_id } => {
+                retry_reveniu_plan(plan_id, quote_id).await
+            }
+            Job::PruneJobs => prune_jobs(env_number("JOB_RETENTION_DAYS", 7))
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+// When scheduled jobs are queued, in UTC. Fields are
+// `second minute hour day-of-month month day-of-week`.
+fn schedules() -> Vec<(Job, &'static str)> {
+    vec![
+        // Early morning in Chile.
+        (Job::ExpireQuotes, "0 0 7 * * *"),
+        (Job::RefreshVehicles, "0 30 7 * * *"),
+        (Job::PruneJobs, "0 0 8 * * *"),
+    ]
+}
+
+fn env_number(var: &str, default: i64) -> i64 {
+    env::var(var)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn sql_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+struct JobContext {
+    redis: RedisStore,
+}
+
+// Queues a job to run as soon as a worker is free. Returns its id.
+pub async fn enqueue_job(job: &Job) -> Result<String, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert Job");
+    let id = Uuid::new_v4().to_string();
+    let timestamp = sql_datetime(Utc::now());
+    let payload = serde_json::to_string(job).unwrap();
+
+    sqlx::query!(
+        r#"insert into Job(id, kind, payload, status, max_attempts, creation_timestamp, run_at)
+        values (?, ?, ?, ?, ?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        id,
+        job.kind(),
+        payload,
+        PENDING,
+        job.max_attempts(),
+        timestamp,
+        timestamp
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(id)
+}
+
+// Queues the scheduled jobs whose time has come. Each schedule's next run is
+// moved forward with a conditional update, so only one instance queues it.
+async fn enqueue_scheduled_jobs() -> Result<(), sqlx::Error> {
+    let mut conn = establish_connection().await;
+    let now = Utc::now();
+    let timestamp = sql_datetime(now);
+
+    for (job, expression) in schedules() {
+        let schedule = Schedule::from_str(expression).unwrap();
+        let next_run_at = sql_datetime(schedule.after(&now).next().unwrap());
+
+        db_breadcrumb("upsert JobSchedule");
+
+        // New schedules, and changed ones, start counting from now.
+        sqlx::query!(
+            r#"insert into JobSchedule(name, schedule, next_run_at)
+            values (?, ?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+            on duplicate key update next_run_at = if(schedule = values(schedule), next_run_at, values(next_run_at)),
+            schedule = values(schedule)"#,
+            job.kind(),
+            expression,
+            next_run_at
+        )
+        .execute(&mut conn)
+        .await?;
+
+        let claimed = sqlx::query!(
+            r#"update JobSchedule set next_run_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),
+            last_run_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+            where name = ? and next_run_at <= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')"#,
+            next_run_at,
+            timestamp,
+            job.kind(),
+            timestamp
+        )
+        .execute(&mut conn)
+        .await?
+        .rows_affected()
+            == 1;
+
+        if claimed {
+            enqueue_job(&job).await?;
+        }
+    }
+
+    Ok(())
+}
+
+struct DueJob {
+    id: String,
+    kind: String,
+    payload: String,
+    status: String,
+    attempts: i32,
+    max_attempts: i32,
+}
+
+// Takes the next due job, including a running one whose lock expired, and
+// marks it as running. Jobs are claimed one at a time, right before they run,
+// so each gets the whole lock. A job whose lock expired on its last attempt
+// is moved to the dead letter state instead of being run again.
+async fn claim_next_job() -> Result<Option<DueJob>, sqlx::Error> {
+    let mut conn = establish_connection().await;
+
+    loop {
+        db_breadcrumb("claim due Job");
+        let now = Utc::now();
+        let timestamp = sql_datetime(now);
+
+        let mut tx = sqlx::Connection::begin(&mut conn).await?;
+
+        let due = sqlx::query_as!(
+            DueJob,
+            r#"select id, kind, payload, status, attempts, max_attempts from Job
+            where (status = ? and run_at <= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+            or (status = ? and locked_until <= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+            order by run_at limit 1 for update skip locked"#,
+            PENDING,
+            timestamp,
+            RUNNING,
+            timestamp
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        let due = match due {
+            Some(due) => due,
+            None => return Ok(None),
+        };
+
+        if due.status == RUNNING && due.attempts >= due.max_attempts {
+            let error = "The worker running it stopped before it finished";
+            sqlx::query!(
+                r#"update Job set status = ?, last_error = ?, locked_until = NULL,
+                finished_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+                DEAD,
+                error,
+                timestamp,
+                due.id
+            )
+            .execute(&mut tx)
+            .await?;
+            tx.commit().await?;
+
+            sentry::capture_message(
+                &format!(
+                    "Job {} ({}) failed after {} attempts: {}",
+                    due.id, due.kind, due.attempts, error
+                ),
+                sentry::Level::Error,
+            );
+            continue;
+        }
+
+        let locked_until = sql_datetime(now + Duration::seconds(LOCK_SECONDS));
+        sqlx::query!(
+            r#"update Job set status = ?, attempts = attempts + 1,
+            started_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),
+            locked_until = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+            RUNNING,
+            timestamp,
+            locked_until,
+            due.id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        return Ok(Some(DueJob {
+            attempts: due.attempts + 1,
+            ..due
+        }));
+    }
+}
+
+async fn mark_succeeded(id: &str) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Job succeeded");
+    let timestamp = sql_datetime(Utc::now());
+
+    sqlx::query!(
+        r#"update Job set status = ?, last_error = NULL, locked_until = NULL,
+        finished_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+        SUCCEEDED,
+        timestamp,
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+// Retries the job later, or moves it to the dead letter state once it has
+// used all its attempts.
+async fn mark_failed(job: &DueJob, error: &str) {
+    let now = Utc::now();
+    let dead = job.attempts >= job.max_attempts;
+    let backoff = (BACKOFF_SECONDS << (job.attempts - 1).clamp(0, 20)).min(MAX_BACKOFF_SECONDS);
+    let run_at = sql_datetime(now + Duration::seconds(backoff));
+    let finished_at = dead.then(|| sql_datetime(now));
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Job failed");
+
+    sqlx::query!(
+        r#"update Job set status = ?, last_error = ?, locked_until = NULL,
+        run_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),
+        finished_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+        if dead { DEAD } else { PENDING },
+        error,
+        run_at,
+        finished_at,
+        job.id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    if dead {
+        sentry::capture_message(
+            &format!(
+                "Job {} ({}) failed after {} attempts: {}",
+                job.id, job.kind, job.attempts, error
+            ),
+            sentry::Level::Error,
+        );
+    }
+}
+
+async fn run_due_jobs(ctx: &JobContext) -> Result<(), sqlx::Error> {
+    for _ in 0..BATCH_SIZE {
+        let due = match claim_next_job().await? {
+            Some(due) => due,
+            None => break,
+        };
+
+        let job: Job = match serde_json::from_str(&due.payload) {
+            Ok(job) => job,
+            Err(err) => {
+                let err = format!("Unreadable payload: {}", err);
+                mark_failed(
+                    &DueJob {
+                        max_attempts: 0,
+                        ..due
+                    },
+                    &err,
+                )
+                .await;
+                continue;
+            }
+        };
+
+        match job.run(ctx).await {
+            Ok(()) => mark_succeeded(&due.id).await,
+            Err(err) => mark_failed(&due, &err).await,
+        }
+    }
+
+    Ok(())
+}
+
+// Deletes succeeded jobs older than `retention_days`. Dead ones are kept
+// until someone looks at them.
+async fn prune_jobs(retention_days: i64) -> Result<u64, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("delete old Jobs");
+    let finished_before = sql_datetime(Utc::now() - Duration::days(retention_days));
+
+    let res = sqlx::query!(
+        "delete from Job where status = ? and finished_at < STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')",
+        SUCCEEDED,
+        finished_before
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+// Queues scheduled jobs, runs due ones and sends due notifications until the
+// process exits. Every instance runs this; jobs and notifications are claimed
+// so each is handled once. The notification outbox is polled directly rather
+// than through a job, so it doesn't leave a Job row behind every few seconds.
+pub async fn run_jobs(state: AppState) {
+    let transport = EmailTransport::from_env().map_err(|err| {
+        sentry::capture_message(
+            &format!(
+                "Email transport misconfigured, notifications won't be sent: {}",
+                err
+            ),
+            sentry::Level::Fatal,
+        );
+        err.to_string()
+    });
+    let ctx = JobContext { redis: state.redis };
+
+    loop {
+        if let Err(err) = enqueue_scheduled_jobs().await {
+            sentry::capture_message(
+                &format!("Failed to queue scheduled jobs: {}", err),
+                sentry::Level::Error,
+            );
+        }
+        if let Ok(transport) = &transport {
+            if let Err(err) = send_due_notifications(transport).await {
+                sentry::capture_message(
+                    &format!("Failed to send notifications: {}", err),
+                    sentry::Level::Error,
+                );
+            }
+        }
+        if let Err(err) = run_due_jobs(&ctx).await {
+            sentry::capture_message(
+                &format!("Failed to run jobs: {}", err),
+                sentry::Level::Error,
+            );
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "jobs",
+    params(JobsQP),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Job runs, newest first", body = [JobRun]),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to see jobs", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_jobs(Query(query): Query<JobsQP>) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Jobs");
+
+    let jobs = sqlx::query!(
+        r#"select id, kind, payload, status, attempts, max_attempts, last_error,
+        DATE_FORMAT(creation_timestamp, '%Y-%m-%dT%TZ') as "created_at!",
+        DATE_FORMAT(run_at, '%Y-%m-%dT%TZ') as "run_at!",
+        DATE_FORMAT(started_at, '%Y-%m-%dT%TZ') as started_at,
+        DATE_FORMAT(finished_at, '%Y-%m-%dT%TZ') as finished_at
+        from Job where (? is null or status = ?) and (? is null or kind = ?)
+        order by creation_timestamp desc limit ?"#,
+        query.status,
+        query.status,
+        query.kind,
+        query.kind,
+        query.limit.unwrap_or(100)
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap();
+
+    let jobs: Vec<JobRun> = jobs
+        .into_iter()
+        .map(|job| JobRun {
+            id: job.id,
+            kind: job.kind,
+            payload: serde_json::from_str(&job.payload).unwrap_or(serde_json::Value::Null),
+            status: job.status,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            last_error: job.last_error,
+            created_at: job.created_at,
+            run_at: job.run_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+        })
+        .collect();
+
+    (StatusCode::OK, Json(jobs)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/jobs/{job_id}/retry",
+    tag = "jobs",
+    params(("job_id" = String, Path, description = "Job id")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 202, description = "Job queued to run now, with its attempts reset if it was dead", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to retry jobs", body = String),
+        (status = 404, description = "Job not found", body = String),
+        (status = 409, description = "Job is running or already succeeded", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn retry_job(Path(job_id): Path<String>) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Job retry");
+    let timestamp = sql_datetime(Utc::now());
+
+    let res = sqlx::query!(
+        r#"update Job set status = ?, attempts = if(status = ?, 0, attempts), finished_at = NULL,
+        run_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ? and status in (?, ?)"#,
+        PENDING,
+        DEAD,
+        timestamp,
+        job_id,
+        PENDING,
+        DEAD
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    if res.rows_affected() == 1 {
+        return (StatusCode::ACCEPTED, Json(String::from("Job queued"))).into_response();
+    }
+
+    let exists = sqlx::query!("select id from Job where id = ?", job_id)
+        .fetch_optional(&mut conn)
+        .await
+        .unwrap()
+        .is_some();
+
+    match exists {
+        true => (
+            StatusCode::CONFLICT,
+            Json(String::from("Job is running or already succeeded")),
+        )
+            .into_response(),
+        false => (StatusCode::NOT_FOUND, Json(String::from("Job not found"))).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{schedules, Job};
+    use cron::Schedule;
+    use std::str::FromStr;
+
+    #[test]
+    fn schedules_parse() {
+        for (job, expression) in schedules() {
+            assert!(
+                Schedule::from_str(expression).is_ok(),
+                "{}: {:?}",
+                job.kind(),
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn payload_is_tagged_with_kind() {
+        let job = Job::RefreshVehicle {
+            license_plate: String::from("GKSB78"),
+        };
+        let payload = serde_json::to_value(&job).unwrap();
+
+        assert_eq!(payload["kind"], job.kind());
+        assert_eq!(payload["license_plate"], "GKSB78");
+        assert_eq!(serde_json::from_value::<Job>(payload).unwrap(), job);
+        assert_eq!(
+            serde_json::to_value(Job::PruneJobs).unwrap()["kind"],
+            Job::PruneJobs.kind()
+        );
+    }
+}
@@ -0,0 +1,377 @@
+// One-off import of the quotes and plans the old Redis-based plan flow left
+// behind (`plan-quote:{id}` and `plan:{id}` hashes) into MySQL.
+//
+// Redis never stored when those were created, nor the client's email or the
+// Reveniu checkout slug, so imported rows are stamped with the import time,
+// an empty client_email (their clients aren't notified) and no payment link.
+// Labour coverage is derived from the price the way quotes compute it, and
+// plans are only imported for vehicles MySQL knows. Legacy plans all went
+// through the Reveniu checkout, so they're imported as credit card plans with
+// a deferred sign. Whether they were ever paid isn't known either, so they're
+// imported inactive and listed for staff to activate once Reveniu confirms
+// the subscription.
+
+use chrono::Utc;
+use redis::AsyncCommands;
+use sqlx::types::BigDecimal;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::helper_structs::{PaymentMethod, SignMethod};
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::vehicle_handler::check_vehicle_exists;
+
+const QUOTE_PREFIX: &str = "plan-quote:";
+const PLAN_PREFIX: &str = "plan:";
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported_quotes: usize,
+    pub imported_plans: usize,
+    // Entries already in MySQL with the same data, e.g. from a previous run.
+    pub already_imported: usize,
+    // Imported plans waiting to be activated by hand.
+    pub pending_activation: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+struct LegacyQuote {
+    id: String,
+    monthly_price: BigDecimal,
+}
+
+struct LegacyPlan {
+    id: String,
+    license_plate: String,
+    reveniu_id: String,
+}
+
+struct StoredPlan {
+    vehicle: String,
+    reveniu_id: Option<String>,
+}
+
+// LEGACY_REDIS_URL points at the instance the old flow wrote to, if it isn't
+// the one in REDIS_URL.
+async fn get_connection() -> redis::RedisResult<redis::aio::Connection> {
+    let url = env::var("LEGACY_REDIS_URL")
+        .or_else(|_| env::var("REDIS_URL"))
+        .unwrap_or_default();
+    redis::Client::open(url.as_str())?
+        .get_async_connection()
+        .await
+}
+
+async fn read_hashes(prefix: &str) -> redis::RedisResult<Vec<(String, HashMap<String, String>)>> {
+    let mut con = get_connection().await?;
+
+    let mut keys: Vec<String> = vec![];
+    {
+        let mut iter = con.scan_match::<_, String>(format!("{}*", prefix)).await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+    }
+    keys.sort();
+
+    let mut hashes = vec![];
+    for key in keys {
+        let hash: HashMap<String, String> = con.hgetall(&key).await?;
+        hashes.push((key[prefix.len()..].to_string(), hash));
+    }
+
+    Ok(hashes)
+}
+
+fn parse_quote(id: String, hash: &HashMap<String, String>) -> Result<LegacyQuote, String> {
+    let price = hash
+        .get("monthly_price")
+        .ok_or_else(|| format!("quote {}: no monthly_price", id))?;
+    let monthly_price = BigDecimal::from_str(price)
+        .map_err(|_| format!("quote {}: invalid monthly_price {:?}", id, price))?;
+
+    Ok(LegacyQuote { id, monthly_price })
+}
+
+fn parse_plan(id: String, hash: &HashMap<String, String>) -> Result<LegacyPlan, String> {
+    let field = |name: &str| {
+        hash.get(name)
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .ok_or_else(|| format!("plan {}: no {}", id, name))
+    };
+
+    Ok(LegacyPlan {
+        license_plate: field("vehicle_license_plate")?,
+        reveniu_id: field("reveniu_plan_id")?,
+        id,
+    })
+}
+
+async fn get_quote_price(id: &str) -> Option<Option<BigDecimal>> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Quote monthly_price by id");
+
+    sqlx::query_scalar!("select monthly_price from Quote where id = ?", id)
+        .fetch_optional(&mut conn)
+        .await
+        .unwrap()
+}
+
+async fn get_stored_plan(id: &str) -> Option<StoredPlan> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Plan by id");
+
+    sqlx::query_as!(
+        StoredPlan,
+        "select vehicle, reveniu_id from Plan where id = ?",
+        id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+}
+
+// Quotes cover 15 times the monthly price before IVA.
+fn labour_coverage(monthly_price: &BigDecimal) -> BigDecimal {
+    (monthly_price / BigDecimal::from_str("1.19").unwrap() * BigDecimal::from(15)).with_scale(2)
+}
+
+async fn insert_quote(quote: &LegacyQuote, license_plate: Option<&str>) -> Result<(), sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert legacy Quote");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    sqlx::query!(
+        r#"insert into Quote(id, license_plate, monthly_price, labour_coverage, creation_timestamp)
+        values (?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        quote.id,
+        license_plate,
+        quote.monthly_price,
+        labour_coverage(&quote.monthly_price),
+        timestamp
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(())
+}
+
+// The plan and its sign go in together so a failed import leaves nothing behind.
+async fn insert_plan(plan: &LegacyPlan) -> Result<(), sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert legacy Plan");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let sign_id = Uuid::new_v4().to_string();
+
+    let mut tx = sqlx::Connection::begin(&mut conn).await?;
+
+    sqlx::query!(
+        r#"insert into Sign(id, sign_link, sign_method, creation_timestamp, verified)
+        values (?,NULL,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),false)"#,
+        sign_id,
+        SignMethod::Deferred.value(),
+        timestamp
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        r#"insert into Plan(id, quote_id, client_email, vehicle, sign, creation_timestamp, active, reveniu_id, payment_link, payment_method)
+        values (?,?,'',?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),false,?,NULL,?)"#,
+        plan.id,
+        plan.id,
+        plan.license_plate,
+        sign_id,
+        timestamp,
+        plan.reveniu_id,
+        PaymentMethod::CreditCard.value()
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Imports every legacy quote and plan that isn't in MySQL yet. Entries that
+// disagree with what MySQL already has are reported and left alone, so the
+// import can be re-run safely. With `dry_run` nothing is written.
+pub async fn import_redis_plans(dry_run: bool) -> redis::RedisResult<ImportReport> {
+    let mut report = ImportReport::default();
+
+    let mut quotes: HashMap<String, LegacyQuote> = HashMap::new();
+    for (id, hash) in read_hashes(QUOTE_PREFIX).await? {
+        match parse_quote(id.clone(), &hash) {
+            Ok(quote) => {
+                quotes.insert(id, quote);
+            }
+            Err(conflict) => report.conflicts.push(conflict),
+        }
+    }
+
+    let mut plans: HashMap<String, LegacyPlan> = HashMap::new();
+    for (id, hash) in read_hashes(PLAN_PREFIX).await? {
+        match parse_plan(id.clone(), &hash) {
+            Ok(plan) => {
+                plans.insert(id, plan);
+            }
+            Err(conflict) => report.conflicts.push(conflict),
+        }
+    }
+
+    let mut quote_ids: Vec<&String> = quotes.keys().collect();
+    quote_ids.sort();
+
+    for id in quote_ids {
+        let quote = &quotes[id];
+        let license_plate = plans.get(id).map(|plan| plan.license_plate.as_str());
+
+        match get_quote_price(id).await {
+            Some(Some(price)) if price == quote.monthly_price => report.already_imported += 1,
+            Some(price) => report.conflicts.push(format!(
+                "quote {}: MySQL has monthly_price {:?}, Redis has {}",
+                id, price, quote.monthly_price
+            )),
+            None if dry_run => report.imported_quotes += 1,
+            None => match insert_quote(quote, license_plate).await {
+                Ok(()) => report.imported_quotes += 1,
+                Err(err) => report.conflicts.push(format!("quote {}: {}", id, err)),
+            },
+        }
+    }
+
+    let mut plan_ids: Vec<&String> = plans.keys().collect();
+    plan_ids.sort();
+
+    for id in plan_ids {
+        let plan = &plans[id];
+
+        if !quotes.contains_key(id) && get_quote_price(id).await.is_none() {
+            report
+                .conflicts
+                .push(format!("plan {}: no quote in Redis or MySQL", id));
+            continue;
+        }
+
+        if check_vehicle_exists(plan.license_plate.clone())
+            .await
+            .is_none()
+        {
+            report.conflicts.push(format!(
+                "plan {}: vehicle {} is not in MySQL",
+                id, plan.license_plate
+            ));
+            continue;
+        }
+
+        match get_stored_plan(id).await {
+            Some(stored)
+                if stored.vehicle == plan.license_plate
+                    && stored.reveniu_id.as_deref() == Some(plan.reveniu_id.as_str()) =>
+            {
+                report.already_imported += 1
+            }
+            Some(stored) => report.conflicts.push(format!(
+                "plan {}: MySQL has vehicle {} and Reveniu plan {:?}, Redis has {} and {}",
+                id, stored.vehicle, stored.reveniu_id, plan.license_plate, plan.reveniu_id
+            )),
+            None if dry_run => {
+                report.imported_plans += 1;
+                report.pending_activation.push(id.clone());
+            }
+            None => match insert_plan(plan).await {
+                Ok(()) => {
+                    report.imported_plans += 1;
+                    report.pending_activation.push(id.clone());
+                }
+                Err(err) => report.conflicts.push(format!("plan {}: {}", id, err)),
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{labour_coverage, parse_plan, parse_quote};
+    use sqlx::types::BigDecimal;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    fn hash(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_quotes() {
+        let quote = parse_quote("q1".to_string(), &hash(&[("monthly_price", "21450.5")])).unwrap();
+        assert_eq!(quote.id, "q1");
+        assert_eq!(
+            quote.monthly_price,
+            BigDecimal::from_str("21450.5").unwrap()
+        );
+
+        assert_eq!(
+            parse_quote("q2".to_string(), &hash(&[])).err().unwrap(),
+            "quote q2: no monthly_price"
+        );
+        assert_eq!(
+            parse_quote("q3".to_string(), &hash(&[("monthly_price", "abc")]))
+                .err()
+                .unwrap(),
+            "quote q3: invalid monthly_price \"abc\""
+        );
+    }
+
+    #[test]
+    fn parses_plans() {
+        let plan = parse_plan(
+            "p1".to_string(),
+            &hash(&[
+                ("vehicle_license_plate", "GKSB78"),
+                ("reveniu_plan_id", "4321"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(plan.id, "p1");
+        assert_eq!(plan.license_plate, "GKSB78");
+        assert_eq!(plan.reveniu_id, "4321");
+
+        assert_eq!(
+            parse_plan(
+                "p2".to_string(),
+                &hash(&[("vehicle_license_plate", "GKSB78"), ("reveniu_plan_id", "")]),
+            )
+            .err()
+            .unwrap(),
+            "plan p2: no reveniu_plan_id"
+        );
+        assert_eq!(
+            parse_plan("p3".to_string(), &hash(&[("reveniu_plan_id", "4321")]))
+                .err()
+                .unwrap(),
+            "plan p3: no vehicle_license_plate"
+        );
+    }
+
+    #[test]
+    fn derives_labour_coverage_from_price() {
+        assert_eq!(
+            labour_coverage(&BigDecimal::from_str("21450.5").unwrap()),
+            BigDecimal::from_str("270384.45").unwrap()
+        );
+        assert_eq!(
+            labour_coverage(&BigDecimal::from_str("17850").unwrap()),
+            BigDecimal::from_str("225000.00").unwrap()
+        );
+    }
+}
@@ -0,0 +1,323 @@
+mod access_token;
+mod api_structs;
+mod appointments;
+mod auth;
+pub mod catalogue;
+mod coverage;
+mod email;
+mod helper_structs;
+mod idempotency;
+pub mod jobs;
+pub mod legacy_import;
+mod license_plate;
+mod maintenance;
+mod messaging;
+pub mod notifications;
+pub mod openapi;
+mod phone;
+mod plan_handlers;
+mod quote_handlers;
+mod rate_limit;
+pub mod redis_store;
+mod regcheck;
+pub mod sql;
+mod structs;
+pub mod telemetry;
+mod validation;
+mod vehicle_cache;
+mod vehicle_category;
+mod vehicle_handler;
+mod vehicle_history;
+mod vehicle_lookup;
+mod vin;
+mod workshops;
+use axum::extract::FromRef;
+use appointments::{
+    book_appointment, cancel_appointment, complete_appointment, get_appointments,
+    reschedule_appointment,
+};
+use auth::{get_current_principal, require_role, Role};
+use catalogue::{get_catalogue_makes, get_catalogue_models};
+use coverage::{get_plan_claims, submit_claim};
+use openapi::{get_docs, get_openapi_spec};
+use plan_handlers::{
+    create_plan_handler, create_plan_handler_v2, get_plan_by_id_handler, get_plan_by_id_handler_v2,
+    record_payment_event,
+};
+use idempotency::idempotency;
+use jobs::{get_jobs, retry_job};
+use maintenance::get_schedule;
+use notifications::{get_notifications, opt_out, put_preference};
+use quote_handlers::{create_quote, get_quote};
+use rate_limit::{rate_limit, BucketConfig, RateLimiter};
+use redis_store::RedisStore;
+use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+use tower_http::cors::CorsLayer;
+use vehicle_handler::{
+    get_review_queue, get_vehicle_data, get_vehicle_history_handler, get_vehicle_types,
+    get_vehicle_types_v2, refresh_vehicle, rederive_vehicle, review_vehicle, update_vehicle,
+    vehicle_manual_creation,
+};
+use vehicle_category::{get_vehicle_type_mappings, put_vehicle_type_mapping};
+use vin::decode_vin;
+use workshops::{create_workshop, get_workshop_slots, get_workshops, update_workshop};
+
+use axum::{
+    http::{HeaderValue, Method},
+    middleware,
+    routing::{get, patch, post, put},
+    Router,
+};
+
+
+// Shared resources created once at startup and handed to the handlers.
+#[derive(Clone)]
+pub struct AppState {
+    pub redis: RedisStore,
+}
+
+impl AppState {
+    pub async fn from_env() -> Self {
+        // Checked here so a bad value stops the API from starting rather than
+        // failing the first notification or client link.
+        notifications::client_base_url();
+        access_token::secret();
+
+        AppState {
+            redis: RedisStore::from_env(),
+        }
+    }
+}
+
+impl FromRef<AppState> for RedisStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.redis.clone()
+    }
+}
+
+// The API with all its routes and layers, as served by the binary.
+pub fn app(state: AppState) -> Router {
+    // Public endpoints that can trigger paid lookups or writes are rate limited.
+    let vehicle_limiter =
+        RateLimiter::from_env("VEHICLE", BucketConfig::new(10, 60), BucketConfig::new(120, 60));
+    let manual_vehicle_limiter = RateLimiter::from_env(
+        "VEHICLE_MANUAL",
+        BucketConfig::new(5, 60),
+        BucketConfig::new(60, 60),
+    );
+    let quote_limiter =
+        RateLimiter::from_env("QUOTE", BucketConfig::new(10, 60), BucketConfig::new(120, 60));
+
+    // Routes that behave the same in every API version.
+    let common = Router::new()
+        .route(
+            "/vehicle",
+            get(get_vehicle_data)
+                .route_layer(middleware::from_fn_with_state(vehicle_limiter, rate_limit)),
+        )
+        .route(
+            "/vehicle-type/mappings",
+            get(get_vehicle_type_mappings).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle-type/mappings/:provider_type",
+            put(put_vehicle_type_mapping).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/manual",
+            post(vehicle_manual_creation).route_layer(middleware::from_fn_with_state(
+                manual_vehicle_limiter,
+                rate_limit,
+            )),
+        )
+        .route(
+            "/vehicle/review-queue",
+            get(get_review_queue).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/:license_plate/review",
+            post(review_vehicle).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/:license_plate",
+            patch(update_vehicle).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/:license_plate/refresh",
+            post(refresh_vehicle).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/:license_plate/rederive",
+            post(rederive_vehicle).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/vehicle/:license_plate/history",
+            get(get_vehicle_history_handler).route_layer(middleware::from_fn_with_state(
+                &[Role::Sales, Role::Support][..],
+                require_role,
+            )),
+        )
+        .route("/catalogue/makes", get(get_catalogue_makes))
+        .route("/catalogue/makes/:make/models", get(get_catalogue_models))
+        .route(
+            "/vin/:vin",
+            get(decode_vin).route_layer(middleware::from_fn_with_state(
+                &[Role::Sales, Role::Support][..],
+                require_role,
+            )),
+        )
+        .route("/quote/:quote_id", get(get_quote))
+        .route(
+            "/plan/:plan_id/payment-events",
+            post(record_payment_event).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/plan/:plan_id/appointments",
+            get(get_appointments).post(book_appointment),
+        )
+        .route(
+            "/plan/:plan_id/appointments/:appointment_id",
+            put(reschedule_appointment).delete(cancel_appointment),
+        )
+        .route(
+            "/plan/:plan_id/appointments/:appointment_id/complete",
+            post(complete_appointment).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route("/plan/:plan_id/schedule", get(get_schedule))
+        .route(
+            "/plan/:plan_id/claims",
+            get(get_plan_claims).merge(post(submit_claim).route_layer(
+                middleware::from_fn_with_state(&[Role::Support][..], require_role),
+            )),
+        )
+        .route(
+            "/workshops",
+            get(get_workshops).merge(post(create_workshop).route_layer(
+                middleware::from_fn_with_state(&[Role::Support][..], require_role),
+            )),
+        )
+        .route(
+            "/workshops/:workshop_id",
+            put(update_workshop).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route("/workshops/:workshop_id/slots", get(get_workshop_slots))
+        .route("/notifications/opt-out", post(opt_out))
+        .route("/notifications/preferences", put(put_preference))
+        .route(
+            "/notifications",
+            get(get_notifications).route_layer(middleware::from_fn_with_state(
+                &[Role::Support][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/jobs",
+            get(get_jobs).route_layer(middleware::from_fn_with_state(
+                &[Role::Admin][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/jobs/:job_id/retry",
+            post(retry_job).route_layer(middleware::from_fn_with_state(
+                &[Role::Admin][..],
+                require_role,
+            )),
+        )
+        .route(
+            "/quote",
+            post(create_quote)
+                .route_layer(middleware::from_fn_with_state(quote_limiter, rate_limit)),
+        )
+        .route(
+            "/auth/me",
+            get(get_current_principal).route_layer(middleware::from_fn_with_state(
+                &[Role::Sales, Role::Support, Role::Admin][..],
+                require_role,
+            )),
+        );
+
+    let v1 = common
+        .clone()
+        .route("/vehicle-type", get(get_vehicle_types))
+        .route(
+            "/plan",
+            post(create_plan_handler).route_layer(middleware::from_fn_with_state(
+                state.redis.clone(),
+                idempotency,
+            )),
+        )
+        .route("/plan/:plan_id", get(get_plan_by_id_handler));
+
+    // v2 uses names instead of numeric values for payment and sign methods,
+    // and describes vehicle categories instead of listing their codes.
+    let v2 = common
+        .route("/vehicle-type", get(get_vehicle_types_v2))
+        .route(
+            "/plan",
+            post(create_plan_handler_v2).route_layer(middleware::from_fn_with_state(
+                state.redis.clone(),
+                idempotency,
+            )),
+        )
+        .route("/plan/:plan_id", get(get_plan_by_id_handler_v2));
+
+    Router::new()
+        .route("/health", get(|| async { "Hello, World!" }))
+        .route("/openapi.json", get(get_openapi_spec))
+        .route("/docs", get(get_docs))
+        // Unprefixed routes are kept for existing clients and behave as v1.
+        .merge(v1.clone())
+        .nest("/v1", v1)
+        .nest("/v2", v2)
+        .layer(
+            CorsLayer::new()
+                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
+                .allow_headers([
+                    http::header::CONTENT_TYPE,
+                    http::header::AUTHORIZATION,
+                    http::HeaderName::from_static("x-api-key"),
+                    http::HeaderName::from_static("idempotency-key"),
+                ])
+                .allow_methods([
+                    Method::GET,
+                    Method::POST,
+                    Method::PATCH,
+                    Method::PUT,
+                    Method::DELETE,
+                ]),
+        )
+        .layer(SentryHttpLayer::with_transaction())
+        .layer(NewSentryLayer::new_from_top())
+        .with_state(state)
+}
@@ -0,0 +1,415 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+// Letters used on plates issued since 2007 (no vowels, M, N, Ñ or Q).
+const CURRENT_LETTERS: &str = "BCDFGHJKLPRSTVWXYZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlateFormat {
+    // AA·1234, cars registered before 2007.
+    Legacy,
+    // BBBB·12, cars registered since 2007.
+    Current,
+    // AA·123, motorcycles registered before 2008.
+    LegacyMotorcycle,
+    // BBB·12, motorcycles registered since 2008.
+    Motorcycle,
+}
+
+impl PlateFormat {
+    fn detect(plate: &str) -> Option<PlateFormat> {
+        let chars: Vec<char> = plate.chars().collect();
+        let letters = chars.iter().take_while(|c| c.is_ascii_uppercase()).count();
+        let digits = &chars[letters..];
+
+        if !digits.iter().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let current = || {
+            chars[..letters]
+                .iter()
+                .all(|c| CURRENT_LETTERS.contains(*c))
+        };
+        let legacy = || chars[..letters].iter().all(|c| *c != 'Q');
+
+        match (letters, digits.len()) {
+            (2, 4) if legacy() => Some(PlateFormat::Legacy),
+            (4, 2) if current() => Some(PlateFormat::Current),
+            (2, 3) if legacy() => Some(PlateFormat::LegacyMotorcycle),
+            (3, 2) if current() => Some(PlateFormat::Motorcycle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicensePlateError {
+    InvalidFormat(String),
+    CheckDigitMismatch { expected: char, found: char },
+}
+
+impl fmt::Display for LicensePlateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LicensePlateError::InvalidFormat(input) => {
+                write!(f, "License plate '{}' is not a valid license plate", input)
+            }
+            LicensePlateError::CheckDigitMismatch { expected, found } => write!(
+                f,
+                "License plate check digit '{}' does not match, expected '{}'",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LicensePlateError {}
+
+// A Chilean license plate in its normalised form: upper-case, without
+// separators or check digit, e.g. "BBCL12".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LicensePlate(String);
+
+impl LicensePlate {
+    // Accepts any casing and the usual separators ("bb-cl-12", "BB·CL·12").
+    // A check digit may follow the plate after a dash ("BBCL12-0"), or glued
+    // to it when that is unambiguous ("BBCL120"); it is verified and dropped.
+    pub fn parse(input: &str) -> Result<LicensePlate, LicensePlateError> {
+        let invalid = || LicensePlateError::InvalidFormat(input.to_string());
+
+        let (body, check_digit) = match input.trim().rsplit_once('-') {
+            Some((body, dv)) if dv.trim().chars().count() == 1 => (body, dv.trim().chars().next()),
+            _ => (input, None),
+        };
+
+        let mut plate = normalise(body);
+        let mut check_digit = check_digit.map(|c| c.to_ascii_uppercase());
+
+        if check_digit.is_none() && PlateFormat::detect(&plate).is_none() {
+            check_digit = plate.pop();
+        }
+
+        if PlateFormat::detect(&plate).is_none() {
+            return Err(invalid());
+        }
+
+        let plate = LicensePlate(plate);
+
+        if let Some(found) = check_digit {
+            let expected = plate.check_digit();
+            if found != expected {
+                return Err(LicensePlateError::CheckDigitMismatch { expected, found });
+            }
+        }
+
+        Ok(plate)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn format(&self) -> PlateFormat {
+        PlateFormat::detect(&self.0).unwrap()
+    }
+
+    // The plate's verification digit (DV), computed like a RUT (modulo 11
+    // with weights 2..7) over the plate's letters translated to numbers with
+    // the Registro Civil tables.
+    pub fn check_digit(&self) -> char {
+        let translated: String = match self.format() {
+            PlateFormat::Current | PlateFormat::Motorcycle => self
+                .0
+                .chars()
+                .map(|c| current_letter_value(c).unwrap_or(c))
+                .collect(),
+            PlateFormat::Legacy | PlateFormat::LegacyMotorcycle => self
+                .0
+                .chars()
+                .map(|c| legacy_letter_value(c).unwrap_or_else(|| c.to_string()))
+                .collect(),
+        };
+
+        let sum: u32 = translated
+            .chars()
+            .rev()
+            .filter_map(|c| c.to_digit(10))
+            .zip([2, 3, 4, 5, 6, 7].iter().cycle())
+            .map(|(digit, weight)| digit * weight)
+            .sum();
+
+        match 11 - sum % 11 {
+            11 => '0',
+            10 => 'K',
+            dv => char::from_digit(dv, 10).unwrap(),
+        }
+    }
+
+    // Human readable form with separators and check digit, e.g. "BB·CL·12-0".
+    pub fn formatted(&self) -> String {
+        let split = match self.format() {
+            PlateFormat::Current => vec![2, 4],
+            PlateFormat::Legacy | PlateFormat::LegacyMotorcycle => vec![2],
+            PlateFormat::Motorcycle => vec![3],
+        };
+
+        let mut out = String::new();
+        for (i, c) in self.0.chars().enumerate() {
+            if split.contains(&i) {
+                out.push('·');
+            }
+            out.push(c);
+        }
+        format!("{}-{}", out, self.check_digit())
+    }
+}
+
+fn normalise(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn current_letter_value(letter: char) -> Option<char> {
+    let value = match letter {
+        'B' => '1',
+        'C' => '2',
+        'D' => '3',
+        'F' => '4',
+        'G' => '5',
+        'H' => '6',
+        'J' => '7',
+        'K' => '8',
+        'L' => '9',
+        'P' => '0',
+        'R' => '2',
+        'S' => '3',
+        'T' => '4',
+        'V' => '5',
+        'W' => '6',
+        'X' => '7',
+        'Y' => '8',
+        'Z' => '9',
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn legacy_letter_value(letter: char) -> Option<String> {
+    let value = match letter {
+        'A' => "14",
+        'B' => "01",
+        'C' => "02",
+        'D' => "03",
+        'E' => "16",
+        'F' => "04",
+        'G' => "05",
+        'H' => "06",
+        'I' => "19",
+        'J' => "07",
+        'K' => "08",
+        'L' => "09",
+        'M' => "10",
+        'N' => "11",
+        'O' => "20",
+        'P' => "12",
+        'R' => "13",
+        'S' => "15",
+        'T' => "17",
+        'U' => "18",
+        'V' => "21",
+        'W' => "22",
+        'X' => "23",
+        'Y' => "24",
+        'Z' => "25",
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+impl std::str::FromStr for LicensePlate {
+    type Err = LicensePlateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LicensePlate::parse(s)
+    }
+}
+
+impl TryFrom<String> for LicensePlate {
+    type Error = LicensePlateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        LicensePlate::parse(&value)
+    }
+}
+
+impl From<LicensePlate> for String {
+    fn from(plate: LicensePlate) -> Self {
+        plate.0
+    }
+}
+
+impl fmt::Display for LicensePlate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'s> ToSchema<'s> for LicensePlate {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "LicensePlate",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "Chilean license plate (AA1234, BBBB12, AA123 or BBB12). Casing, separators \
+                     and a trailing check digit are accepted on input; responses use the \
+                     normalised form.",
+                ))
+                .example(Some(serde_json::json!("BBCL12")))
+                .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LicensePlate, LicensePlateError, PlateFormat};
+
+    #[test]
+    fn parses_plates() {
+        let cases = [
+            ("BBCL12", "BBCL12", PlateFormat::Current),
+            ("bb-cl-12", "BBCL12", PlateFormat::Current),
+            ("BB·CL·12-0", "BBCL12", PlateFormat::Current),
+            ("BBCL120", "BBCL12", PlateFormat::Current),
+            ("bbcl18-k", "BBCL18", PlateFormat::Current),
+            ("bbcl18k", "BBCL18", PlateFormat::Current),
+            (" HJ 4521 ", "HJ4521", PlateFormat::Legacy),
+            ("HJ4521-8", "HJ4521", PlateFormat::Legacy),
+            ("AB123", "AB123", PlateFormat::LegacyMotorcycle),
+            ("BCD12", "BCD12", PlateFormat::Motorcycle),
+            ("BCD129", "BCD12", PlateFormat::Motorcycle),
+        ];
+
+        for (input, plate, format) in cases {
+            let parsed = LicensePlate::parse(input).unwrap();
+            assert_eq!(parsed.as_str(), plate, "{}", input);
+            assert_eq!(parsed.format(), format, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_plates() {
+        let cases = [
+            (
+                "BBCL12-5",
+                LicensePlateError::CheckDigitMismatch {
+                    expected: '0',
+                    found: '5',
+                },
+            ),
+            (
+                "BBCL121",
+                LicensePlateError::CheckDigitMismatch {
+                    expected: '0',
+                    found: '1',
+                },
+            ),
+            (
+                "BACL12",
+                LicensePlateError::InvalidFormat(String::from("BACL12")),
+            ),
+            (
+                "QQ1234",
+                LicensePlateError::InvalidFormat(String::from("QQ1234")),
+            ),
+            (
+                "B12345",
+                LicensePlateError::InvalidFormat(String::from("B12345")),
+            ),
+            ("", LicensePlateError::InvalidFormat(String::new())),
+        ];
+
+        for (input, err) in cases {
+            assert_eq!(LicensePlate::parse(input).unwrap_err(), err, "{}", input);
+        }
+    }
+
+    #[test]
+    fn computes_check_digit() {
+        let cases = [
+            ("BBCL12", '0'),
+            ("BBCL04", 'K'),
+            ("GKSB78", '4'),
+            ("HJ4521", '8'),
+            ("ZZ9999", '1'),
+            ("AB123", '4'),
+            ("BCD12", '9'),
+        ];
+
+        for (plate, dv) in cases {
+            assert_eq!(
+                LicensePlate::parse(plate).unwrap().check_digit(),
+                dv,
+                "{}",
+                plate
+            );
+        }
+    }
+
+    #[test]
+    fn detects_format() {
+        let cases = [
+            ("AB1234", Some(PlateFormat::Legacy)),
+            ("BBCL12", Some(PlateFormat::Current)),
+            ("AB123", Some(PlateFormat::LegacyMotorcycle)),
+            ("BCD12", Some(PlateFormat::Motorcycle)),
+            // Vowels aren't used since 2007, Q never.
+            ("BACL12", None),
+            ("ABC12", None),
+            ("QB1234", None),
+            ("AB12C4", None),
+            ("ABCDE1", None),
+        ];
+
+        for (plate, format) in cases {
+            assert_eq!(PlateFormat::detect(plate), format, "{}", plate);
+        }
+    }
+
+    // AB123 has check digit 4, so "AB1234" could also be that motorcycle with
+    // its check digit glued on. A plate that is valid as written is taken as is.
+    #[test]
+    fn prefers_plate_over_glued_check_digit() {
+        let plate = LicensePlate::parse("AB1234").unwrap();
+        assert_eq!(plate.as_str(), "AB1234");
+        assert_eq!(plate.format(), PlateFormat::Legacy);
+
+        let plate = LicensePlate::parse("AB123-4").unwrap();
+        assert_eq!(plate.as_str(), "AB123");
+        assert_eq!(plate.format(), PlateFormat::LegacyMotorcycle);
+    }
+
+    #[test]
+    fn formats_with_separators() {
+        assert_eq!(
+            LicensePlate::parse("bbcl12").unwrap().formatted(),
+            "BB·CL·12-0"
+        );
+        assert_eq!(
+            LicensePlate::parse("HJ4521").unwrap().formatted(),
+            "HJ·4521-8"
+        );
+        assert_eq!(
+            LicensePlate::parse("BCD12").unwrap().formatted(),
+            "BCD·12-9"
+        );
+    }
+}
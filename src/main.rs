@@ -1,97 +1,68 @@
-mod api_structs;
-mod handlers;
-mod helper_structs;
-mod plan_handlers;
-mod quote_handlers;
-mod sql;
-mod structs;
-mod vehicle_handler;
-use plan_handlers::{create_plan_handler, get_plan_by_id_handler};
-use quote_handlers::{create_quote, get_quote};
-use sentry::integrations::panic::PanicIntegration;
-use tower_http::cors::CorsLayer;
-use vehicle_handler::{get_vehicle_data, get_vehicle_types, vehicle_manual_creation};
-
-use axum::{
-    http::{HeaderValue, Method},
-    routing::{get, post},
-    Router,
+use mechania_api::{
+    app, catalogue, jobs, legacy_import, openapi, sql, telemetry, AppState,
 };
+use std::net::SocketAddr;
 
 #[tokio::main]
 async fn main() {
-    let _guard = sentry::init((
-        "https://7f2f98fe51504514a5ad0eceba3c9d03@o1166558.ingest.sentry.io/4505213614161920",
-        sentry::ClientOptions {
-            environment: Some(std::env::var("SENTRY_ENVIRONMENT").unwrap().into()),
-            release: sentry::release_name!(),
-            ..Default::default()
-        }
-        .add_integration(PanicIntegration::new()),
-    ));
+    // `mechania-api openapi` prints the OpenAPI document instead of serving.
+    if std::env::args().nth(1).as_deref() == Some("openapi") {
+        println!("{}", openapi::openapi_json());
+        return;
+    }
+
+    let _guard = telemetry::init_sentry();
+
+    sql::run_migrations().await;
+
+    // `mechania-api seed-catalogue` fills the make/model catalogue from the
+    // vehicles already stored, then exits.
+    if std::env::args().nth(1).as_deref() == Some("seed-catalogue") {
+        let seeded = catalogue::seed_from_vehicles().await;
+        println!("Seeded catalogue from {} make/model pairs", seeded);
+        return;
+    }
 
-    // build our application with a single route
-    let app = Router::new()
-        .route("/health", get(|| async { "Hello, World!" }))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/vehicle", get(get_vehicle_data))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/vehicle-type", get(get_vehicle_types))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/vehicle/manual", post(vehicle_manual_creation))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/quote/:quote_id", get(get_quote))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/quote", post(create_quote))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/plan", post(create_plan_handler))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
-        )
-        .route("/plan/:plan_id", get(get_plan_by_id_handler))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
-                .allow_headers([http::header::CONTENT_TYPE])
-                .allow_methods([Method::GET, Method::POST]),
+    // `mechania-api import-redis-plans [--dry-run]` copies the quotes and plans
+    // left in Redis by the old plan flow into MySQL and reports conflicts.
+    if std::env::args().nth(1).as_deref() == Some("import-redis-plans") {
+        let dry_run = std::env::args().any(|arg| arg == "--dry-run");
+        let report = legacy_import::import_redis_plans(dry_run)
+            .await
+            .expect("Could not read the legacy plans from Redis");
+        if dry_run {
+            println!("Dry run, nothing was written");
+        }
+        println!(
+            "Imported {} quotes and {} plans, {} already imported, {} conflicts",
+            report.imported_quotes,
+            report.imported_plans,
+            report.already_imported,
+            report.conflicts.len()
         );
+        for conflict in &report.conflicts {
+            println!("  {}", conflict);
+        }
+        if !report.pending_activation.is_empty() {
+            println!(
+                "{} plans were imported inactive. Activate each one by recording a received payment (POST /plan/<id>/payment-events) once Reveniu confirms it:",
+                report.pending_activation.len()
+            );
+            for plan_id in &report.pending_activation {
+                println!("  {}", plan_id);
+            }
+        }
+        return;
+    }
+
+    let state = AppState::from_env().await;
+
+    // Notifications, retries and scheduled maintenance run in the background.
+    tokio::spawn(jobs::run_jobs(state.clone()));
 
     // run it with hyper on localhost:3000
     axum::Server::bind(&"0.0.0.0:8080".parse().unwrap())
-        .serve(app.into_make_service())
+        .serve(app(state).into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
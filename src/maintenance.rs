@@ -0,0 +1,515 @@
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Months, NaiveDate, Utc};
+use http::StatusCode;
+use num_traits::ToPrimitive;
+use serde::Serialize;
+use sqlx::MySqlConnection;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::access_token::{authorize_client_access, Resource};
+use crate::api_structs::AccessTokenQP;
+use crate::appointments::get_active_plan_vehicle;
+use crate::auth::Principal;
+use crate::catalogue::normalize;
+use crate::plan_handlers::PLAN_CYCLES;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::workshops::Service;
+
+const PENDING: &str = "pending";
+const DONE: &str = "done";
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MaintenanceItem {
+    pub id: String,
+    pub service: Service,
+    pub description: String,
+    // Month of the plan (1 to 12) the service is due in.
+    pub cycle: i32,
+    // YYYY-MM-DD
+    pub due_date: String,
+    // "pending" or "done"
+    pub status: String,
+    // The completed appointment that took care of it.
+    pub appointment_id: Option<String>,
+    pub done_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MaintenanceSchedule {
+    pub plan_id: String,
+    pub license_plate: String,
+    // YYYY-MM-DD, first day of the plan term.
+    pub starts_on: String,
+    // Labour covered by the plan for these services, in CLP.
+    pub labour_coverage: f32,
+    // Ordered by due date.
+    pub items: Vec<MaintenanceItem>,
+}
+
+// What the intervals are matched against.
+#[derive(Debug, Default)]
+struct VehicleProfile {
+    make: Option<String>,
+    model: Option<String>,
+    year: Option<i32>,
+    fuel: Option<String>,
+    engine_code: Option<String>,
+}
+
+// A row of MaintenanceInterval.
+#[derive(Debug)]
+struct Interval {
+    make: Option<String>,
+    model: Option<String>,
+    fuel: Option<String>,
+    engine_code: Option<String>,
+    year_from: Option<i32>,
+    year_to: Option<i32>,
+    service: String,
+    description: String,
+    every_months: Option<i32>,
+}
+
+impl Interval {
+    fn matches(&self, vehicle: &VehicleProfile) -> bool {
+        let same = |criterion: &Option<String>, value: &Option<String>| match criterion {
+            None => true,
+            Some(criterion) => value
+                .as_deref()
+                .is_some_and(|value| normalize(value) == normalize(criterion)),
+        };
+        let engine = match &self.engine_code {
+            None => true,
+            Some(prefix) => vehicle
+                .engine_code
+                .as_deref()
+                .is_some_and(|code| normalize(code).starts_with(&normalize(prefix))),
+        };
+        let year = match vehicle.year {
+            Some(year) => {
+                self.year_from.is_none_or(|from| year >= from)
+                    && self.year_to.is_none_or(|to| year <= to)
+            }
+            None => self.year_from.is_none() && self.year_to.is_none(),
+        };
+
+        same(&self.make, &vehicle.make)
+            && same(&self.model, &vehicle.model)
+            && same(&self.fuel, &vehicle.fuel)
+            && engine
+            && year
+    }
+
+    // A fuel row without `every_months`, e.g. no oil changes for electric
+    // vehicles, rules the service out whatever the other rows say.
+    fn excludes_fuel(&self) -> bool {
+        self.fuel.is_some() && self.every_months.is_none()
+    }
+
+    // An engine code beats a model, which beats a make; fuel and years only
+    // break ties between rows at the same level.
+    fn specificity(&self) -> u32 {
+        u32::from(self.engine_code.is_some()) * 8
+            + u32::from(self.model.is_some()) * 4
+            + u32::from(self.make.is_some()) * 2
+            + u32::from(self.fuel.is_some() || self.year_from.is_some() || self.year_to.is_some())
+    }
+}
+
+// A service due in month `cycle` of the plan.
+#[derive(Debug, PartialEq)]
+struct PlannedService {
+    service: Service,
+    description: String,
+    cycle: u32,
+    due_date: NaiveDate,
+}
+
+// Every service the intervals call for over the plan term, by due date.
+fn plan_services(
+    vehicle: &VehicleProfile,
+    intervals: &[Interval],
+    starts_on: NaiveDate,
+) -> Vec<PlannedService> {
+    let matching: Vec<&Interval> = intervals
+        .iter()
+        .filter(|interval| interval.matches(vehicle))
+        .collect();
+    let excluded: Vec<&str> = matching
+        .iter()
+        .filter(|interval| interval.excludes_fuel())
+        .map(|interval| interval.service.as_str())
+        .collect();
+
+    let mut chosen: Vec<&Interval> = vec![];
+    for interval in matching
+        .into_iter()
+        .filter(|interval| !excluded.contains(&interval.service.as_str()))
+    {
+        match chosen.iter_mut().find(|c| c.service == interval.service) {
+            Some(current) if interval.specificity() > current.specificity() => *current = interval,
+            Some(_) => {}
+            None => chosen.push(interval),
+        }
+    }
+
+    let mut services = vec![];
+    for interval in chosen {
+        let (service, every) = match (Service::parse(&interval.service), interval.every_months) {
+            (Some(service), Some(every)) if every > 0 => (service, every),
+            _ => continue,
+        };
+        for cycle in (every as u32..=PLAN_CYCLES).step_by(every as usize) {
+            services.push(PlannedService {
+                service,
+                description: interval.description.clone(),
+                cycle,
+                due_date: starts_on + Months::new(cycle),
+            });
+        }
+    }
+
+    services
+        .sort_by(|a, b| (a.due_date, a.service.as_str()).cmp(&(b.due_date, b.service.as_str())));
+    services
+}
+
+async fn get_vehicle_profile(conn: &mut MySqlConnection, license_plate: &str) -> VehicleProfile {
+    db_breadcrumb("select Vehicle for maintenance");
+
+    let vehicle = sqlx::query!(
+        "select make, model, registration_year, fuel, engine_code from Vehicle where license_plate = ?",
+        license_plate
+    )
+    .fetch_optional(conn)
+    .await
+    .unwrap();
+
+    match vehicle {
+        Some(vehicle) => VehicleProfile {
+            make: vehicle.make,
+            model: vehicle.model,
+            year: vehicle.registration_year.and_then(|year| year.parse().ok()),
+            fuel: vehicle.fuel,
+            engine_code: vehicle.engine_code,
+        },
+        None => VehicleProfile::default(),
+    }
+}
+
+async fn get_intervals(conn: &mut MySqlConnection) -> Vec<Interval> {
+    db_breadcrumb("select MaintenanceIntervals");
+
+    sqlx::query_as!(
+        Interval,
+        "select make, model, fuel, engine_code, year_from, year_to, service, description, every_months
+        from MaintenanceInterval order by id"
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_items(conn: &mut MySqlConnection, plan_id: &str) -> Vec<MaintenanceItem> {
+    db_breadcrumb("select MaintenanceItems by plan");
+
+    sqlx::query!(
+        r#"select id, service, description, cycle, DATE_FORMAT(due_date, '%Y-%m-%d') as "due_date!",
+        status, appointment_id, DATE_FORMAT(done_at, '%Y-%m-%dT%TZ') as done_at
+        from MaintenanceItem where plan_id = ? order by due_date, service"#,
+        plan_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .filter_map(|item| {
+        Some(MaintenanceItem {
+            id: item.id,
+            service: Service::parse(&item.service)?,
+            description: item.description,
+            cycle: item.cycle,
+            due_date: item.due_date,
+            status: item.status,
+            appointment_id: item.appointment_id,
+            done_at: item.done_at,
+        })
+    })
+    .collect()
+}
+
+// Marks the earliest pending item for the appointment's service as done. The
+// plan may have nothing pending for it, e.g. extra brake work.
+pub async fn mark_done(
+    conn: &mut MySqlConnection,
+    plan_id: &str,
+    service: &str,
+    appointment_id: &str,
+) -> bool {
+    db_breadcrumb("update MaintenanceItem done");
+
+    let res = sqlx::query!(
+        r#"update MaintenanceItem set status = ?, appointment_id = ?,
+        done_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        where plan_id = ? and service = ? and status = ?
+        order by due_date limit 1"#,
+        DONE,
+        appointment_id,
+        Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        plan_id,
+        service,
+        PENDING
+    )
+    .execute(conn)
+    .await
+    .unwrap();
+
+    res.rows_affected() > 0
+}
+
+// The plan's schedule, generated from the vehicle the first time it's needed.
+// Locks the plan so concurrent requests don't generate it twice.
+pub async fn ensure_schedule(
+    conn: &mut MySqlConnection,
+    plan_id: &str,
+    license_plate: &str,
+) -> MaintenanceSchedule {
+    db_breadcrumb("select Plan term for update");
+    let plan = sqlx::query!(
+        r#"select DATE_FORMAT(P.creation_timestamp, '%Y-%m-%d') as "starts_on!", Q.labour_coverage
+        from Plan P join Quote Q on Q.id = P.quote_id where P.id = ? for update"#,
+        plan_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .unwrap();
+    let starts_on = NaiveDate::parse_from_str(&plan.starts_on, "%Y-%m-%d").unwrap();
+
+    let mut items = get_items(conn, plan_id).await;
+    if items.is_empty() {
+        let vehicle = get_vehicle_profile(conn, license_plate).await;
+        let intervals = get_intervals(conn).await;
+
+        for planned in plan_services(&vehicle, &intervals, starts_on) {
+            db_breadcrumb("insert MaintenanceItem");
+            sqlx::query!(
+                r#"insert into MaintenanceItem(id, plan_id, service, description, cycle, due_date, status)
+                values (?, ?, ?, ?, ?, STR_TO_DATE(?, '%Y-%m-%d'), ?)"#,
+                Uuid::new_v4().to_string(),
+                plan_id,
+                planned.service.as_str(),
+                planned.description,
+                planned.cycle,
+                planned.due_date.format("%Y-%m-%d").to_string(),
+                PENDING
+            )
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        }
+        items = get_items(conn, plan_id).await;
+    }
+
+    MaintenanceSchedule {
+        plan_id: plan_id.to_string(),
+        license_plate: license_plate.to_string(),
+        starts_on: plan.starts_on,
+        labour_coverage: plan
+            .labour_coverage
+            .and_then(|coverage| coverage.to_f32())
+            .unwrap_or_default(),
+        items,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/plan/{plan_id}/schedule",
+    tag = "appointments",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Services the plan covers over its term and which are done", body = MaintenanceSchedule),
+        (status = 403, description = "Missing or invalid plan access token", body = String),
+        (status = 404, description = "Plan not found", body = String),
+        (status = 409, description = "Plan not active yet", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_schedule(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Plan,
+        &plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+
+    let license_plate = match get_active_plan_vehicle(&mut tx, &plan_id).await {
+        Ok(license_plate) => license_plate,
+        Err(res) => return res,
+    };
+    let schedule = ensure_schedule(&mut tx, &plan_id, &license_plate).await;
+    tx.commit().await.unwrap();
+
+    (StatusCode::OK, Json(schedule)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_services, Interval, VehicleProfile};
+    use crate::workshops::Service;
+    use chrono::NaiveDate;
+
+    fn interval(service: &str, every_months: Option<i32>) -> Interval {
+        Interval {
+            make: None,
+            model: None,
+            fuel: None,
+            engine_code: None,
+            year_from: None,
+            year_to: None,
+            service: service.to_string(),
+            description: service.to_string(),
+            every_months,
+        }
+    }
+
+    fn yaris() -> VehicleProfile {
+        VehicleProfile {
+            make: Some("TOYOTA".to_string()),
+            model: Some("YARIS".to_string()),
+            year: Some(2015),
+            fuel: Some("GASOLINA".to_string()),
+            engine_code: Some("2NZ4567890".to_string()),
+        }
+    }
+
+    #[test]
+    fn most_specific_interval_wins() {
+        let intervals = vec![
+            interval("maintenance", Some(12)),
+            Interval {
+                make: Some("Toyota".to_string()),
+                ..interval("maintenance", Some(6))
+            },
+            Interval {
+                make: Some("HYUNDAI".to_string()),
+                ..interval("maintenance", Some(3))
+            },
+            interval("oil_change", Some(4)),
+            Interval {
+                engine_code: Some("2NZ".to_string()),
+                ..interval("oil_change", Some(12))
+            },
+            Interval {
+                year_to: Some(2010),
+                ..interval("oil_change", Some(1))
+            },
+            interval("tires", Some(6)),
+            Interval {
+                fuel: Some("gasolina".to_string()),
+                ..interval("tires", None)
+            },
+        ];
+        let starts_on = NaiveDate::from_ymd_opt(2026, 1, 31).unwrap();
+
+        let services: Vec<(Service, u32, String)> = plan_services(&yaris(), &intervals, starts_on)
+            .into_iter()
+            .map(|s| (s.service, s.cycle, s.due_date.to_string()))
+            .collect();
+        assert_eq!(
+            services,
+            [
+                (Service::Maintenance, 6, "2026-07-31".to_string()),
+                (Service::Maintenance, 12, "2027-01-31".to_string()),
+                (Service::OilChange, 12, "2027-01-31".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn electric_vehicles_skip_oil_changes_whatever_the_make() {
+        let intervals = vec![
+            interval("maintenance", Some(6)),
+            interval("oil_change", Some(6)),
+            Interval {
+                fuel: Some("ELECTRICO".to_string()),
+                ..interval("oil_change", None)
+            },
+            Interval {
+                make: Some("HYUNDAI".to_string()),
+                year_from: Some(2015),
+                ..interval("maintenance", Some(12))
+            },
+            Interval {
+                make: Some("HYUNDAI".to_string()),
+                year_from: Some(2015),
+                ..interval("oil_change", Some(12))
+            },
+        ];
+        let ioniq = VehicleProfile {
+            make: Some("HYUNDAI".to_string()),
+            model: Some("IONIQ".to_string()),
+            year: Some(2020),
+            fuel: Some("ELECTRICO".to_string()),
+            engine_code: None,
+        };
+        let starts_on = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+
+        let services: Vec<(Service, u32)> = plan_services(&ioniq, &intervals, starts_on)
+            .into_iter()
+            .map(|s| (s.service, s.cycle))
+            .collect();
+        assert_eq!(services, [(Service::Maintenance, 12)]);
+
+        // The same car with a combustion engine still gets them.
+        let elantra = VehicleProfile {
+            fuel: Some("GASOLINA".to_string()),
+            ..ioniq
+        };
+        let services = plan_services(&elantra, &intervals, starts_on);
+        assert!(services
+            .iter()
+            .any(|s| s.service == Service::OilChange && s.cycle == 12));
+    }
+
+    #[test]
+    fn unknown_vehicles_get_the_defaults() {
+        let intervals = vec![
+            interval("maintenance", Some(6)),
+            Interval {
+                make: Some("TOYOTA".to_string()),
+                ..interval("maintenance", Some(3))
+            },
+            Interval {
+                year_from: Some(2015),
+                ..interval("brakes", Some(12))
+            },
+            interval("not_a_service", Some(1)),
+        ];
+        let starts_on = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+
+        let services = plan_services(&VehicleProfile::default(), &intervals, starts_on);
+        assert_eq!(services.len(), 2);
+        assert!(services.iter().all(|s| s.service == Service::Maintenance));
+        assert_eq!(
+            services[1].due_date,
+            NaiveDate::from_ymd_opt(2027, 10, 19).unwrap()
+        );
+    }
+}
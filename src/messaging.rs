@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::phone::PhoneNumber;
+use crate::telemetry::http_breadcrumb;
+
+// Messages not accepted by the provider within this long are retried later.
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(SEND_TIMEOUT)
+        .build()
+        .unwrap()
+}
+
+// How a client wants to be notified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Email,
+    Whatsapp,
+    Sms,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Whatsapp => "whatsapp",
+            Channel::Sms => "sms",
+        }
+    }
+
+    pub fn parse(channel: &str) -> Option<Channel> {
+        match channel {
+            "email" => Some(Channel::Email),
+            "whatsapp" => Some(Channel::Whatsapp),
+            "sms" => Some(Channel::Sms),
+            _ => None,
+        }
+    }
+}
+
+// A short message for a phone: the rendered text, and the template it came
+// from with the values of its placeholders for channels that only deliver
+// pre-approved templates.
+pub struct Message<'a> {
+    pub template: &'a str,
+    pub parameters: &'a [String],
+    pub text: &'a str,
+}
+
+// A channel that delivers short messages to a phone number.
+#[async_trait]
+pub trait MessagingChannel: Send + Sync {
+    async fn send(
+        &self,
+        to: &PhoneNumber,
+        message: &Message<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+// WhatsApp Business Cloud API. WHATSAPP_API_URL is the messages endpoint of
+// the sending number (https://graph.facebook.com/v17.0/<phone number id>/messages),
+// or a local fake. Businesses can only start a conversation with an approved
+// template, so messages are sent as the template named like ours (e.g.
+// `quote_summary`) in WHATSAPP_TEMPLATE_LANGUAGE (default `es`), with the
+// placeholder values as body parameters.
+pub struct WhatsAppChannel {
+    url: String,
+    token: String,
+    language: String,
+}
+
+#[async_trait]
+impl MessagingChannel for WhatsAppChannel {
+    async fn send(
+        &self,
+        to: &PhoneNumber,
+        message: &Message<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let parameters: Vec<_> = message
+            .parameters
+            .iter()
+            .map(|value| json!({ "type": "text", "text": value }))
+            .collect();
+        let resp = http_client()
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .json(&json!({
+                "messaging_product": "whatsapp",
+                "to": to.as_str().trim_start_matches('+'),
+                "type": "template",
+                "template": {
+                    "name": message.template,
+                    "language": { "code": self.language },
+                    "components": [{ "type": "body", "parameters": parameters }],
+                },
+            }))
+            .send()
+            .await;
+        http_breadcrumb(
+            "POST",
+            &self.url,
+            resp.as_ref().ok().map(|r| r.status().as_u16()),
+        );
+        resp?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+// SMS gateway taking `{"to": "+569...", "text": "..."}` at SMS_API_URL,
+// authenticated with SMS_API_KEY as a bearer token.
+pub struct SmsChannel {
+    url: String,
+    api_key: String,
+}
+
+#[async_trait]
+impl MessagingChannel for SmsChannel {
+    async fn send(
+        &self,
+        to: &PhoneNumber,
+        message: &Message<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let resp = http_client()
+            .post(&self.url)
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "to": to.as_str(), "text": message.text }))
+            .send()
+            .await;
+        http_breadcrumb(
+            "POST",
+            &self.url,
+            resp.as_ref().ok().map(|r| r.status().as_u16()),
+        );
+        resp?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+// The messaging channel configured for `channel`, if any. Email isn't a
+// messaging channel, it goes through EmailTransport.
+pub fn messaging_channel(channel: Channel) -> Option<Box<dyn MessagingChannel>> {
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+
+    match channel {
+        Channel::Email => None,
+        Channel::Whatsapp => Some(Box::new(WhatsAppChannel {
+            url: var("WHATSAPP_API_URL")?,
+            token: var("WHATSAPP_TOKEN").unwrap_or_default(),
+            language: var("WHATSAPP_TEMPLATE_LANGUAGE").unwrap_or_else(|| String::from("es")),
+        })),
+        Channel::Sms => Some(Box::new(SmsChannel {
+            url: var("SMS_API_URL")?,
+            api_key: var("SMS_API_KEY").unwrap_or_default(),
+        })),
+    }
+}
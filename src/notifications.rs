@@ -0,0 +1,823 @@
+use axum::{extract::Query, response::IntoResponse, Json};
+use chrono::{Duration, Utc};
+use http::StatusCode;
+use num_traits::ToPrimitive;
+use sqlx::types::BigDecimal;
+use std::env;
+use std::error::Error;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::access_token::{authorize_client_access, issue_access_token, Resource};
+use crate::api_structs::{
+    AccessTokenQP, CreateQuoteBody, Notification, NotificationOptOut, NotificationPreference,
+    NotificationPreferenceUpdate, NotificationsQP, Quote,
+};
+use crate::auth::Principal;
+use crate::email::EmailTransport;
+use crate::messaging::{messaging_channel, Channel, Message};
+use crate::phone::PhoneNumber;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::validation::{ValidatedJson, ValidationRejection};
+
+// Sending is retried with an exponential backoff (1, 2, 4, 8 minutes) before
+// the notification is marked as failed.
+const MAX_ATTEMPTS: i32 = 5;
+const BATCH_SIZE: i64 = 20;
+// How long a claimed notification is kept from other workers while it's sent.
+const CLAIM_SECONDS: i64 = 5 * 60;
+
+const PENDING: &str = "pending";
+const SENT: &str = "sent";
+const FAILED: &str = "failed";
+const SKIPPED: &str = "skipped";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Template {
+    QuoteSummary,
+    PlanCreated,
+    ContractToSign,
+    PaymentReceived,
+    PaymentFailed,
+}
+
+impl Template {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Template::QuoteSummary => "quote_summary",
+            Template::PlanCreated => "plan_created",
+            Template::ContractToSign => "contract_to_sign",
+            Template::PaymentReceived => "payment_received",
+            Template::PaymentFailed => "payment_failed",
+        }
+    }
+
+    // The first line of a template is the subject, the rest the body.
+    fn source(&self) -> &'static str {
+        match self {
+            Template::QuoteSummary => include_str!("../templates/email/quote_summary.txt"),
+            Template::PlanCreated => include_str!("../templates/email/plan_created.txt"),
+            Template::ContractToSign => include_str!("../templates/email/contract_to_sign.txt"),
+            Template::PaymentReceived => include_str!("../templates/email/payment_received.txt"),
+            Template::PaymentFailed => include_str!("../templates/email/payment_failed.txt"),
+        }
+    }
+
+    // Short version for WhatsApp and SMS, without subject.
+    fn message_source(&self) -> &'static str {
+        match self {
+            Template::QuoteSummary => include_str!("../templates/message/quote_summary.txt"),
+            Template::PlanCreated => include_str!("../templates/message/plan_created.txt"),
+            Template::ContractToSign => include_str!("../templates/message/contract_to_sign.txt"),
+            Template::PaymentReceived => include_str!("../templates/message/payment_received.txt"),
+            Template::PaymentFailed => include_str!("../templates/message/payment_failed.txt"),
+        }
+    }
+}
+
+const FOOTER: &str = include_str!("../templates/email/footer.txt");
+
+fn fill(source: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(source.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+// Names of the `{{name}}` placeholders in `source`, in order.
+fn placeholders(source: &str) -> Vec<&str> {
+    source
+        .split("{{")
+        .skip(1)
+        .filter_map(|part| part.split_once("}}"))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Values for the placeholders of a template's message version, in order, as
+// WhatsApp template parameters. Those can't contain line breaks.
+fn message_parameters(template: Template, vars: &[(&str, String)]) -> Vec<String> {
+    placeholders(template.message_source())
+        .into_iter()
+        .map(|name| {
+            let value = vars
+                .iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or_default();
+            value.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect()
+}
+
+// Renders a template and the opt-out footer into (subject, body).
+fn render(template: Template, vars: &[(&str, String)]) -> (String, String) {
+    let text = fill(template.source(), vars);
+    let (subject, body) = text.split_once('\n').unwrap_or((&text, ""));
+
+    (
+        subject.trim().to_string(),
+        format!("{}{}", body.trim_start_matches('\n'), fill(FOOTER, vars)),
+    )
+}
+
+// Chilean peso amounts, e.g. 21450.5 -> "$21.451".
+pub fn format_clp(amount: f32) -> String {
+    let digits = (amount.round() as i64).to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push('.');
+        }
+        grouped.push(digit);
+    }
+    format!("${}", grouped)
+}
+
+static CLIENT_BASE_URL: OnceLock<String> = OnceLock::new();
+
+// Where the client app is served, from CLIENT_BASE_URL. Links sent to clients
+// and payment redirects point there, never to the request's Host header,
+// which whoever calls the API controls.
+pub fn client_base_url() -> &'static str {
+    CLIENT_BASE_URL.get_or_init(|| {
+        let url = env::var("CLIENT_BASE_URL").expect("CLIENT_BASE_URL must be set");
+        match reqwest::Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => {
+                url.trim_end_matches('/').to_string()
+            }
+            _ => panic!("CLIENT_BASE_URL must be an http(s) URL, got {:?}", url),
+        }
+    })
+}
+
+pub fn client_link(path: &str, params: &[(&str, &str)]) -> String {
+    let base = client_base_url();
+    reqwest::Url::parse_with_params(&format!("{}{}", base, path), params)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| format!("{}{}", base, path))
+}
+
+async fn is_opted_out(email: &str) -> Result<bool, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select NotificationOptOut");
+
+    let opt_out = sqlx::query_scalar!(
+        "select email from NotificationOptOut where email = ?",
+        email.to_lowercase()
+    )
+    .fetch_optional(&mut conn)
+    .await?;
+
+    Ok(opt_out.is_some())
+}
+
+struct StoredPreference {
+    phone: Option<String>,
+    channel: String,
+}
+
+async fn get_preference(
+    email: &str,
+) -> Result<Option<(Channel, Option<PhoneNumber>)>, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select NotificationPreference");
+
+    let preference = sqlx::query_as!(
+        StoredPreference,
+        "select phone, channel from NotificationPreference where email = ?",
+        email.to_lowercase()
+    )
+    .fetch_optional(&mut conn)
+    .await?;
+
+    Ok(preference.map(|preference| {
+        (
+            Channel::parse(&preference.channel).unwrap_or(Channel::Email),
+            preference
+                .phone
+                .and_then(|phone| PhoneNumber::parse(&phone).ok()),
+        )
+    }))
+}
+
+// Records the client's phone and/or preferred channel, keeping whatever
+// isn't given.
+pub async fn set_preference(
+    email: &str,
+    phone: Option<&PhoneNumber>,
+    channel: Option<Channel>,
+) -> Result<(), sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("upsert NotificationPreference");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let phone = phone.map(|phone| phone.as_str());
+    let channel = channel.map(|channel| channel.as_str());
+
+    sqlx::query!(
+        r#"insert into NotificationPreference(email, phone, channel, updated_at)
+        values (?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+        on duplicate key update phone = coalesce(?, phone), channel = coalesce(?, channel),
+        updated_at = values(updated_at)"#,
+        email.to_lowercase(),
+        phone,
+        channel.unwrap_or(Channel::Email.as_str()),
+        timestamp,
+        phone,
+        channel
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(())
+}
+
+// Channel and phone given with a quote, for its own notifications.
+pub struct QuoteContact {
+    pub channel: Option<Channel>,
+    pub phone: Option<PhoneNumber>,
+}
+
+// Queues a notification for the outbox worker, on the channel the client
+// prefers, or the one asked for with the quote when they haven't set one.
+// WhatsApp and SMS fall back to email when there's no phone or the channel
+// isn't configured. Clients who opted out still get a row, marked as
+// skipped, so the outbox shows what they would have received.
+// Failing to queue is reported but never fails the caller.
+pub async fn notify(
+    template: Template,
+    recipient: &str,
+    reference_id: &str,
+    quote_contact: &QuoteContact,
+    vars: &[(&str, String)],
+) {
+    let res = queue_notification(template, recipient, reference_id, quote_contact, vars).await;
+
+    if let Err(err) = res {
+        sentry::capture_message(
+            &format!(
+                "Failed to queue {} notification for {}: {}",
+                template.as_str(),
+                reference_id,
+                err
+            ),
+            sentry::Level::Error,
+        );
+    }
+}
+
+async fn queue_notification(
+    template: Template,
+    recipient: &str,
+    reference_id: &str,
+    quote_contact: &QuoteContact,
+    vars: &[(&str, String)],
+) -> Result<(), sqlx::Error> {
+    let token = issue_access_token(Resource::Notifications, &recipient.to_lowercase());
+    let opt_out_link = client_link(
+        "/notifications/opt-out",
+        &[("email", recipient), ("access_token", &token)],
+    );
+    let mut vars = vars.to_vec();
+    vars.push(("opt_out_link", opt_out_link));
+    let (subject, mut body) = render(template, &vars);
+
+    let mut channel = Channel::Email;
+    let mut to = recipient.to_string();
+    let mut parameters = None;
+    let preference = match get_preference(recipient).await? {
+        Some(preference) => Some(preference),
+        None => quote_contact
+            .channel
+            .map(|channel| (channel, quote_contact.phone.clone())),
+    };
+    if let Some((preferred, Some(phone))) = preference {
+        if messaging_channel(preferred).is_some() {
+            channel = preferred;
+            to = phone.to_string();
+            body = fill(template.message_source(), &vars).trim().to_string();
+            parameters = Some(serde_json::to_string(&message_parameters(template, &vars)).unwrap());
+        }
+    }
+
+    let status = match is_opted_out(recipient).await? {
+        true => SKIPPED,
+        false => PENDING,
+    };
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert Notification");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    sqlx::query!(
+        r#"insert into Notification(id, channel, recipient, template, reference_id, subject, body, parameters, status, attempts, creation_timestamp, next_attempt_at)
+        values (?,?,?,?,?,?,?,?,?,0,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'),STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        Uuid::new_v4().to_string(),
+        channel.as_str(),
+        to,
+        template.as_str(),
+        reference_id,
+        subject,
+        body,
+        parameters,
+        status,
+        timestamp,
+        timestamp
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn notify_quote_summary(params: &CreateQuoteBody, quote: &Quote) {
+    let net_price = quote.monthly_cost / 1.19;
+    let access_token = quote.access_token.clone().unwrap_or_default();
+
+    notify(
+        Template::QuoteSummary,
+        &params.email,
+        &quote.id,
+        &QuoteContact {
+            channel: params.notification_channel,
+            phone: params.phone.clone(),
+        },
+        &[
+            ("client_name", params.client_name.clone()),
+            ("license_plate", params.license_plate.to_string()),
+            ("labour_coverage", format_clp(quote.labour_coverage)),
+            ("net_price", format_clp(net_price)),
+            ("iva", format_clp(quote.monthly_cost - net_price)),
+            ("monthly_price", format_clp(quote.monthly_cost)),
+            (
+                "quote_link",
+                client_link(
+                    &format!("/quote/{}", quote.id),
+                    &[("access_token", &access_token)],
+                ),
+            ),
+        ],
+    )
+    .await;
+}
+
+// What plan notifications need to know about a plan and its client.
+pub struct PlanContact {
+    pub plan_id: String,
+    pub client_email: String,
+    pub client_name: Option<String>,
+    pub license_plate: String,
+    pub monthly_price: Option<BigDecimal>,
+    pub payment_link: Option<String>,
+    pub sign_link: Option<String>,
+    pub client_phone: Option<String>,
+    pub notification_channel: Option<String>,
+}
+
+pub async fn get_plan_contact(plan_id: &str) -> Option<PlanContact> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Plan contact");
+
+    sqlx::query_as!(
+        PlanContact,
+        r#"select P.id as plan_id, P.client_email, Q.client_name, P.vehicle as license_plate,
+        Q.monthly_price, P.payment_link, S.sign_link, Q.client_phone, Q.notification_channel
+        from Plan P join Quote Q on P.quote_id = Q.id join Sign S on P.sign = S.id
+        where P.id = ?"#,
+        plan_id
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+}
+
+fn payment_instructions(template: Template, payment_link: Option<&str>) -> String {
+    match (template, payment_link) {
+        (Template::PaymentFailed, Some(link)) => {
+            format!("Puedes intentar pagar nuevamente aquí:\n{}", link)
+        }
+        (_, Some(link)) => format!("Para activar tu plan, completa el pago aquí:\n{}", link),
+        (_, None) => String::from("Nos pondremos en contacto contigo para coordinar el pago."),
+    }
+}
+
+// Queues one of the plan templates for the plan's client. Plans imported
+// from the legacy flow have no client email, so there's no one to notify.
+pub async fn notify_plan(template: Template, plan: &PlanContact) {
+    if plan.client_email.is_empty() {
+        return;
+    }
+
+    let access_token = issue_access_token(Resource::Plan, &plan.plan_id);
+    let plan_link = client_link(
+        &format!("/plan/{}", plan.plan_id),
+        &[("access_token", &access_token)],
+    );
+    let monthly_price = plan
+        .monthly_price
+        .as_ref()
+        .and_then(|price| price.to_f32())
+        .unwrap_or_default();
+
+    notify(
+        template,
+        &plan.client_email,
+        &plan.plan_id,
+        &QuoteContact {
+            channel: plan
+                .notification_channel
+                .as_deref()
+                .and_then(Channel::parse),
+            phone: plan
+                .client_phone
+                .as_deref()
+                .and_then(|phone| PhoneNumber::parse(phone).ok()),
+        },
+        &[
+            ("client_name", plan.client_name.clone().unwrap_or_default()),
+            ("license_plate", plan.license_plate.clone()),
+            ("monthly_price", format_clp(monthly_price)),
+            (
+                "payment_instructions",
+                payment_instructions(template, plan.payment_link.as_deref()),
+            ),
+            (
+                "sign_link",
+                plan.sign_link.clone().unwrap_or_else(|| plan_link.clone()),
+            ),
+            ("plan_link", plan_link),
+        ],
+    )
+    .await;
+}
+
+struct DueNotification {
+    id: String,
+    channel: String,
+    recipient: String,
+    template: String,
+    subject: String,
+    body: String,
+    parameters: Option<String>,
+    attempts: i32,
+}
+
+// Takes the next due notifications, pushing their next attempt forward so
+// other instances running the outbox don't send them too.
+async fn claim_due_notifications() -> Result<Vec<DueNotification>, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("claim due Notifications");
+    let now = Utc::now();
+    let timestamp = now.format("%Y-%m-%dT%H:%M:%S").to_string();
+    let claimed_until = (now + Duration::seconds(CLAIM_SECONDS))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    let mut tx = sqlx::Connection::begin(&mut conn).await?;
+
+    let due = sqlx::query_as!(
+        DueNotification,
+        r#"select id, channel, recipient, template, subject, body, parameters, attempts from Notification
+        where status = ? and next_attempt_at <= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        order by next_attempt_at limit ? for update skip locked"#,
+        PENDING,
+        timestamp,
+        BATCH_SIZE
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    for notification in &due {
+        sqlx::query!(
+            "update Notification set next_attempt_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?",
+            claimed_until,
+            notification.id
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(due)
+}
+
+async fn mark_sent(id: &str) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Notification sent");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    sqlx::query!(
+        r#"update Notification set status = ?, attempts = attempts + 1, last_error = NULL,
+        sent_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+        SENT,
+        timestamp,
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+async fn mark_attempt_failed(notification: &DueNotification, error: &str) {
+    let attempts = notification.attempts + 1;
+    let status = match attempts >= MAX_ATTEMPTS {
+        true => FAILED,
+        false => PENDING,
+    };
+    let next_attempt_at = (Utc::now() + Duration::minutes(1 << (attempts - 1).min(10)))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Notification failed attempt");
+
+    sqlx::query!(
+        r#"update Notification set status = ?, attempts = ?, last_error = ?,
+        next_attempt_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') where id = ?"#,
+        status,
+        attempts,
+        error,
+        next_attempt_at,
+        notification.id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    if status == FAILED {
+        sentry::capture_message(
+            &format!(
+                "Giving up on notification {} after {} attempts: {}",
+                notification.id, attempts, error
+            ),
+            sentry::Level::Error,
+        );
+    }
+}
+
+// Sends the notifications that are due. Run by the job worker on every poll.
+pub async fn send_due_notifications(transport: &EmailTransport) -> Result<(), sqlx::Error> {
+    let due = claim_due_notifications().await?;
+
+    for notification in due {
+        let res = match Channel::parse(&notification.channel) {
+            Some(Channel::Email) => {
+                transport
+                    .send(
+                        &notification.id,
+                        &notification.recipient,
+                        &notification.subject,
+                        &notification.body,
+                    )
+                    .await
+            }
+            Some(channel) => send_message(channel, &notification).await,
+            None => Err(format!("Unknown channel {:?}", notification.channel).into()),
+        };
+
+        match res {
+            Ok(()) => mark_sent(&notification.id).await,
+            Err(err) => mark_attempt_failed(&notification, &err.to_string()).await,
+        }
+    }
+
+    Ok(())
+}
+
+async fn send_message(
+    channel: Channel,
+    notification: &DueNotification,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let messaging = messaging_channel(channel)
+        .ok_or_else(|| format!("The {} channel is not configured", channel.as_str()))?;
+    let to = PhoneNumber::parse(&notification.recipient)?;
+    let parameters: Vec<String> = match &notification.parameters {
+        Some(parameters) => serde_json::from_str(parameters)?,
+        None => vec![],
+    };
+
+    messaging
+        .send(
+            &to,
+            &Message {
+                template: &notification.template,
+                parameters: &parameters,
+                text: &notification.body,
+            },
+        )
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/notifications/opt-out",
+    tag = "notifications",
+    params(AccessTokenQP),
+    request_body = NotificationOptOut,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "The address won't receive notifications anymore", body = String),
+        (status = 403, description = "Missing or invalid access token", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn opt_out(
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(body): ValidatedJson<NotificationOptOut>,
+) -> impl IntoResponse {
+    let email = body.email.to_lowercase();
+
+    if let Err(res) = authorize_client_access(
+        Resource::Notifications,
+        &email,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert NotificationOptOut");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    sqlx::query!(
+        r#"insert ignore into NotificationOptOut(email, creation_timestamp)
+        values (?, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        email,
+        timestamp
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    (
+        StatusCode::OK,
+        Json(String::from("Unsubscribed from notifications")),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    put,
+    path = "/notifications/preferences",
+    tag = "notifications",
+    params(AccessTokenQP),
+    request_body = NotificationPreferenceUpdate,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Updated preference", body = NotificationPreference),
+        (status = 403, description = "Missing or invalid access token", body = String),
+        (status = 422, description = "Invalid request body, or no phone number for WhatsApp or SMS", body = ErrorBody),
+        (status = 500, description = "Failed to read or save the preference", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn put_preference(
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(body): ValidatedJson<NotificationPreferenceUpdate>,
+) -> impl IntoResponse {
+    let email = body.email.to_lowercase();
+
+    if let Err(res) = authorize_client_access(
+        Resource::Notifications,
+        &email,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
+    let phone = match body.phone {
+        Some(phone) => Some(phone),
+        None => match get_preference(&email).await {
+            Ok(preference) => preference.and_then(|(_, phone)| phone),
+            Err(err) => {
+                let err_msg = format!("Error reading notification preference: {}", err);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(err_msg)).into_response();
+            }
+        },
+    };
+    if body.channel != Channel::Email && phone.is_none() {
+        return ValidationRejection::field(
+            "phone",
+            "required",
+            "is required to be notified by WhatsApp or SMS",
+        )
+        .into_response();
+    }
+
+    if let Err(err) = set_preference(&email, phone.as_ref(), Some(body.channel)).await {
+        let err_msg = format!("Error saving notification preference: {}", err);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(err_msg)).into_response();
+    }
+
+    (
+        StatusCode::OK,
+        Json(NotificationPreference {
+            email,
+            phone,
+            channel: body.channel,
+        }),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/notifications",
+    tag = "notifications",
+    params(NotificationsQP),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Notifications queued for the client (by email or their phone), newest first", body = [Notification]),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to see notifications", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_notifications(Query(query): Query<NotificationsQP>) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Notifications by recipient");
+
+    let notifications = sqlx::query_as!(
+        Notification,
+        r#"select id, channel, recipient, template, reference_id, subject, status, attempts, last_error,
+        DATE_FORMAT(creation_timestamp, '%Y-%m-%dT%TZ') as "created_at!",
+        DATE_FORMAT(sent_at, '%Y-%m-%dT%TZ') as sent_at
+        from Notification where recipient = ? or recipient in
+        (select phone from NotificationPreference where email = ?)
+        order by creation_timestamp desc"#,
+        query.recipient,
+        query.recipient
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap();
+
+    (StatusCode::OK, Json(notifications)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_clp, message_parameters, render, Template};
+
+    #[test]
+    fn formats_clp() {
+        assert_eq!(format_clp(0.0), "$0");
+        assert_eq!(format_clp(950.4), "$950");
+        assert_eq!(format_clp(21450.5), "$21.451");
+        assert_eq!(format_clp(1234567.0), "$1.234.567");
+    }
+
+    #[test]
+    fn renders_subject_body_and_footer() {
+        let (subject, body) = render(
+            Template::PaymentReceived,
+            &[
+                ("client_name", String::from("Ana")),
+                ("license_plate", String::from("GKSB78")),
+                ("monthly_price", String::from("$21.451")),
+                ("plan_link", String::from("https://app.test/plan/1")),
+                ("opt_out_link", String::from("https://app.test/opt-out")),
+            ],
+        );
+
+        assert_eq!(subject, "Recibimos tu pago del plan Mechania para GKSB78");
+        assert!(body.starts_with("Hola Ana,"));
+        assert!(body.contains("$21.451"));
+        assert!(body.contains("https://app.test/plan/1"));
+        assert!(body.trim_end().ends_with("https://app.test/opt-out"));
+        assert!(!body.contains("{{"));
+    }
+
+    #[test]
+    fn message_parameters_follow_the_placeholders() {
+        let parameters = message_parameters(
+            Template::PlanCreated,
+            &[
+                ("client_name", String::from("Ana")),
+                ("license_plate", String::from("GKSB78")),
+                ("monthly_price", String::from("$21.451")),
+                (
+                    "payment_instructions",
+                    String::from(
+                        "Para activar tu plan, completa el pago aquí:\nhttps://pay.test/1",
+                    ),
+                ),
+            ],
+        );
+
+        assert_eq!(
+            parameters,
+            [
+                "GKSB78",
+                "$21.451",
+                "Para activar tu plan, completa el pago aquí: https://pay.test/1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{
+    response::{Html, IntoResponse},
+    Json,
+};
+use http::StatusCode;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::api_structs::{
+    CreatePlanBody, CreatePlanBodyV2, CreateQuoteBody, JobRun, ManualVehicleCreation, Notification,
+    NotificationOptOut, NotificationPreference, NotificationPreferenceUpdate, PaymentEvent,
+    PaymentOutcome, Plan, PlanV2, Quote, Vehicle, VehicleChange, VehicleReview, VehicleUpdate,
+    VehicleUpdateResult,
+};
+use crate::appointments::{Appointment, AppointmentBody};
+use crate::auth::{Principal, PrincipalKind, Role};
+use crate::coverage::{Claim, ClaimBody, ClaimLine, ClaimReason, Coverage, RejectionCode};
+use crate::helper_structs::{PaymentMethod, PaymentMethodName, SignMethod, SignMethodName};
+use crate::license_plate::LicensePlate;
+use crate::maintenance::{MaintenanceItem, MaintenanceSchedule};
+use crate::messaging::Channel;
+use crate::phone::PhoneNumber;
+use crate::validation::{ErrorBody, FieldError};
+use crate::vehicle_category::{VehicleCategory, VehicleTypeMapping, VehicleTypeMappingUpdate};
+use crate::vin::DecodedVin;
+use crate::workshops::{Service, Slot, Workshop, WorkshopBody};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Mechania API"),
+    paths(
+        crate::vehicle_handler::get_vehicle_data,
+        crate::vehicle_handler::get_vehicle_types,
+        crate::vehicle_handler::get_vehicle_types_v2,
+        crate::vehicle_category::get_vehicle_type_mappings,
+        crate::vehicle_category::put_vehicle_type_mapping,
+        crate::vehicle_handler::vehicle_manual_creation,
+        crate::vehicle_handler::update_vehicle,
+        crate::vehicle_handler::refresh_vehicle,
+        crate::vehicle_handler::rederive_vehicle,
+        crate::vehicle_handler::get_vehicle_history_handler,
+        crate::vehicle_handler::get_review_queue,
+        crate::vehicle_handler::review_vehicle,
+        crate::vin::decode_vin,
+        crate::catalogue::get_catalogue_makes,
+        crate::catalogue::get_catalogue_models,
+        crate::quote_handlers::get_quote,
+        crate::quote_handlers::create_quote,
+        crate::plan_handlers::create_plan_handler,
+        crate::plan_handlers::get_plan_by_id_handler,
+        crate::plan_handlers::create_plan_handler_v2,
+        crate::plan_handlers::get_plan_by_id_handler_v2,
+        crate::plan_handlers::record_payment_event,
+        crate::workshops::get_workshops,
+        crate::workshops::create_workshop,
+        crate::workshops::update_workshop,
+        crate::workshops::get_workshop_slots,
+        crate::appointments::get_appointments,
+        crate::appointments::book_appointment,
+        crate::appointments::reschedule_appointment,
+        crate::appointments::cancel_appointment,
+        crate::appointments::complete_appointment,
+        crate::maintenance::get_schedule,
+        crate::coverage::submit_claim,
+        crate::coverage::get_plan_claims,
+        crate::notifications::opt_out,
+        crate::notifications::put_preference,
+        crate::notifications::get_notifications,
+        crate::jobs::get_jobs,
+        crate::jobs::retry_job,
+        crate::auth::get_current_principal,
+    ),
+    components(schemas(
+        CreateQuoteBody,
+        CreatePlanBody,
+        ManualVehicleCreation,
+        Vehicle,
+        VehicleUpdate,
+        VehicleChange,
+        VehicleUpdateResult,
+        VehicleReview,
+        VehicleCategory,
+        DecodedVin,
+        VehicleTypeMapping,
+        VehicleTypeMappingUpdate,
+        Quote,
+        Plan,
+        CreatePlanBodyV2,
+        PlanV2,
+        PaymentEvent,
+        PaymentOutcome,
+        Notification,
+        NotificationOptOut,
+        NotificationPreference,
+        NotificationPreferenceUpdate,
+        Channel,
+        PhoneNumber,
+        JobRun,
+        Workshop,
+        WorkshopBody,
+        Service,
+        Slot,
+        Appointment,
+        AppointmentBody,
+        MaintenanceSchedule,
+        MaintenanceItem,
+        Claim,
+        ClaimBody,
+        ClaimLine,
+        ClaimReason,
+        Coverage,
+        RejectionCode,
+        PaymentMethod,
+        SignMethod,
+        PaymentMethodName,
+        SignMethodName,
+        LicensePlate,
+        ErrorBody,
+        FieldError,
+        Principal,
+        PrincipalKind,
+        Role,
+    )),
+    modifiers(&SecuritySchemes, &VersionedPaths),
+    tags(
+        (name = "vehicle", description = "Vehicle lookup and registration"),
+        (name = "catalogue", description = "Known makes and models"),
+        (name = "quote", description = "Plan quotes"),
+        (name = "plan", description = "Maintenance plans"),
+        (name = "workshops", description = "Workshops where plan maintenance is done"),
+        (name = "appointments", description = "Service appointments and maintenance schedule of a plan"),
+        (name = "coverage", description = "Labour claims against a plan's coverage"),
+        (name = "notifications", description = "Client emails"),
+        (name = "jobs", description = "Background jobs"),
+        (name = "auth", description = "Staff and service authentication"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.as_mut().unwrap();
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+// Handlers document their path without a version. Routes shared by every
+// version are listed under both /v1 and /v2; the unprefixed aliases kept for
+// existing clients are left out of the spec.
+struct VersionedPaths;
+
+impl Modify for VersionedPaths {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = std::mem::take(&mut openapi.paths.paths);
+        let v2_paths: Vec<String> = paths
+            .keys()
+            .filter(|path| path.starts_with("/v2/"))
+            .cloned()
+            .collect();
+
+        for (path, item) in paths {
+            if path.starts_with("/v2/") {
+                openapi.paths.paths.insert(path, item);
+                continue;
+            }
+
+            let v2_path = format!("/v2{}", path);
+            if !v2_paths.contains(&v2_path) {
+                let mut v2_item = item.clone();
+                for operation in v2_item.operations.values_mut() {
+                    operation.operation_id = operation
+                        .operation_id
+                        .as_ref()
+                        .map(|id| format!("{}_v2", id));
+                }
+                openapi.paths.paths.insert(v2_path, v2_item);
+            }
+            openapi.paths.paths.insert(format!("/v1{}", path), item);
+        }
+    }
+}
+
+pub fn openapi_json() -> String {
+    ApiDoc::openapi().to_pretty_json().unwrap()
+}
+
+pub async fn get_openapi_spec() -> impl IntoResponse {
+    (StatusCode::OK, Json(ApiDoc::openapi())).into_response()
+}
+
+pub async fn get_docs() -> impl IntoResponse {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Mechania API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+      SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>
+"##,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::openapi_json;
+
+    #[test]
+    fn committed_spec_matches_code() {
+        let committed = include_str!("../openapi.json");
+
+        assert_eq!(
+            committed.trim_end(),
+            openapi_json(),
+            "openapi.json is out of date, regenerate it with `cargo run -- openapi > openapi.json`"
+        );
+    }
+}
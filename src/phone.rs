@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+const COUNTRY_CODE: &str = "56";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhoneNumberError(String);
+
+impl fmt::Display for PhoneNumberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Phone number '{}' is not a Chilean mobile number (e.g. +56 9 1234 5678)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for PhoneNumberError {}
+
+// A Chilean mobile number in E.164 form, e.g. "+56912345678". Only mobile
+// numbers (9 followed by eight digits) are accepted, since they're the ones
+// that can receive WhatsApp and SMS messages.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    // Accepts the usual ways of writing a number: with or without +56 (or
+    // 0056), and with spaces, dashes, dots or parentheses in between.
+    pub fn parse(input: &str) -> Result<PhoneNumber, PhoneNumberError> {
+        let invalid = || PhoneNumberError(input.to_string());
+
+        // A + is only allowed in front, possibly inside parentheses: "(+56) 9...".
+        let trimmed = input.trim().trim_start_matches('(');
+        if trimmed
+            .chars()
+            .any(|c| !(c.is_ascii_digit() || " +-.()".contains(c)))
+            || trimmed.chars().skip(1).any(|c| c == '+')
+        {
+            return Err(invalid());
+        }
+
+        let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+        let international = trimmed.starts_with('+') || digits.starts_with("00");
+        let digits = digits.strip_prefix("00").unwrap_or(&digits);
+
+        let national = match digits.strip_prefix(COUNTRY_CODE) {
+            Some(national) if digits.len() == 11 => national,
+            _ if international => return Err(invalid()),
+            _ => digits,
+        };
+
+        if national.len() != 9 || !national.starts_with('9') {
+            return Err(invalid());
+        }
+
+        Ok(PhoneNumber(format!("+{}{}", COUNTRY_CODE, national)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for PhoneNumber {
+    type Err = PhoneNumberError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PhoneNumber::parse(s)
+    }
+}
+
+impl TryFrom<String> for PhoneNumber {
+    type Error = PhoneNumberError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        PhoneNumber::parse(&value)
+    }
+}
+
+impl From<PhoneNumber> for String {
+    fn from(phone: PhoneNumber) -> Self {
+        phone.0
+    }
+}
+
+impl fmt::Display for PhoneNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'s> ToSchema<'s> for PhoneNumber {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "PhoneNumber",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "Chilean mobile number. +56 and separators are optional on input; \
+                     responses use the E.164 form.",
+                ))
+                .example(Some(serde_json::json!("+56912345678")))
+                .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhoneNumber;
+
+    #[test]
+    fn normalises_to_e164() {
+        for input in [
+            "+56912345678",
+            "+56 9 1234 5678",
+            "56912345678",
+            "0056912345678",
+            "912345678",
+            "9-1234-5678",
+            "(+56) 9 1234.5678",
+        ] {
+            assert_eq!(
+                PhoneNumber::parse(input).unwrap().as_str(),
+                "+56912345678",
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_numbers() {
+        for input in [
+            "",
+            "12345678",
+            // Santiago landline
+            "+56 2 2123 4567",
+            "+54 9 11 1234 5678",
+            "+1 912345678",
+            "9123456789",
+            "9 1234 567a",
+            "56+912345678",
+        ] {
+            assert!(PhoneNumber::parse(input).is_err(), "{:?}", input);
+        }
+    }
+}
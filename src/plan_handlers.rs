@@ -1,33 +1,144 @@
 use axum::{
-    extract::{Host, Path},
+    extract::{Path, Query},
     response::IntoResponse,
     Json,
 };
 use chrono::Utc;
-use http::{StatusCode, Uri, uri::Scheme};
+use http::StatusCode;
 use num_traits::ToPrimitive;
 use reqwest::ClientBuilder;
 use std::env;
 use std::error::Error;
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::{
-    api_structs::{CreatePlanBody, Plan},
+    access_token::{authorize_client_access, issue_access_token, Resource},
+    api_structs::{
+        AccessTokenQP, CreatePlanBody, CreatePlanBodyV2, PaymentEvent, PaymentOutcome, Plan, PlanV2,
+    },
+    auth::Principal,
+    coverage::get_coverage,
     helper_structs::{PaymentMethod, PlanData, QuoteData, SignData, SignMethod},
+    jobs::{enqueue_job, Job},
+    notifications::{client_link, get_plan_contact, notify_plan, PlanContact, Template},
     sql::{establish_connection, get_quote_by_id},
     structs::{ReveniuPlan, ReveniuResponse},
+    telemetry::{db_breadcrumb, http_breadcrumb},
+    validation::ValidatedJson,
+    vehicle_handler::{check_vehicle_exists, review_hold},
 };
 
+// Months a plan runs for, each one billed separately.
+pub const PLAN_CYCLES: u32 = 12;
+
+// Reveniu calls are given up on after this long, so a stuck request fails the
+// plan creation (and is retried by a job) instead of hanging it.
+const REVENIU_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[utoipa::path(
+    post,
+    path = "/plan",
+    tag = "plan",
+    params(
+        AccessTokenQP,
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key get the original response instead of creating another plan"),
+    ),
+    request_body = CreatePlanBody,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 201, description = "Plan created, including the client access token. Card plans come without payment link if the payment provider is down; the client gets it by email once it's created", body = Plan),
+        (status = 403, description = "Missing or invalid quote access token", body = String),
+        (status = 404, description = "Quote not found", body = String),
+        (status = 409, description = "A request with the same Idempotency-Key is still being processed, or the vehicle data is pending review or was rejected", body = String),
+        (status = 410, description = "Quote expired", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+        (status = 424, description = "Payment provider unavailable and the retry could not be queued", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn create_plan_handler(
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(plan): ValidatedJson<CreatePlanBody>,
+) -> impl IntoResponse {
+    match create_plan_from_body(access, principal, plan).await {
+        Ok(plan) => (StatusCode::CREATED, Json(plan)).into_response(),
+        Err(res) => res.into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/plan",
+    tag = "plan",
+    params(
+        AccessTokenQP,
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key get the original response instead of creating another plan"),
+    ),
+    request_body = CreatePlanBodyV2,
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 201, description = "Plan created, including the client access token. Card plans come without payment link if the payment provider is down; the client gets it by email once it's created", body = PlanV2),
+        (status = 403, description = "Missing or invalid quote access token", body = String),
+        (status = 404, description = "Quote not found", body = String),
+        (status = 409, description = "A request with the same Idempotency-Key is still being processed, or the vehicle data is pending review or was rejected", body = String),
+        (status = 410, description = "Quote expired", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+        (status = 424, description = "Payment provider unavailable and the retry could not be queued", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
-pub async fn create_plan_handler(host: Host, plan: Json<CreatePlanBody>) -> impl IntoResponse {
+pub async fn create_plan_handler_v2(
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+    ValidatedJson(plan): ValidatedJson<CreatePlanBodyV2>,
+) -> impl IntoResponse {
+    match create_plan_from_body(access, principal, plan.into()).await {
+        Ok(plan) => (StatusCode::CREATED, Json(PlanV2::from(plan))).into_response(),
+        Err(res) => res.into_response(),
+    }
+}
+
+async fn create_plan_from_body(
+    access: AccessTokenQP,
+    principal: Option<Principal>,
+    plan: CreatePlanBody,
+) -> Result<Plan, (StatusCode, Json<String>)> {
+    // Only the client holding the quote link (or staff) can turn it into a plan.
+    authorize_client_access(
+        Resource::Quote,
+        &plan.quote_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    )?;
+
     // Get quote by quote id
-    let quote: Option<QuoteData> = get_quote_by_id(&plan.quote_id).await;
+    let quote: QuoteData = match get_quote_by_id(&plan.quote_id).await {
+        Some(quote) => quote,
+        None => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(String::from("Specified quote doesn't exist")),
+            ))
+        }
+    };
 
-    if quote.is_none() {
-        //return (StatusCode::NOT_FOUND, "Specified quote doesn't exist".to_string()).into_response();
+    if quote.expired_at.is_some() {
+        return Err((
+            StatusCode::GONE,
+            Json(String::from("Quote expired, please request a new one")),
+        ));
     }
 
-    let quote = quote.unwrap();
+    // Vehicles can go back to review after the quote, e.g. when a refresh
+    // finds VIN issues.
+    let license_plate = quote.license_plate.clone().unwrap_or_default();
+    if let Some(vehicle) = check_vehicle_exists(license_plate).await {
+        if let Some(reason) = review_hold(&vehicle) {
+            return Err((StatusCode::CONFLICT, Json(String::from(reason))));
+        }
+    }
 
     // Create new sign
     let sign = create_sign(&plan.sign_method).await;
@@ -40,15 +151,41 @@ pub async fn create_plan_handler(host: Host, plan: Json<CreatePlanBody>) -> impl
 
     // Create plan
     let plan_res = create_plan(&quote, &sign, &plan.payment_method).await;
+    let access_token = issue_access_token(Resource::Plan, &plan_res.id);
 
     // Create reveniu plan
-    let client_host = format!("https://{}", host.0);
     let reveniu_plan = match plan.payment_method {
-        PaymentMethod::CreditCard | PaymentMethod::DebitCard => Some(
-            create_reveniu_plan(&plan_res.id, &quote, client_host)
+        PaymentMethod::CreditCard | PaymentMethod::DebitCard => {
+            match create_reveniu_plan(&plan_res.id, &access_token, &quote)
                 .await
-                .unwrap(),
-        ),
+                .map_err(|err| err.to_string())
+            {
+                Ok(reveniu_plan) => Some(reveniu_plan),
+                Err(err) => {
+                    sentry::capture_message(
+                        &format!("Reveniu plan creation failed for plan {}: {}", plan_res.id, err),
+                        sentry::Level::Warning,
+                    );
+                    // The plan exists already, so the checkout is retried in the
+                    // background and the client gets the payment link by email.
+                    let retry = Job::CreateReveniuPlan {
+                        plan_id: plan_res.id.clone(),
+                        quote_id: quote.id.clone(),
+                    };
+                    if let Err(err) = enqueue_job(&retry).await {
+                        sentry::capture_message(
+                            &format!("Could not queue Reveniu retry for plan {}: {}", plan_res.id, err),
+                            sentry::Level::Error,
+                        );
+                        return Err((
+                            StatusCode::FAILED_DEPENDENCY,
+                            Json(String::from("Payment provider unavailable, please try again later")),
+                        ));
+                    }
+                    None
+                }
+            }
+        }
         _ => None,
     };
 
@@ -71,18 +208,118 @@ pub async fn create_plan_handler(host: Host, plan: Json<CreatePlanBody>) -> impl
 
     //if plan_res.is_err() {}
 
-    let plan = Plan {
+    // Let the client know, with the payment link if there is one. Card plans
+    // still waiting for their link are notified by the Reveniu retry job.
+    let awaiting_link = matches!(
+        plan.payment_method,
+        PaymentMethod::CreditCard | PaymentMethod::DebitCard
+    ) && payment_link.is_none();
+    if let Some(contact) = get_plan_contact(&plan_res.id).await {
+        if !awaiting_link {
+            notify_plan(Template::PlanCreated, &contact).await;
+        }
+        if matches!(plan.sign_method, SignMethod::Digital) {
+            notify_plan(Template::ContractToSign, &contact).await;
+        }
+    }
+
+    Ok(Plan {
         id: plan_res.id,
         payment_link,
-        payment_method: plan.payment_method.clone(),
-        sign_method: plan.sign_method.clone(),
+        payment_method: plan.payment_method,
+        sign_method: plan.sign_method,
+        access_token: Some(access_token),
+        coverage: None,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/plan/{plan_id}/payment-events",
+    tag = "plan",
+    params(("plan_id" = String, Path, description = "Plan id")),
+    request_body = PaymentEvent,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 202, description = "Client notification queued. A received payment also activates the plan", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to record payments", body = String),
+        (status = 404, description = "Plan not found", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn record_payment_event(
+    Path(plan_id): Path<String>,
+    ValidatedJson(event): ValidatedJson<PaymentEvent>,
+) -> impl IntoResponse {
+    let contact = match get_plan_contact(&plan_id).await {
+        Some(contact) => contact,
+        None => return (StatusCode::NOT_FOUND, Json(String::from("Plan not found"))).into_response(),
     };
 
-    (StatusCode::CREATED, Json(plan))
+    let template = match event.outcome {
+        PaymentOutcome::Received => {
+            // A paid plan can book workshop appointments.
+            set_plan_active(&plan_id).await;
+            Template::PaymentReceived
+        }
+        PaymentOutcome::Failed => Template::PaymentFailed,
+    };
+    notify_plan(template, &contact).await;
+
+    (StatusCode::ACCEPTED, Json(String::from("Client notification queued"))).into_response()
+}
+
+// Creates the Reveniu checkout of a card plan that couldn't get one when it
+// was created, then sends the client the plan created email with the link.
+// Run by the create-reveniu-plan job.
+pub async fn retry_reveniu_plan(plan_id: &str, quote_id: &str) -> Result<(), String> {
+    let contact = get_plan_contact(plan_id)
+        .await
+        .ok_or_else(|| format!("Plan {} not found", plan_id))?;
+    if contact.payment_link.is_some() {
+        return Ok(());
+    }
+
+    let quote = get_quote_by_id(quote_id)
+        .await
+        .ok_or_else(|| format!("Quote {} not found", quote_id))?;
+    let access_token = issue_access_token(Resource::Plan, plan_id);
+
+    let reveniu_plan = match create_reveniu_plan(plan_id, &access_token, &quote).await {
+        Ok(reveniu_plan) => reveniu_plan,
+        Err(err) => return Err(format!("Reveniu plan creation failed: {}", err)),
+    };
+    let payment_link = format!(
+        "{}/checkout-custom-link/{}",
+        env::var("REVENIU_HOST").unwrap(),
+        reveniu_plan.slug
+    );
+    set_plan_reveniu_fields(plan_id, reveniu_plan.id.to_string(), payment_link.clone()).await;
+
+    let contact = PlanContact {
+        payment_link: Some(payment_link),
+        ..contact
+    };
+    notify_plan(Template::PlanCreated, &contact).await;
+
+    Ok(())
+}
+
+async fn set_plan_active(plan_id: &str) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Plan active");
+
+    sqlx::query!("UPDATE Plan SET active=true WHERE id=?", plan_id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
 }
 
 pub async fn set_plan_reveniu_fields(plan_id: &str, reveniu_id: String, payment_link: String) {
     let mut conn = establish_connection().await;
+    db_breadcrumb("update Plan reveniu fields");
 
     let res = sqlx::query!(
         "UPDATE Plan SET reveniu_id=?, payment_link=? WHERE id=?",
@@ -101,6 +338,7 @@ async fn create_plan(
     payment_method: &PaymentMethod,
 ) -> PlanData {
     let mut conn = establish_connection().await;
+    db_breadcrumb("insert Plan");
 
     let id = Uuid::new_v4().to_string();
     let timestamp = Utc::now().to_rfc3339();
@@ -140,16 +378,71 @@ async fn create_plan(
     plan
 }
 
+#[utoipa::path(
+    get,
+    path = "/plan/{plan_id}",
+    tag = "plan",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Plan", body = Plan),
+        (status = 403, description = "Missing or invalid access token", body = String),
+        (status = 404, description = "Plan not found", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
-pub async fn get_plan_by_id_handler(Path(plan_id): Path<String>) -> impl IntoResponse {
-    let plan_res = get_plan_by_id(&plan_id).await;
+pub async fn get_plan_by_id_handler(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    match get_authorized_plan(&plan_id, access, principal).await {
+        Ok(plan) => (StatusCode::OK, Json(plan)).into_response(),
+        Err(res) => res.into_response(),
+    }
+}
 
-    match plan_res {
-        Some(plan) => (StatusCode::OK, Json(plan)).into_response(),
-        None => (StatusCode::NOT_FOUND, Json(String::from("Plan not found"))).into_response(),
+#[utoipa::path(
+    get,
+    path = "/v2/plan/{plan_id}",
+    tag = "plan",
+    params(("plan_id" = String, Path, description = "Plan id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Plan", body = PlanV2),
+        (status = 403, description = "Missing or invalid access token", body = String),
+        (status = 404, description = "Plan not found", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_plan_by_id_handler_v2(
+    Path(plan_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    match get_authorized_plan(&plan_id, access, principal).await {
+        Ok(plan) => (StatusCode::OK, Json(PlanV2::from(plan))).into_response(),
+        Err(res) => res.into_response(),
     }
 }
 
+async fn get_authorized_plan(
+    plan_id: &str,
+    access: AccessTokenQP,
+    principal: Option<Principal>,
+) -> Result<Plan, (StatusCode, Json<String>)> {
+    authorize_client_access(
+        Resource::Plan,
+        plan_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    )?;
+
+    get_plan_by_id(plan_id)
+        .await
+        .ok_or((StatusCode::NOT_FOUND, Json(String::from("Plan not found"))))
+}
+
 async fn get_plan_by_id(plan_id: &str) -> Option<Plan> {
     struct Data {
         pub plan_id: String,
@@ -158,6 +451,7 @@ async fn get_plan_by_id(plan_id: &str) -> Option<Plan> {
         pub payment_method: i16,
     }
     let mut conn = establish_connection().await;
+    db_breadcrumb("select Plan by id");
 
     let res = sqlx::query_as!(
         Data,
@@ -175,12 +469,15 @@ async fn get_plan_by_id(plan_id: &str) -> Option<Plan> {
     let res = res.unwrap();
     let pm = PaymentMethod::from_u8(res.payment_method as u8).unwrap();
     let sm = SignMethod::from_u8(res.sign_method as u8).unwrap();
+    let coverage = get_coverage(&mut conn, &res.plan_id).await;
 
     Some(Plan {
         id: res.plan_id,
         payment_link: res.payment_link,
         payment_method: pm,
         sign_method: sm,
+        access_token: None,
+        coverage,
     })
 }
 
@@ -201,6 +498,7 @@ async fn create_sign(sign_method: &SignMethod) -> SignData {
     };
 
     let mut conn = establish_connection().await;
+    db_breadcrumb("insert Sign");
 
     let res = sqlx::query!(
         r#"insert into Sign(id,sign_link, sign_method, creation_timestamp, verified)
@@ -220,15 +518,15 @@ async fn create_sign(sign_method: &SignMethod) -> SignData {
 
 async fn create_reveniu_plan(
     plan_id: &str,
+    access_token: &str,
     quote: &QuoteData,
-    client_host: String,
 ) -> Result<ReveniuResponse, Box<dyn Error>> {
     let price = quote.monthly_price.clone().unwrap().to_f32().unwrap();
     let client_name = quote.client_name.clone().unwrap();
 
     let body: ReveniuPlan = ReveniuPlan {
         frequency: 3,
-        cicles: 12,
+        cicles: PLAN_CYCLES,
         trial_cicles: 0,
         title: "Plan Mechania - Anual".to_string(),
         description: format!("Plan mensual para {}", client_name),
@@ -240,17 +538,25 @@ async fn create_reveniu_plan(
         address_field: true,
         street_field: true,
         rsocial_field: true,
-        redirect_to: format!("{}/plan/{}/payment-successful", &client_host, plan_id),
-        redirect_to_failure: format!("{}/plan/{}/payment-failed", &client_host, plan_id),
+        redirect_to: client_link(
+            &format!("/plan/{}/payment-successful", plan_id),
+            &[("access_token", access_token)],
+        ),
+        redirect_to_failure: client_link(
+            &format!("/plan/{}/payment-failed", plan_id),
+            &[("access_token", access_token)],
+        ),
     };
 
     let client = ClientBuilder::new()
         .danger_accept_invalid_certs(true)
+        .timeout(REVENIU_TIMEOUT)
         .build()
         .unwrap();
     //https://integration.reveniu.com
+    let url = std::env::var("REVENIU_API_HOST").unwrap() + "/api/v1/plans/";
     let resp = client
-        .post(std::env::var("REVENIU_API_HOST").unwrap() + "/api/v1/plans/")
+        .post(&url)
         .header("content-type", "application/json")
         .header(
             "reveniu-secret-key",
@@ -258,7 +564,9 @@ async fn create_reveniu_plan(
         )
         .json(&body)
         .send()
-        .await?;
+        .await;
+    http_breadcrumb("POST", &url, resp.as_ref().ok().map(|r| r.status().as_u16()));
+    let resp = resp?.error_for_status()?;
 
     println!("resp: {:?}", resp);
 
@@ -1,5 +1,9 @@
-use axum::{extract::Path, response::IntoResponse, Json};
-use chrono::{Datelike, Utc};
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Datelike, Duration, Utc};
 use http::StatusCode;
 use num_traits::ToPrimitive;
 use sqlx::types::BigDecimal;
@@ -7,22 +11,81 @@ use std::error::Error;
 use uuid::Uuid;
 
 use crate::{
-    api_structs::{CreateQuoteBody, Quote, Vehicle},
+    access_token::{authorize_client_access, issue_access_token, Resource},
+    api_structs::{AccessTokenQP, CreateQuoteBody, Quote, Vehicle},
+    auth::Principal,
+    messaging::Channel,
+    notifications::notify_quote_summary,
     sql::establish_connection,
-    vehicle_handler::check_vehicle_exists,
+    telemetry::{capture_notice, db_breadcrumb},
+    validation::{ValidatedJson, ValidationRejection},
+    vehicle_category::{resolve_category, VehicleCategory},
+    vehicle_handler::{check_vehicle_exists, review_hold},
 };
 
+#[utoipa::path(
+    get,
+    path = "/quote/{quote_id}",
+    tag = "quote",
+    params(("quote_id" = String, Path, description = "Quote id"), AccessTokenQP),
+    security((), ("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Quote", body = Quote),
+        (status = 403, description = "Missing or invalid access token", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
-pub async fn get_quote(Path(quote_id): Path<String>) -> impl IntoResponse {
+pub async fn get_quote(
+    Path(quote_id): Path<String>,
+    Query(access): Query<AccessTokenQP>,
+    principal: Option<Principal>,
+) -> impl IntoResponse {
+    if let Err(res) = authorize_client_access(
+        Resource::Quote,
+        &quote_id,
+        access.access_token.as_deref(),
+        principal.as_ref(),
+    ) {
+        return res.into_response();
+    }
+
     let quote = get_quote_by_id(quote_id).await;
 
     return (StatusCode::OK, Json(quote)).into_response();
 }
 
+#[utoipa::path(
+    post,
+    path = "/quote",
+    tag = "quote",
+    request_body = CreateQuoteBody,
+    responses(
+        (status = 201, description = "Quote created, including the client access token", body = Quote),
+        (status = 400, description = "Unknown vehicle", body = String),
+        (status = 409, description = "Vehicle data is pending review, was rejected or has no valid year", body = String),
+        (status = 422, description = "Invalid request body, or no phone number for WhatsApp or SMS", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
-pub async fn create_quote(create_params: Json<CreateQuoteBody>) -> impl IntoResponse {
+pub async fn create_quote(
+    ValidatedJson(create_params): ValidatedJson<CreateQuoteBody>,
+) -> impl IntoResponse {
+    let wants_message = matches!(
+        create_params.notification_channel,
+        Some(Channel::Whatsapp | Channel::Sms)
+    );
+    if wants_message && create_params.phone.is_none() {
+        return ValidationRejection::field(
+            "phone",
+            "required",
+            "is required to be notified by WhatsApp or SMS",
+        )
+        .into_response();
+    }
+
     // Check if vehicle with specified license plate exists
-    let vehicle = check_vehicle_exists(create_params.license_plate.clone()).await;
+    let vehicle = check_vehicle_exists(create_params.license_plate.to_string()).await;
 
     if vehicle.is_none() {
         return (
@@ -33,38 +96,76 @@ pub async fn create_quote(create_params: Json<CreateQuoteBody>) -> impl IntoResp
     }
     let vehicle = vehicle.unwrap();
 
+    if let Some(reason) = review_hold(&vehicle) {
+        return (StatusCode::CONFLICT, Json(reason)).into_response();
+    }
+
+    // Provider and legacy vehicles can lack a usable year; staff fix it
+    // through PATCH /vehicle/{license_plate}.
+    let year = match vehicle.year.as_deref().and_then(|year| year.parse().ok()) {
+        Some(year) => year,
+        None => {
+            return (
+                StatusCode::CONFLICT,
+                Json("Vehicle has no valid registration year, it must be corrected before quoting"),
+            )
+                .into_response();
+        }
+    };
+
+    let category = match &vehicle.vehicle_type {
+        Some(vehicle_type) => resolve_category(vehicle_type).await,
+        None => None,
+    };
+
     // Calculate monthly price for plan
-    let quote: Quote = calculate_price(&create_params.0, &vehicle);
+    let mut quote: Quote = calculate_price(&create_params, &vehicle, year, category.as_ref());
 
     // Save quote to DB
-    let res = create_new_quote(&vehicle, &create_params.0, &quote).await;
+    // The error is turned into a String so it isn't held across the awaits below.
+    let res = create_new_quote(&vehicle, &create_params, &quote)
+        .await
+        .map_err(|err| err.to_string());
 
     if res.is_err() {
         let err_msg = format!("Error creating quote: {}", res.unwrap_err());
         return (StatusCode::INTERNAL_SERVER_ERROR, err_msg).into_response();
     }
 
+    quote.access_token = Some(issue_access_token(Resource::Quote, &quote.id));
+
+    notify_quote_summary(&create_params, &quote).await;
+
     return (StatusCode::CREATED, Json(quote)).into_response();
 }
 
-fn calculate_price(create_params: &CreateQuoteBody, vehicle: &Vehicle) -> Quote {
+fn calculate_price(
+    create_params: &CreateQuoteBody,
+    vehicle: &Vehicle,
+    year: u32,
+    category: Option<&VehicleCategory>,
+) -> Quote {
     let mut price = 15_000.0;
 
     // Check if vehicle is 5 years old or newer
     let current_year = chrono::Utc::now().year() as u32;
     let limit_year = current_year - 5;
 
-    let parsed_year: u32 = vehicle.clone().year.unwrap().parse().unwrap();
+    price *= if year >= limit_year { 1.1 } else { 1.0 };
 
-    price *= if parsed_year >= limit_year { 1.1 } else { 1.0 };
-
-    // Vehicle type multiplier
-    price *= match vehicle.clone().vehicle_type.unwrap().as_str() {
-        "STATION WAGON" => 1.1,
-        "AUTOMOVIL" => 1.0,
-        _ => {
-            let sentry_res = sentry::capture_message(&format!("Received unmanaged vehicle type from external service: license plate: {} vehicle_type: {}",vehicle.clone().license_plate ,vehicle.clone().vehicle_type.unwrap()), sentry::Level::Error);
-            println!("sentry_res: {sentry_res}");
+    // Vehicle category multiplier
+    price *= match category {
+        Some(category) => category.price_multiplier,
+        None => {
+            // Staff can map the type through /vehicle-type/mappings.
+            capture_notice(
+                "Received vehicle type without a category mapping",
+                sentry::Level::Warning,
+                &[
+                    ("license_plate", &vehicle.license_plate),
+                    ("vehicle_type", vehicle.vehicle_type.as_deref().unwrap_or_default()),
+                ],
+            );
             1.0
         }
     };
@@ -83,6 +184,7 @@ fn calculate_price(create_params: &CreateQuoteBody, vehicle: &Vehicle) -> Quote
         id: id.to_string(),
         labour_coverage: coverage,
         monthly_cost: price,
+        access_token: None,
     }
 }
 
@@ -92,13 +194,14 @@ pub async fn create_new_quote(
     quote: &Quote,
 ) -> Result<(), Box<dyn Error>> {
     let mut conn = establish_connection().await;
+    db_breadcrumb("insert Quote");
     let timestamp = Utc::now().to_rfc3339();
     let datetime: Vec<&str> = timestamp.split(".").collect();
     println!("{}", datetime.get(0).unwrap());
 
     let res = sqlx::query!(
-        r#"insert into Quote(id,license_plate, monthly_price, client_email, fuel_consumption, creation_timestamp, client_name, labour_coverage)
-        values (?,?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), ?, ?)"#,
+        r#"insert into Quote(id,license_plate, monthly_price, client_email, fuel_consumption, creation_timestamp, client_name, labour_coverage, client_phone, notification_channel)
+        values (?,?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'), ?, ?, ?, ?)"#,
         quote.id,
         vehicle.license_plate,
         quote.monthly_cost,
@@ -106,7 +209,9 @@ pub async fn create_new_quote(
         quote_params.fuel_consumption,
         datetime.get(0).unwrap(),
         quote_params.client_name,
-        quote.labour_coverage
+        quote.labour_coverage,
+        quote_params.phone.as_ref().map(|phone| phone.as_str()),
+        quote_params.notification_channel.map(|channel| channel.as_str())
         )
     .execute(&mut conn)
     .await?;
@@ -114,8 +219,33 @@ pub async fn create_new_quote(
     Ok(())
 }
 
+// Marks quotes older than `validity_days` that weren't turned into a plan as
+// expired, so they can't be used anymore. Returns how many expired.
+pub async fn expire_quotes(validity_days: i64) -> Result<u64, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update expired Quotes");
+    let now = Utc::now();
+    let timestamp = now.format("%Y-%m-%dT%H:%M:%S").to_string();
+    let created_before = (now - Duration::days(validity_days))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    let res = sqlx::query!(
+        r#"update Quote set expired_at = STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        where expired_at is null and creation_timestamp < STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        and id not in (select quote_id from Plan)"#,
+        timestamp,
+        created_before
+    )
+    .execute(&mut conn)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
 pub async fn get_quote_by_id(quote_id: String) -> Quote {
     let mut conn = establish_connection().await;
+    db_breadcrumb("select Quote by id");
 
     struct QuoteIR {
         pub id: String,
@@ -150,5 +280,6 @@ pub async fn get_quote_by_id(quote_id: String) -> Quote {
             .unwrap()
             .to_f32()
             .unwrap(),
+        access_token: None,
     }
 }
@@ -0,0 +1,282 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use http::{header::RETRY_AFTER, HeaderMap, HeaderValue, Request, StatusCode};
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::redis_store::RedisStore;
+
+// Per-IP buckets are pruned once there are more than this many tracked
+// addresses, dropping the ones that have fully refilled.
+const MAX_TRACKED_IPS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    pub capacity: f64,
+    pub refill_per_second: f64,
+}
+
+impl BucketConfig {
+    // `capacity` requests are allowed in a burst, refilling completely over `period_seconds`.
+    pub fn new(capacity: u32, period_seconds: u32) -> Self {
+        BucketConfig {
+            capacity: capacity as f64,
+            refill_per_second: capacity as f64 / period_seconds as f64,
+        }
+    }
+
+    // Parses a limit in the form `<requests>/<seconds>`, e.g. `20/60`.
+    fn parse(value: &str) -> Option<Self> {
+        let (capacity, period) = value.split_once('/')?;
+        let capacity: u32 = capacity.trim().parse().ok()?;
+        let period: u32 = period.trim().parse().ok()?;
+
+        if capacity == 0 || period == 0 {
+            return None;
+        }
+
+        Some(BucketConfig::new(capacity, period))
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn full(config: &BucketConfig, now: Instant) -> Self {
+        Bucket {
+            tokens: config.capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.refill_per_second).min(config.capacity);
+        self.updated_at = now;
+    }
+
+    // Takes a token, or returns how long until one is available.
+    fn take(&mut self, config: &BucketConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let missing = 1.0 - self.tokens;
+        Err(Duration::from_secs_f64(missing / config.refill_per_second))
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_ip: Option<BucketConfig>,
+    global: Option<BucketConfig>,
+    ip_buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    global_bucket: Mutex<Option<Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_ip: Option<BucketConfig>, global: Option<BucketConfig>) -> Self {
+        RateLimiter {
+            per_ip,
+            global,
+            ip_buckets: Mutex::new(HashMap::new()),
+            global_bucket: Mutex::new(None),
+        }
+    }
+
+    // Builds the limiter for a route from RATE_LIMIT_<ROUTE>_PER_IP and
+    // RATE_LIMIT_<ROUTE>_GLOBAL, falling back to the given defaults. Setting
+    // a variable to `off` disables that limit.
+    pub fn from_env(route: &str, per_ip: BucketConfig, global: BucketConfig) -> Arc<Self> {
+        let read = |scope: &str, default: BucketConfig| match env::var(format!(
+            "RATE_LIMIT_{}_{}",
+            route, scope
+        )) {
+            Ok(value) if value == "off" => None,
+            Ok(value) => Some(BucketConfig::parse(&value).unwrap_or(default)),
+            Err(_) => Some(default),
+        };
+
+        Arc::new(RateLimiter::new(
+            read("PER_IP", per_ip),
+            read("GLOBAL", global),
+        ))
+    }
+
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        if let Some(config) = &self.per_ip {
+            let mut buckets = self.ip_buckets.lock().unwrap();
+
+            if buckets.len() > MAX_TRACKED_IPS {
+                buckets.retain(|_, bucket| {
+                    bucket.refill(config, now);
+                    bucket.tokens < config.capacity
+                });
+            }
+
+            buckets
+                .entry(ip)
+                .or_insert_with(|| Bucket::full(config, now))
+                .take(config, now)?;
+        }
+
+        if let Some(config) = &self.global {
+            self.global_bucket
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| Bucket::full(config, now))
+                .take(config, now)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Each proxy appends the address it received the request from to
+// X-Forwarded-For, so only the entries added by our own proxies can be
+// trusted; anything before them came from the client. TRUSTED_PROXY_HOPS
+// (default 1, the ingress) is how many proxies sit in front of us, and the
+// client address is the entry appended by the outermost one. With no proxies,
+// or fewer entries than expected, the peer address is used.
+fn trusted_proxy_hops() -> usize {
+    static HOPS: OnceLock<usize> = OnceLock::new();
+    *HOPS.get_or_init(|| {
+        env::var("TRUSTED_PROXY_HOPS")
+            .ok()
+            .and_then(|hops| hops.trim().parse().ok())
+            .unwrap_or(1)
+    })
+}
+
+fn client_ip(headers: &HeaderMap, peer: SocketAddr, trusted_hops: usize) -> IpAddr {
+    if trusted_hops == 0 {
+        return peer.ip();
+    }
+
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').nth(trusted_hops - 1))
+        .and_then(|ip| ip.trim().parse().ok())
+        .unwrap_or_else(|| peer.ip())
+}
+
+pub async fn rate_limit<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = client_ip(req.headers(), peer, trusted_proxy_hops());
+
+    if let Err(wait) = limiter.check(ip) {
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        let mut res = (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(String::from("Too many requests, please try again later")),
+        )
+            .into_response();
+        res.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        return res;
+    }
+
+    next.run(req).await
+}
+
+// Counts a paid RegCheck call against today's budget. Returns false when
+// REGCHECK_DAILY_LIMIT calls have already been made today, in which case the
+// lookup must not be sent. Without a limit configured, or if the counter
+// can't be reached, lookups are allowed.
+pub async fn consume_regcheck_budget(redis: &RedisStore) -> bool {
+    let limit: u64 = match env::var("REGCHECK_DAILY_LIMIT")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+    {
+        Some(limit) => limit,
+        None => return true,
+    };
+
+    let key = redis.key(format!("regcheck_calls:{}", Utc::now().format("%Y-%m-%d")));
+
+    let calls: redis::RedisResult<u64> = async {
+        let mut con = redis.connection().await?;
+        let calls: u64 = con.incr(&key, 1).await?;
+        if calls == 1 {
+            con.expire::<_, ()>(&key, 2 * 24 * 60 * 60).await?;
+        }
+        Ok(calls)
+    }
+    .await;
+
+    match calls {
+        Ok(calls) if calls > limit => {
+            if calls == limit + 1 {
+                sentry::capture_message(
+                    &format!("RegCheck daily limit of {} calls reached", limit),
+                    sentry::Level::Warning,
+                );
+            }
+            false
+        }
+        Ok(_) => true,
+        Err(err) => {
+            println!("Failed to count RegCheck call: {}", err);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn client_ip_ignores_entries_sent_by_the_client() {
+        let peer: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+        let headers = forwarded("1.1.1.1, 203.0.113.7");
+
+        assert_eq!(
+            client_ip(&headers, peer, 1),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(&headers, peer, 2),
+            "1.1.1.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn client_ip_falls_back_to_the_peer() {
+        let peer: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+
+        assert_eq!(client_ip(&HeaderMap::new(), peer, 1), peer.ip());
+        assert_eq!(client_ip(&forwarded("203.0.113.7"), peer, 0), peer.ip());
+        assert_eq!(client_ip(&forwarded("203.0.113.7"), peer, 2), peer.ip());
+        assert_eq!(client_ip(&forwarded("garbage"), peer, 1), peer.ip());
+    }
+}
@@ -0,0 +1,103 @@
+use redis::aio::ConnectionManager;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::env;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// How long to wait for Redis when connecting, and how long to wait after a
+// failed attempt before trying again.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Connection {
+    manager: Option<ConnectionManager>,
+    failed_at: Option<Instant>,
+}
+
+// Shared async connection to Redis, handed to handlers through the app state.
+// Redis only backs caches, limits and idempotency keys, so the API starts
+// without it: the connection is made the first time it's needed, and until
+// it succeeds callers get an error and carry on without Redis. Once made, the
+// connection is multiplexed, so clones are cheap, and it reconnects by itself
+// after Redis restarts (the command that hit the dropped connection fails,
+// the next ones go through).
+#[derive(Clone)]
+pub struct RedisStore {
+    client: Option<redis::Client>,
+    connection: Arc<Mutex<Connection>>,
+    prefix: Arc<str>,
+}
+
+impl RedisStore {
+    // Reads REDIS_URL. Every key is prefixed with REDIS_KEY_PREFIX (e.g.
+    // `mr-42`), so deployments sharing a Redis don't read or overwrite each
+    // other's entries. Without a prefix keys are used as they are.
+    pub fn from_env() -> Self {
+        let client = match redis::Client::open(env::var("REDIS_URL").unwrap_or_default().as_str()) {
+            Ok(client) => Some(client),
+            Err(err) => {
+                println!("Redis is disabled, REDIS_URL is not usable: {}", err);
+                None
+            }
+        };
+        let prefix = match env::var("REDIS_KEY_PREFIX") {
+            Ok(prefix) if !prefix.is_empty() => format!("{}:", prefix.trim_end_matches(':')),
+            _ => String::new(),
+        };
+
+        RedisStore {
+            client,
+            connection: Arc::new(Mutex::new(Connection::default())),
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn key(&self, key: impl Display) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    pub async fn connection(&self) -> RedisResult<ConnectionManager> {
+        let client = self.client.as_ref().ok_or_else(|| {
+            RedisError::from((ErrorKind::InvalidClientConfig, "Redis is not configured"))
+        })?;
+
+        let mut connection = self.connection.lock().await;
+        if let Some(manager) = &connection.manager {
+            return Ok(manager.clone());
+        }
+        if connection
+            .failed_at
+            .is_some_and(|failed_at| failed_at.elapsed() < RETRY_INTERVAL)
+        {
+            return Err(RedisError::from((
+                ErrorKind::IoError,
+                "Redis is unavailable",
+            )));
+        }
+
+        let manager = tokio::time::timeout(CONNECT_TIMEOUT, ConnectionManager::new(client.clone()))
+            .await
+            .unwrap_or_else(|_| {
+                Err(RedisError::from((
+                    ErrorKind::IoError,
+                    "Timed out connecting to Redis",
+                )))
+            });
+
+        match manager {
+            Ok(manager) => {
+                connection.manager = Some(manager.clone());
+                connection.failed_at = None;
+                Ok(manager)
+            }
+            Err(err) => {
+                println!("Failed to connect to Redis: {}", err);
+                connection.failed_at = Some(Instant::now());
+                Err(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+use std::fmt;
+
+use crate::helper_structs::VehicleDescription;
+
+// Fault and message texts RegCheck uses when it has no data for a plate.
+const NOT_FOUND_MESSAGES: &[&str] = &["no vehicle", "not found", "no data", "no results"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegCheckError {
+    // The response isn't well-formed XML (or isn't XML at all).
+    Malformed(String),
+    // RegCheck answered with a SOAP fault other than "not found".
+    SoapFault { code: String, message: String },
+    // RegCheck has no data for the license plate.
+    NotFound,
+    // Neither vehicleJson nor vehicleData is in the response.
+    MissingVehicle,
+    // The vehicle is there but can't be decoded into a VehicleDescription.
+    InvalidVehicle(String),
+    // The request failed or the response couldn't be read.
+    Unreachable(String),
+}
+
+impl fmt::Display for RegCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegCheckError::Malformed(err) => {
+                write!(f, "RegCheck response is not valid XML: {}", err)
+            }
+            RegCheckError::SoapFault { code, message } => {
+                write!(f, "RegCheck returned a fault ({}): {}", code, message)
+            }
+            RegCheckError::NotFound => write!(f, "RegCheck has no data for this license plate"),
+            RegCheckError::MissingVehicle => write!(f, "RegCheck response has no vehicle data"),
+            RegCheckError::InvalidVehicle(err) => {
+                write!(f, "RegCheck vehicle data is invalid: {}", err)
+            }
+            RegCheckError::Unreachable(err) => write!(f, "RegCheck could not be reached: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RegCheckError {}
+
+// An XML element with its (concatenated) text and child elements. RegCheck
+// responses are a few KB, so reading them whole is fine.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    // First element with the given local name, depth first.
+    fn find(&self, name: &str) -> Option<&Element> {
+        self.children
+            .iter()
+            .find_map(|child| match child.name == name {
+                true => Some(child),
+                false => child.find(name),
+            })
+    }
+
+    fn child_text(&self, name: &str) -> Option<&str> {
+        self.find(name).map(|child| child.text.trim())
+    }
+
+    // Elements with children become objects keyed by child name and leaves
+    // become strings, matching the shape of the vehicleJson document.
+    fn to_json(&self) -> Value {
+        if self.children.is_empty() {
+            return Value::String(self.text.trim().to_string());
+        }
+
+        let fields: Map<String, Value> = self
+            .children
+            .iter()
+            .map(|child| (child.name.clone(), child.to_json()))
+            .collect();
+        Value::Object(fields)
+    }
+}
+
+fn malformed(err: impl fmt::Display) -> RegCheckError {
+    RegCheckError::Malformed(err.to_string())
+}
+
+fn local_name(name: &[u8]) -> Result<String, RegCheckError> {
+    String::from_utf8(name.to_vec()).map_err(malformed)
+}
+
+// Reads the whole document into a tree under an unnamed root. Text may come
+// in several events (entities, CDATA sections), so it's appended rather than
+// taken from a single read.
+fn read_document(xml: &str) -> Result<Element, RegCheckError> {
+    let mut reader = Reader::from_str(xml);
+    reader.expand_empty_elements(true);
+
+    let mut stack = vec![Element::default()];
+
+    loop {
+        match reader.read_event().map_err(malformed)? {
+            Event::Start(e) => stack.push(Element {
+                name: local_name(e.local_name().as_ref())?,
+                ..Default::default()
+            }),
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Err(malformed("unexpected closing tag")),
+                }
+            }
+            Event::Text(e) => {
+                let text = e.unescape().map_err(malformed)?;
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::CData(e) => {
+                let text = String::from_utf8(e.into_inner().to_vec()).map_err(malformed)?;
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    let root = stack.pop().unwrap();
+    if !stack.is_empty() {
+        return Err(malformed("unexpected end of document"));
+    }
+    if root.children.is_empty() {
+        return Err(malformed("no root element"));
+    }
+
+    Ok(root)
+}
+
+fn is_not_found(message: &str) -> bool {
+    let message = message.to_lowercase();
+    NOT_FOUND_MESSAGES.iter().any(|m| message.contains(m))
+}
+
+fn from_json(json: &str) -> Result<VehicleDescription, RegCheckError> {
+    serde_json::from_str(json).map_err(|err| RegCheckError::InvalidVehicle(err.to_string()))
+}
+
+fn from_vehicle_data(data: &Element) -> Result<VehicleDescription, RegCheckError> {
+    serde_json::from_value(data.to_json())
+        .map_err(|err| RegCheckError::InvalidVehicle(err.to_string()))
+}
+
+// Parses a CheckChile response. The vehicle comes as JSON in vehicleJson
+// and as XML in vehicleData; the JSON form is preferred and vehicleData is
+// used when vehicleJson is missing or can't be decoded.
+pub fn parse(xml: &str) -> Result<VehicleDescription, RegCheckError> {
+    let document = read_document(xml)?;
+
+    if let Some(fault) = document.find("Fault") {
+        let code = fault.child_text("faultcode").unwrap_or_default();
+        let message = fault.child_text("faultstring").unwrap_or_default();
+        if is_not_found(message) {
+            return Err(RegCheckError::NotFound);
+        }
+        return Err(RegCheckError::SoapFault {
+            code: code.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    let json = document.find("vehicleJson");
+    let data = document.find("vehicleData");
+
+    if json.is_none() && data.is_none() {
+        // Plain-text answers like <string>No data found</string>.
+        let text = document.children[0].text.trim();
+        if is_not_found(text) {
+            return Err(RegCheckError::NotFound);
+        }
+        return Err(RegCheckError::MissingVehicle);
+    }
+
+    let json = json
+        .map(|json| json.text.trim())
+        .filter(|json| !json.is_empty() && *json != "null" && *json != "{}");
+    let data = data.filter(|data| !data.children.is_empty());
+
+    match (json, data) {
+        (None, None) => Err(RegCheckError::NotFound),
+        (Some(json), None) => from_json(json),
+        (None, Some(data)) => from_vehicle_data(data),
+        (Some(json), Some(data)) => {
+            from_json(json).or_else(|err| from_vehicle_data(data).map_err(|_| err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, RegCheckError};
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_str!(concat!("../tests/fixtures/regcheck/", $name, ".xml"))
+        };
+    }
+
+    #[test]
+    fn parses_vehicle_json() {
+        let vehicle = parse(fixture!("vehicle_json")).unwrap();
+
+        assert_eq!(vehicle.make_description.current_text_value, "TOYOTA");
+        assert_eq!(vehicle.model_description.current_text_value, "YARIS");
+        assert_eq!(vehicle.registration_year, "2015");
+        assert_eq!(vehicle.vin, "MR0HA3CD700701234");
+        assert_eq!(
+            vehicle.image_url,
+            "http://cl.matriculaapi.com/image.aspx/@VE9ZT1RBIFlBUklT"
+        );
+    }
+
+    #[test]
+    fn unescapes_vehicle_json() {
+        let vehicle = parse(fixture!("vehicle_json_escaped")).unwrap();
+
+        assert_eq!(vehicle.description, "MERCEDES-BENZ A 200 \"Style\" & Co");
+        assert_eq!(vehicle.make_description.current_text_value, "MERCEDES-BENZ");
+    }
+
+    #[test]
+    fn parses_vehicle_json_in_cdata() {
+        let vehicle = parse(fixture!("vehicle_json_cdata")).unwrap();
+
+        assert_eq!(vehicle.make_description.current_text_value, "KIA");
+        assert_eq!(vehicle.fuel, "GASOLINA");
+    }
+
+    #[test]
+    fn parses_vehicle_data_without_json() {
+        let vehicle = parse(fixture!("vehicle_data_only")).unwrap();
+
+        assert_eq!(vehicle.car_make.current_text_value, "HYUNDAI");
+        assert_eq!(vehicle.car_model.current_text_value, "ACCENT");
+        assert_eq!(vehicle.registration_year, "2012");
+        assert_eq!(vehicle.vehicle_type, "AUTOMOVIL");
+    }
+
+    #[test]
+    fn falls_back_to_vehicle_data_on_invalid_json() {
+        let vehicle = parse(fixture!("invalid_json_with_vehicle_data")).unwrap();
+
+        assert_eq!(vehicle.make_description.current_text_value, "CHEVROLET");
+    }
+
+    #[test]
+    fn empty_vehicle_json_is_not_found() {
+        assert_eq!(
+            parse(fixture!("empty_vehicle_json")).unwrap_err(),
+            RegCheckError::NotFound
+        );
+    }
+
+    #[test]
+    fn not_found_fault_is_not_found() {
+        assert_eq!(
+            parse(fixture!("soap_fault_not_found")).unwrap_err(),
+            RegCheckError::NotFound
+        );
+    }
+
+    #[test]
+    fn not_found_message_is_not_found() {
+        assert_eq!(
+            parse(fixture!("string_not_found")).unwrap_err(),
+            RegCheckError::NotFound
+        );
+    }
+
+    #[test]
+    fn soap_fault() {
+        assert_eq!(
+            parse(fixture!("soap_fault")).unwrap_err(),
+            RegCheckError::SoapFault {
+                code: String::from("soap:Server"),
+                message: String::from("Out of credits"),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_vehicle_json() {
+        assert!(matches!(
+            parse(fixture!("invalid_vehicle_json")).unwrap_err(),
+            RegCheckError::InvalidVehicle(_)
+        ));
+    }
+
+    #[test]
+    fn missing_vehicle() {
+        assert_eq!(
+            parse(fixture!("missing_vehicle")).unwrap_err(),
+            RegCheckError::MissingVehicle
+        );
+    }
+
+    #[test]
+    fn malformed_responses() {
+        for xml in [
+            fixture!("malformed_truncated"),
+            fixture!("malformed_mismatched_tags"),
+            fixture!("malformed_html"),
+            "",
+        ] {
+            assert!(
+                matches!(parse(xml).unwrap_err(), RegCheckError::Malformed(_)),
+                "{:?} should be malformed",
+                xml
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::helper_structs::QuoteData;
+use crate::telemetry::db_breadcrumb;
 use sqlx::Connection;
 use sqlx::MySqlConnection;
 use std::env;
@@ -8,10 +9,17 @@ pub async fn establish_connection() -> MySqlConnection {
     conn.unwrap()
 }
 
+// Applies the pending migrations in ./migrations, embedded at build time.
+pub async fn run_migrations() {
+    let mut conn = establish_connection().await;
+    sqlx::migrate!().run(&mut conn).await.unwrap();
+}
+
 pub async fn get_quote_by_id(quote_id: &str) -> Option<QuoteData> {
     let mut conn = establish_connection().await;
+    db_breadcrumb("select Quote by id");
 
-    let res: Option<QuoteData> = sqlx::query_as!(QuoteData, "select id,client_name, license_plate, monthly_price, client_email, fuel_consumption, DATE_FORMAT(creation_timestamp, '%Y-%m-%dT%TZ') as creation_timestamp  from Quote where id=?", quote_id)
+    let res: Option<QuoteData> = sqlx::query_as!(QuoteData, "select id,client_name, license_plate, monthly_price, client_email, fuel_consumption, DATE_FORMAT(creation_timestamp, '%Y-%m-%dT%TZ') as creation_timestamp, DATE_FORMAT(expired_at, '%Y-%m-%dT%TZ') as expired_at  from Quote where id=?", quote_id)
         .fetch_optional(&mut conn)
         .await.unwrap();
 
@@ -0,0 +1,66 @@
+use sentry::protocol::{Breadcrumb, Map, Value};
+use sentry::{ClientInitGuard, Level};
+use std::env;
+
+// Initialises sentry from the environment. Reporting is disabled when
+// SENTRY_DSN is not set, so local runs don't need any sentry configuration.
+pub fn init_sentry() -> Option<ClientInitGuard> {
+    let dsn = env::var("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty())?;
+
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            environment: env::var("SENTRY_ENVIRONMENT").ok().map(Into::into),
+            release: sentry::release_name!(),
+            traces_sample_rate: env::var("SENTRY_TRACES_SAMPLE_RATE")
+                .ok()
+                .and_then(|rate| rate.parse().ok())
+                .unwrap_or(0.0),
+            ..Default::default()
+        },
+    ));
+
+    Some(guard)
+}
+
+// Records a database operation so it shows up in the trail of any event
+// captured later in the same request.
+pub fn db_breadcrumb(operation: &str) {
+    sentry::add_breadcrumb(Breadcrumb {
+        ty: "query".into(),
+        category: Some("db.sql".into()),
+        message: Some(operation.to_string()),
+        ..Default::default()
+    });
+}
+
+// Records an outbound HTTP call to an external service.
+pub fn http_breadcrumb(method: &str, url: &str, status: Option<u16>) {
+    let mut data = Map::new();
+    data.insert("method".into(), Value::from(method));
+    data.insert("url".into(), Value::from(url));
+    if let Some(status) = status {
+        data.insert("status_code".into(), Value::from(status));
+    }
+
+    sentry::add_breadcrumb(Breadcrumb {
+        ty: "http".into(),
+        category: Some("http".into()),
+        data,
+        ..Default::default()
+    });
+}
+
+// Sends a business notice (not an error) to sentry with the given tags, so
+// they can be filtered and alerted on separately from failures.
+pub fn capture_notice(message: &str, level: Level, tags: &[(&str, &str)]) {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("kind", "notice");
+            for (key, value) in tags {
+                scope.set_tag(key, value);
+            }
+        },
+        || sentry::capture_message(message, level),
+    );
+}
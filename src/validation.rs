@@ -0,0 +1,186 @@
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest},
+    http::Request,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Datelike;
+use http::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    // None when the error concerns the body as a whole.
+    pub field: Option<String>,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub message: String,
+    pub errors: Vec<FieldError>,
+}
+
+impl ErrorBody {
+    fn from_validation_errors(errors: ValidationErrors) -> Self {
+        let mut field_errors: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: Some(field.to_string()),
+                    code: error.code.to_string(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| format!("{} is invalid", field)),
+                })
+            })
+            .collect();
+        field_errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ErrorBody {
+            message: String::from("Request body is invalid"),
+            errors: field_errors,
+        }
+    }
+
+    fn from_json_rejection(rejection: &JsonRejection) -> Self {
+        let code = match rejection {
+            JsonRejection::JsonDataError(_) => "invalid_data",
+            JsonRejection::JsonSyntaxError(_) => "invalid_json",
+            JsonRejection::MissingJsonContentType(_) => "missing_content_type",
+            _ => "invalid_body",
+        };
+
+        ErrorBody {
+            message: String::from("Request body is invalid"),
+            errors: vec![FieldError {
+                field: None,
+                code: code.to_string(),
+                message: rejection.body_text(),
+            }],
+        }
+    }
+}
+
+pub struct ValidationRejection {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+impl ValidationRejection {
+    // For checks that can't be declared on the struct, e.g. ones needing the DB.
+    pub fn field(field: &str, code: &str, message: &str) -> Self {
+        ValidationRejection {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            body: ErrorBody {
+                message: String::from("Request body is invalid"),
+                errors: vec![FieldError {
+                    field: Some(field.to_string()),
+                    code: code.to_string(),
+                    message: message.to_string(),
+                }],
+            },
+        }
+    }
+}
+
+impl IntoResponse for ValidationRejection {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+// Drop-in replacement for `Json` that also runs the body's declared
+// validations, answering 422 with the list of failing fields.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    B: Send + 'static,
+    Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+{
+    type Rejection = ValidationRejection;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| ValidationRejection {
+                status: rejection.status(),
+                body: ErrorBody::from_json_rejection(&rejection),
+            })?;
+
+        value.validate().map_err(|errors| ValidationRejection {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            body: ErrorBody::from_validation_errors(errors),
+        })?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+pub fn validate_year(year: &str) -> Result<(), ValidationError> {
+    let max_year = chrono::Utc::now().year() + 1;
+
+    match year.parse::<i32>() {
+        Ok(parsed) if year.len() == 4 && (1900..=max_year).contains(&parsed) => Ok(()),
+        _ => {
+            let mut error = ValidationError::new("year_range");
+            error.message = Some(format!("must be a year between 1900 and {}", max_year).into());
+            Err(error)
+        }
+    }
+}
+
+pub fn validate_uuid(id: &str) -> Result<(), ValidationError> {
+    match uuid::Uuid::parse_str(id) {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let mut error = ValidationError::new("uuid");
+            error.message = Some("must be a valid id".into());
+            Err(error)
+        }
+    }
+}
+
+pub fn validate_date(date: &str) -> Result<(), ValidationError> {
+    match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let mut error = ValidationError::new("date");
+            error.message = Some("must be a date formatted as YYYY-MM-DD".into());
+            Err(error)
+        }
+    }
+}
+
+pub fn validate_time(time: &str) -> Result<(), ValidationError> {
+    match chrono::NaiveTime::parse_from_str(time, "%H:%M") {
+        Ok(_) if time.len() == 5 => Ok(()),
+        _ => {
+            let mut error = ValidationError::new("time");
+            error.message = Some("must be a time formatted as HH:MM".into());
+            Err(error)
+        }
+    }
+}
+
+pub fn validate_vin(vin: &str) -> Result<(), ValidationError> {
+    match crate::vin::decode(vin) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let mut error = ValidationError::new("vin");
+            error.message = Some(err.to_string().into());
+            Err(error)
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use http::StatusCode;
+use redis::{AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::helper_structs::VehicleDescription;
+use crate::rate_limit::consume_regcheck_budget;
+use crate::redis_store::RedisStore;
+use crate::regcheck::RegCheckError;
+use crate::vehicle_handler::get_vehicle_data_api;
+
+// Found vehicles are kept for a week, plates RegCheck doesn't know for ten
+// minutes.
+const DEFAULT_TTL_SECONDS: usize = 7 * 24 * 60 * 60;
+const DEFAULT_NEGATIVE_TTL_SECONDS: usize = 10 * 60;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+enum CachedLookup {
+    Found { vehicle: Box<VehicleDescription> },
+    Failed { status: u16, message: String },
+}
+
+// One lock per license plate currently being looked up, so concurrent
+// requests for the same plate wait for the first one instead of calling
+// RegCheck again.
+static IN_FLIGHT: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+fn cache_key(redis: &RedisStore, license_plate: &str) -> String {
+    redis.key(format!("vehicle_data:{}", license_plate))
+}
+
+fn ttl_from_env(var: &str, default: usize) -> usize {
+    env::var(var)
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .unwrap_or(default)
+}
+
+async fn read_cache(redis: &RedisStore, license_plate: &str) -> Option<CachedLookup> {
+    let cached: Option<String> = redis
+        .connection()
+        .await
+        .ok()?
+        .get(cache_key(redis, license_plate))
+        .await
+        .ok()?;
+
+    serde_json::from_str(&cached?).ok()
+}
+
+async fn write_cache(redis: &RedisStore, license_plate: &str, lookup: &CachedLookup) {
+    let ttl = match lookup {
+        CachedLookup::Found { .. } => {
+            ttl_from_env("VEHICLE_CACHE_TTL_SECONDS", DEFAULT_TTL_SECONDS)
+        }
+        CachedLookup::Failed { .. } => ttl_from_env(
+            "VEHICLE_CACHE_NEGATIVE_TTL_SECONDS",
+            DEFAULT_NEGATIVE_TTL_SECONDS,
+        ),
+    };
+
+    let res: RedisResult<()> = async {
+        redis
+            .connection()
+            .await?
+            .set_ex(
+                cache_key(redis, license_plate),
+                serde_json::to_string(lookup).unwrap(),
+                ttl,
+            )
+            .await
+    }
+    .await;
+
+    if let Err(err) = res {
+        println!(
+            "Failed to cache vehicle data for {}: {}",
+            license_plate, err
+        );
+    }
+}
+
+// Removes any cached provider result for the license plate, so the next
+// lookup goes back to RegCheck.
+pub async fn invalidate_vehicle_cache(redis: &RedisStore, license_plate: &str) {
+    let res: RedisResult<()> = async {
+        redis
+            .connection()
+            .await?
+            .del(cache_key(redis, license_plate))
+            .await
+    }
+    .await;
+
+    if let Err(err) = res {
+        println!(
+            "Failed to invalidate cached vehicle data for {}: {}",
+            license_plate, err
+        );
+    }
+}
+
+fn from_cached(lookup: CachedLookup) -> Result<VehicleDescription, (StatusCode, String)> {
+    match lookup {
+        CachedLookup::Found { vehicle } => Ok(*vehicle),
+        CachedLookup::Failed { status, message } => Err((
+            StatusCode::from_u16(status).unwrap_or(StatusCode::FAILED_DEPENDENCY),
+            message,
+        )),
+    }
+}
+
+// What to cache for a provider lookup. Only found vehicles and plates
+// RegCheck doesn't know are cached; outages, timeouts and other faults are
+// not, so the next request tries again once RegCheck recovers.
+fn to_cached(res: &Result<VehicleDescription, (StatusCode, String)>) -> Option<CachedLookup> {
+    match res {
+        Ok(vehicle) => Some(CachedLookup::Found {
+            vehicle: Box::new(vehicle.clone()),
+        }),
+        Err((status, message)) if *message == RegCheckError::NotFound.to_string() => {
+            Some(CachedLookup::Failed {
+                status: status.as_u16(),
+                message: message.clone(),
+            })
+        }
+        Err(_) => None,
+    }
+}
+
+// Read-through cache in front of RegCheck. Found vehicles and unknown plates
+// are cached, and concurrent lookups of the same plate only reach the
+// provider once. Redis being unavailable only disables caching.
+pub async fn get_vehicle_data_cached(
+    redis: &RedisStore,
+    license_plate: String,
+) -> Result<VehicleDescription, (StatusCode, String)> {
+    if let Some(cached) = read_cache(redis, &license_plate).await {
+        return from_cached(cached);
+    }
+
+    let plate_lock = IN_FLIGHT
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(license_plate.clone())
+        .or_default()
+        .clone();
+    let _guard = plate_lock.lock().await;
+
+    // Another request may have finished the lookup while we were waiting.
+    let res = match read_cache(redis, &license_plate).await {
+        Some(cached) => from_cached(cached),
+        None if !consume_regcheck_budget(redis).await => Err((
+            StatusCode::FAILED_DEPENDENCY,
+            String::from("Vehicle lookup unavailable, manual entry required"),
+        )),
+        None => {
+            let res = get_vehicle_data_api(license_plate.clone()).await;
+            if let Some(lookup) = to_cached(&res) {
+                write_cache(redis, &license_plate, &lookup).await;
+            }
+            res
+        }
+    };
+
+    let mut in_flight = IN_FLIGHT.get().unwrap().lock().unwrap();
+    // Only the map and this guard hold the lock once the last waiter is done.
+    if Arc::strong_count(&plate_lock) <= 2 {
+        in_flight.remove(&license_plate);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_cached, CachedLookup};
+    use crate::regcheck::{self, RegCheckError};
+    use http::StatusCode;
+
+    #[test]
+    fn caches_found_vehicles_and_unknown_plates() {
+        let vehicle = regcheck::parse(include_str!("../tests/fixtures/regcheck/vehicle_json.xml"));
+        assert!(matches!(
+            to_cached(&Ok(vehicle.unwrap())),
+            Some(CachedLookup::Found { .. })
+        ));
+
+        let not_found = Err((
+            StatusCode::FAILED_DEPENDENCY,
+            RegCheckError::NotFound.to_string(),
+        ));
+        assert!(matches!(
+            to_cached(&not_found),
+            Some(CachedLookup::Failed { status: 424, .. })
+        ));
+    }
+
+    #[test]
+    fn does_not_cache_transient_failures() {
+        let failures = [
+            RegCheckError::Unreachable(String::from("operation timed out")).to_string(),
+            RegCheckError::SoapFault {
+                code: String::from("soap:Server"),
+                message: String::from("Out of credits"),
+            }
+            .to_string(),
+            String::from(
+                "RegCheck API failed for license plate BBCL12: 503 Service Unavailable - ",
+            ),
+            String::from("Vehicle lookup unavailable, manual entry required"),
+        ];
+
+        for message in failures {
+            assert!(
+                to_cached(&Err((StatusCode::FAILED_DEPENDENCY, message.clone()))).is_none(),
+                "{}",
+                message
+            );
+        }
+    }
+}
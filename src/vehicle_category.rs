@@ -0,0 +1,187 @@
+use axum::{extract::Path, response::IntoResponse, Json};
+use chrono::Utc;
+use http::StatusCode;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use sqlx::types::BigDecimal;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::auth::Principal;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::validation::{ValidatedJson, ValidationRejection};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VehicleCategory {
+    pub code: String,
+    pub name: String,
+    #[serde(skip)]
+    pub price_multiplier: f32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VehicleTypeMapping {
+    pub provider_type: String,
+    pub category: String,
+    pub updated_by: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct VehicleTypeMappingUpdate {
+    #[validate(length(min = 1, max = 32, message = "must be between 1 and 32 characters"))]
+    pub category: String,
+}
+
+struct CategoryRow {
+    code: String,
+    name: String,
+    price_multiplier: BigDecimal,
+}
+
+impl From<CategoryRow> for VehicleCategory {
+    fn from(row: CategoryRow) -> Self {
+        VehicleCategory {
+            code: row.code,
+            name: row.name,
+            price_multiplier: row.price_multiplier.to_f32().unwrap(),
+        }
+    }
+}
+
+pub async fn get_categories() -> Vec<VehicleCategory> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select VehicleCategory");
+
+    sqlx::query_as!(
+        CategoryRow,
+        "select code, name, price_multiplier from VehicleCategory order by name"
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(VehicleCategory::from)
+    .collect()
+}
+
+// Resolves a vehicle_type to its category: either a provider string with a
+// mapping, or a category code (as chosen on manual registration).
+pub async fn resolve_category(vehicle_type: &str) -> Option<VehicleCategory> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select VehicleCategory by vehicle_type");
+
+    sqlx::query_as!(
+        CategoryRow,
+        "select code, name, price_multiplier from VehicleCategory where code = (select category from VehicleTypeMapping where provider_type = ?) or code = ? limit 1",
+        vehicle_type,
+        vehicle_type
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+    .map(VehicleCategory::from)
+}
+
+// 422 for vehicle types no category can be resolved for.
+pub async fn check_vehicle_type(vehicle_type: &str) -> Result<(), ValidationRejection> {
+    match resolve_category(vehicle_type).await {
+        Some(_) => Ok(()),
+        None => Err(ValidationRejection::field(
+            "vehicle_type",
+            "vehicle_type",
+            "must be a vehicle category or a known vehicle type",
+        )),
+    }
+}
+
+async fn get_mappings() -> Vec<VehicleTypeMapping> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select VehicleTypeMapping");
+
+    sqlx::query_as!(
+        VehicleTypeMapping,
+        r#"select provider_type, category, updated_by, DATE_FORMAT(updated_at, '%Y-%m-%dT%TZ') as "updated_at!" from VehicleTypeMapping order by provider_type"#
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
+}
+
+async fn upsert_mapping(provider_type: &str, category: &str, updated_by: &str) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("upsert VehicleTypeMapping");
+    let updated_at = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    sqlx::query!(
+        r#"insert into VehicleTypeMapping(provider_type, category, updated_by, updated_at)
+        values (?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+        on duplicate key update category=values(category), updated_by=values(updated_by), updated_at=values(updated_at)"#,
+        provider_type,
+        category,
+        updated_by,
+        updated_at
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+#[utoipa::path(
+    get,
+    path = "/vehicle-type/mappings",
+    tag = "vehicle",
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Provider vehicle types and the category each maps to", body = [VehicleTypeMapping]),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to manage vehicle types", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_vehicle_type_mappings() -> impl IntoResponse {
+    (StatusCode::OK, Json(get_mappings().await)).into_response()
+}
+
+#[utoipa::path(
+    put,
+    path = "/vehicle-type/mappings/{provider_type}",
+    tag = "vehicle",
+    params(("provider_type" = String, Path, description = "vehicle_type as returned by RegCheck")),
+    request_body = VehicleTypeMappingUpdate,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Mapping saved", body = VehicleTypeMapping),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to manage vehicle types", body = String),
+        (status = 422, description = "Invalid request body or unknown category", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn put_vehicle_type_mapping(
+    Path(provider_type): Path<String>,
+    principal: Principal,
+    ValidatedJson(mapping): ValidatedJson<VehicleTypeMappingUpdate>,
+) -> impl IntoResponse {
+    let known = get_categories()
+        .await
+        .iter()
+        .any(|category| category.code == mapping.category);
+
+    if !known {
+        return ValidationRejection::field("category", "category", "must be a vehicle category")
+            .into_response();
+    }
+
+    let provider_type = provider_type.trim().to_uppercase();
+    upsert_mapping(&provider_type, &mapping.category, &principal.audit_name()).await;
+
+    let saved = get_mappings()
+        .await
+        .into_iter()
+        .find(|m| m.provider_type == provider_type)
+        .unwrap();
+
+    (StatusCode::OK, Json(saved)).into_response()
+}
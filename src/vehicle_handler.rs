@@ -1,27 +1,78 @@
-use crate::api_structs::{ManualVehicleCreation, Vehicle};
-use axum::extract::Host;
+use crate::api_structs::{
+    ManualVehicleCreation, Vehicle, VehicleChange, VehicleReview, VehicleUpdate,
+    VehicleUpdateResult,
+};
+use axum::extract::{Host, Path, State};
 use axum::Json;
-use axum::{extract::Query, response::IntoResponse};
+use axum::{extract::Query, response::{IntoResponse, Response}};
+use chrono::{Duration, Utc};
 use http::StatusCode;
-use quick_xml::events::Event;
-use quick_xml::Reader;
-use regex::Regex;
 use std::error::Error;
+use std::time::Duration as StdDuration;
 
+use crate::auth::Principal;
+use crate::catalogue::{add_to_catalogue, canonicalize};
 use crate::helper_structs::VehicleDescription;
+use crate::telemetry::{capture_notice, db_breadcrumb, http_breadcrumb};
+use crate::license_plate::LicensePlate;
+use crate::redis_store::RedisStore;
+use crate::regcheck::{self, RegCheckError};
+use crate::validation::ValidatedJson;
+use crate::vin::describe_vin_issues;
+use crate::vehicle_category::{check_vehicle_type, get_categories};
+use crate::vehicle_cache::{get_vehicle_data_cached, invalidate_vehicle_cache};
+use crate::vehicle_lookup::{get_latest_lookup, store_lookup, REGCHECK};
+use crate::vehicle_history::{
+    apply_vehicle_changes, get_vehicle_fields, get_vehicle_history, set_review_status,
+    set_vin_issues, ChangeSource, ReviewStatus, VehicleFields,
+};
 use crate::{api_structs::GetVehicleQP, sql::establish_connection};
 
+#[utoipa::path(
+    post,
+    path = "/vehicle/manual",
+    tag = "vehicle",
+    request_body = ManualVehicleCreation,
+    responses(
+        (status = 201, description = "Vehicle registered, pending staff review", body = Vehicle),
+        (status = 409, description = "Vehicle already registered, returns the current data", body = Vehicle),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = String),
+        (status = 500, description = "Vehicle could not be stored", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
 pub async fn vehicle_manual_creation(
-    vehicle_data: Json<ManualVehicleCreation>,
+    State(redis): State<RedisStore>,
+    ValidatedJson(vehicle_data): ValidatedJson<ManualVehicleCreation>,
 ) -> impl IntoResponse {
-    sentry::capture_message("New manual registration", sentry::Level::Error);
+    // Registering a plate we already know would overwrite its data, so the
+    // current record is returned instead.
+    if let Some(existing) = check_vehicle_exists(vehicle_data.license_plate.to_string()).await {
+        return (StatusCode::CONFLICT, Json(existing)).into_response();
+    }
+
+    if let Err(rejection) = check_vehicle_type(&vehicle_data.vehicle_type).await {
+        return rejection.into_response();
+    }
+
+    capture_notice(
+        "New manual registration",
+        sentry::Level::Info,
+        &[
+            ("license_plate", vehicle_data.license_plate.as_str()),
+            ("vehicle_type", &vehicle_data.vehicle_type),
+        ],
+    );
+
+    // Use the catalogue spelling so the same make isn't stored three ways.
+    let (make, model) = canonicalize(&vehicle_data.make, &vehicle_data.model).await;
 
     let vehicle = Vehicle {
-        license_plate: vehicle_data.license_plate.clone(),
+        license_plate: vehicle_data.license_plate.to_string(),
         vehicle_type: Some(vehicle_data.vehicle_type.clone()),
-        make: Some(vehicle_data.make.clone()),
-        model: Some(vehicle_data.model.clone()),
+        make: Some(make),
+        model: Some(model),
         year: Some(vehicle_data.year.clone()),
         vin: None,
         circulation_to: None,
@@ -29,11 +80,26 @@ pub async fn vehicle_manual_creation(
         description: None,
         engine_code: None,
         fuel: None,
+        source: ChangeSource::Manual.as_str().to_string(),
+        review_status: ReviewStatus::Pending.as_str().to_string(),
+        vin_issues: None,
+        image_url: None,
     };
 
-    let new_vehicle = create_new_vehicle(vehicle).await;
+    // Any cached provider result for this plate is superseded by the manual data.
+    invalidate_vehicle_cache(&redis, vehicle_data.license_plate.as_str()).await;
+
+    // The error is turned into a String so it isn't held across the awaits below.
+    let new_vehicle = create_new_vehicle(vehicle)
+        .await
+        .map_err(|err| err.to_string());
 
     if new_vehicle.is_err() {
+        // Lost a race with another registration of the same plate.
+        if let Some(existing) = check_vehicle_exists(vehicle_data.license_plate.to_string()).await {
+            return (StatusCode::CONFLICT, Json(existing)).into_response();
+        }
+
         sentry::capture_message(
             &format!(
                 "Failed to store new manual vehicle with license plate: {} : {}",
@@ -55,30 +121,60 @@ pub async fn vehicle_manual_creation(
     (StatusCode::CREATED, Json(new_vehicle.unwrap())).into_response()
 }
 
+#[utoipa::path(
+    get,
+    path = "/vehicle-type",
+    tag = "vehicle",
+    responses((status = 200, description = "Vehicle category codes", body = [String]))
+)]
 pub async fn get_vehicle_types() -> impl IntoResponse {
-    let list = get_list_vehicle_types().await;
+    let list: Vec<String> = get_categories()
+        .await
+        .into_iter()
+        .map(|category| category.code)
+        .collect();
 
     (StatusCode::OK, Json(list)).into_response()
 }
 
+#[utoipa::path(
+    get,
+    path = "/v2/vehicle-type",
+    tag = "vehicle",
+    responses((status = 200, description = "Vehicle categories", body = [VehicleCategory]))
+)]
+pub async fn get_vehicle_types_v2() -> impl IntoResponse {
+    (StatusCode::OK, Json(get_categories().await)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/vehicle",
+    tag = "vehicle",
+    params(GetVehicleQP),
+    responses(
+        (status = 200, description = "Known vehicle", body = Vehicle),
+        (status = 201, description = "Vehicle fetched from the provider and stored", body = Vehicle),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 424, description = "Provider lookup failed, manual entry required", body = String),
+        (status = 429, description = "Rate limited", body = String),
+    )
+)]
 #[axum_macros::debug_handler]
-pub async fn get_vehicle_data(query_params: Query<GetVehicleQP>, uri: Host) -> impl IntoResponse {
+pub async fn get_vehicle_data(
+    State(redis): State<RedisStore>,
+    query_params: Query<GetVehicleQP>,
+    uri: Host,
+) -> impl IntoResponse {
     println!("extension:{:?}", uri);
-    // Check if received license plate is in a valid format.
-    let rg = Regex::new(r"^[A-Z]{2}[A-Z0-9]{2}\d{2}(\d{2})?$").unwrap();
-    let valid = rg.is_match(&query_params.license_plate);
-
-    // Fails if license plate is not valid.
-    if !valid {
-        let err_msg = format!(
-            "License plate '{}' is not a valid license plate",
-            &query_params.license_plate
-        );
-        return (StatusCode::BAD_REQUEST, err_msg).into_response();
-    }
+    // Normalise the received license plate, failing if it isn't a valid one.
+    let license_plate = match LicensePlate::parse(&query_params.license_plate) {
+        Ok(plate) => plate.to_string(),
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
 
     // Check if vehicle with specified license plate is already on db.
-    let vehicle = check_vehicle_exists(query_params.license_plate.clone()).await;
+    let vehicle = check_vehicle_exists(license_plate.clone()).await;
 
     //if vehicle.is_err() {
     //    return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
@@ -87,20 +183,27 @@ pub async fn get_vehicle_data(query_params: Query<GetVehicleQP>, uri: Host) -> i
     //let vehicle = vehicle.unwrap();
     if vehicle.is_none() {
         // Tries to get license plate data
-        let vehicle_data = get_vehicle_data_api(query_params.license_plate.clone()).await;
+        let vehicle_data = get_vehicle_data_cached(&redis, license_plate.clone()).await;
 
         if vehicle_data.is_err() {
             let err_msg = format!(
                 "Couldn't retrieve data for license plate {}",
-                query_params.license_plate.clone()
+                license_plate.clone()
             );
             return vehicle_data.unwrap_err().into_response();
         }
 
+        let vehicle_data = vehicle_data.unwrap();
+        add_to_catalogue(
+            &vehicle_data.car_make.current_text_value,
+            &vehicle_data.car_model.current_text_value,
+        )
+        .await;
+
         // Get vehicle object form api data
         let new_vehicle = Vehicle::from_vehicle_description(
-            vehicle_data.unwrap(),
-            query_params.license_plate.clone(),
+            vehicle_data,
+            license_plate.clone(),
         );
 
         // Insert new vehicle to db.
@@ -109,7 +212,7 @@ pub async fn get_vehicle_data(query_params: Query<GetVehicleQP>, uri: Host) -> i
         if new_vehicle.is_err() {
             let err_msg = format!(
                 "Couldn't save data for license plate {}: {:?}",
-                query_params.license_plate.clone(),
+                license_plate.clone(),
                 new_vehicle.as_ref().unwrap_err()
             );
             return (StatusCode::INTERNAL_SERVER_ERROR, err_msg).into_response();
@@ -121,20 +224,458 @@ pub async fn get_vehicle_data(query_params: Query<GetVehicleQP>, uri: Host) -> i
     return (StatusCode::OK, Json(vehicle.unwrap())).into_response();
 }
 
+#[utoipa::path(
+    patch,
+    path = "/vehicle/{license_plate}",
+    tag = "vehicle",
+    params(("license_plate" = String, Path, description = "License plate")),
+    request_body = VehicleUpdate,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Vehicle updated, with the recorded changes", body = VehicleUpdateResult),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to edit vehicles", body = String),
+        (status = 404, description = "Vehicle not found", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn update_vehicle(
+    Path(license_plate): Path<String>,
+    principal: Principal,
+    ValidatedJson(update): ValidatedJson<VehicleUpdate>,
+) -> impl IntoResponse {
+    let (license_plate, current) = match find_vehicle_fields(&license_plate).await {
+        Ok(found) => found,
+        Err(res) => return res,
+    };
+
+    if let Some(vehicle_type) = &update.vehicle_type {
+        if let Err(rejection) = check_vehicle_type(vehicle_type).await {
+            return rejection.into_response();
+        }
+    }
+
+    let mut update = update;
+    if update.make.is_some() || update.model.is_some() {
+        let make = update.make.clone().or_else(|| current.make.clone());
+        let model = update.model.clone().or_else(|| current.model.clone());
+        let (make, model) =
+            canonicalize(&make.unwrap_or_default(), &model.unwrap_or_default()).await;
+
+        update.make = update.make.map(|_| make);
+        update.model = update.model.map(|_| model);
+    }
+
+    let updated = current.with_update(update);
+
+    save_vehicle_changes(
+        &license_plate,
+        &current,
+        &updated,
+        ChangeSource::Manual,
+        &principal,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/vehicle/{license_plate}/refresh",
+    tag = "vehicle",
+    params(("license_plate" = String, Path, description = "License plate")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Vehicle refreshed from the provider, with the recorded changes", body = VehicleUpdateResult),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to edit vehicles", body = String),
+        (status = 404, description = "Vehicle not found", body = String),
+        (status = 424, description = "Provider lookup failed", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn refresh_vehicle(
+    State(redis): State<RedisStore>,
+    Path(license_plate): Path<String>,
+    principal: Principal,
+) -> impl IntoResponse {
+    let (license_plate, current) = match find_vehicle_fields(&license_plate).await {
+        Ok(found) => found,
+        Err(res) => return res,
+    };
+
+    // Skip whatever the cache holds so stale data (e.g. an expired
+    // circulation_to) is fetched again from RegCheck.
+    invalidate_vehicle_cache(&redis, &license_plate).await;
+    let vehicle_data = match get_vehicle_data_cached(&redis, license_plate.clone()).await {
+        Ok(vehicle_data) => vehicle_data,
+        Err(err) => return err.into_response(),
+    };
+
+    let updated = current.with_provider_data(vehicle_data);
+
+    save_vehicle_changes(
+        &license_plate,
+        &current,
+        &updated,
+        ChangeSource::Provider,
+        &principal,
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/vehicle/{license_plate}/rederive",
+    tag = "vehicle",
+    params(("license_plate" = String, Path, description = "License plate")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Vehicle derived again from the last stored provider response, with the recorded changes", body = VehicleUpdateResult),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to edit vehicles", body = String),
+        (status = 404, description = "Vehicle or stored provider response not found", body = String),
+        (status = 500, description = "Stored provider response could not be parsed", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn rederive_vehicle(
+    Path(license_plate): Path<String>,
+    principal: Principal,
+) -> impl IntoResponse {
+    let (license_plate, current) = match find_vehicle_fields(&license_plate).await {
+        Ok(found) => found,
+        Err(res) => return res,
+    };
+
+    let lookup = match get_latest_lookup(&license_plate).await {
+        Some(lookup) => lookup,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(String::from("No stored provider response for this vehicle")),
+            )
+                .into_response()
+        }
+    };
+
+    // RegCheck is the only provider we store responses from so far.
+    if lookup.provider != REGCHECK {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("No parser for {} responses", lookup.provider)),
+        )
+            .into_response();
+    }
+
+    let vehicle_data = match regcheck::parse(&lookup.payload) {
+        Ok(vehicle_data) => vehicle_data,
+        Err(err) => {
+            let message = format!("{} (response from {})", err, lookup.fetched_at);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(message)).into_response();
+        }
+    };
+
+    let updated = current.with_provider_data(vehicle_data);
+
+    save_vehicle_changes(
+        &license_plate,
+        &current,
+        &updated,
+        ChangeSource::Provider,
+        &principal,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/vehicle/{license_plate}/history",
+    tag = "vehicle",
+    params(("license_plate" = String, Path, description = "License plate")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Changes made to the vehicle, newest first", body = [VehicleChange]),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to view vehicle history", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_vehicle_history_handler(Path(license_plate): Path<String>) -> impl IntoResponse {
+    let license_plate = match LicensePlate::parse(&license_plate) {
+        Ok(plate) => plate,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    };
+
+    let history = get_vehicle_history(license_plate.as_str()).await;
+
+    (StatusCode::OK, Json(history)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/vehicle/review-queue",
+    tag = "vehicle",
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Manually registered vehicles waiting for review, oldest first", body = [Vehicle]),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to review vehicles", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_review_queue() -> impl IntoResponse {
+    let vehicles = get_vehicles_pending_review().await;
+
+    (StatusCode::OK, Json(vehicles)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/vehicle/{license_plate}/review",
+    tag = "vehicle",
+    params(("license_plate" = String, Path, description = "License plate")),
+    request_body = VehicleReview,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Vehicle reviewed", body = Vehicle),
+        (status = 400, description = "Invalid license plate", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to review vehicles", body = String),
+        (status = 404, description = "Vehicle not found", body = String),
+        (status = 409, description = "Vehicle is not pending review", body = Vehicle),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn review_vehicle(
+    Path(license_plate): Path<String>,
+    principal: Principal,
+    ValidatedJson(review): ValidatedJson<VehicleReview>,
+) -> impl IntoResponse {
+    let license_plate = match LicensePlate::parse(&license_plate) {
+        Ok(plate) => plate.to_string(),
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    };
+
+    let vehicle = match check_vehicle_exists(license_plate.clone()).await {
+        Some(vehicle) => vehicle,
+        None => {
+            return (StatusCode::NOT_FOUND, Json(String::from("Vehicle not found")))
+                .into_response()
+        }
+    };
+
+    if vehicle.review_status != ReviewStatus::Pending.as_str() {
+        return (StatusCode::CONFLICT, Json(vehicle)).into_response();
+    }
+
+    let status = if review.approved {
+        ReviewStatus::Approved
+    } else {
+        ReviewStatus::Rejected
+    };
+
+    if let Err(err) = set_review_status(&license_plate, status, &principal.audit_name()).await {
+        sentry::capture_message(
+            &format!("Failed to review vehicle {}: {}", license_plate, err),
+            sentry::Level::Error,
+        );
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(format!("Failed to review vehicle {}", license_plate)),
+        )
+            .into_response();
+    }
+
+    let vehicle = check_vehicle_exists(license_plate).await.unwrap();
+
+    (StatusCode::OK, Json(vehicle)).into_response()
+}
+
+async fn find_vehicle_fields(license_plate: &str) -> Result<(String, VehicleFields), Response> {
+    let license_plate = match LicensePlate::parse(license_plate) {
+        Ok(plate) => plate.to_string(),
+        Err(err) => return Err((StatusCode::BAD_REQUEST, Json(err.to_string())).into_response()),
+    };
+
+    match get_vehicle_fields(&license_plate).await {
+        Some(current) => Ok((license_plate, current)),
+        None => Err((StatusCode::NOT_FOUND, Json(String::from("Vehicle not found"))).into_response()),
+    }
+}
+
+async fn save_vehicle_changes(
+    license_plate: &str,
+    current: &VehicleFields,
+    updated: &VehicleFields,
+    source: ChangeSource,
+    principal: &Principal,
+) -> Response {
+    let changes = record_vehicle_changes(
+        license_plate,
+        current,
+        updated,
+        source,
+        &principal.audit_name(),
+    )
+    .await;
+
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(err) => {
+            sentry::capture_message(
+                &format!("Failed to update vehicle {}: {}", license_plate, err),
+                sentry::Level::Error,
+            );
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(format!("Failed to update vehicle {}", license_plate)),
+            )
+                .into_response();
+        }
+    };
+
+    let vehicle = check_vehicle_exists(license_plate.to_string()).await.unwrap();
+
+    (StatusCode::OK, Json(VehicleUpdateResult { vehicle, changes })).into_response()
+}
+
+async fn record_vehicle_changes(
+    license_plate: &str,
+    current: &VehicleFields,
+    updated: &VehicleFields,
+    source: ChangeSource,
+    changed_by: &str,
+) -> Result<Vec<VehicleChange>, sqlx::Error> {
+    let changes =
+        apply_vehicle_changes(license_plate, current, updated, source, changed_by).await?;
+
+    // A new VIN, make or year can make them (in)consistent with each other.
+    let vin_related = ["vin", "make", "year"];
+    if changes.iter().any(|change| vin_related.contains(&change.field.as_str())) {
+        let issues = describe_vin_issues(
+            updated.vin.as_deref(),
+            updated.make.as_deref(),
+            updated.year.as_deref(),
+        );
+        set_vin_issues(license_plate, issues).await;
+    }
+
+    Ok(changes)
+}
+
+// Same as POST /vehicle/{license_plate}/refresh, for the refresh-vehicle job.
+// Returns how many fields changed.
+pub async fn refresh_vehicle_from_provider(
+    redis: &RedisStore,
+    license_plate: &str,
+    changed_by: &str,
+) -> Result<usize, String> {
+    let current = get_vehicle_fields(license_plate)
+        .await
+        .ok_or_else(|| format!("Vehicle {} not found", license_plate))?;
+
+    invalidate_vehicle_cache(redis, license_plate).await;
+    let vehicle_data = get_vehicle_data_cached(redis, license_plate.to_string())
+        .await
+        .map_err(|(status, err)| format!("RegCheck lookup failed ({}): {}", status, err))?;
+
+    let updated = current.with_provider_data(vehicle_data);
+    let changes = record_vehicle_changes(
+        license_plate,
+        &current,
+        &updated,
+        ChangeSource::Provider,
+        changed_by,
+    )
+    .await
+    .map_err(|err| err.to_string())?;
+
+    Ok(changes.len())
+}
+
+// Vehicles whose circulation permit has expired, so RegCheck likely has newer
+// data for them. Each is looked up at most once every `min_days`.
+pub async fn get_vehicles_to_refresh(
+    min_days: i64,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Vehicles to refresh");
+    let now = Utc::now();
+    let today = now.format("%Y-%m-%d").to_string();
+    let refreshed_since = (now - Duration::days(min_days))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    let plates = sqlx::query!(
+        r#"select license_plate from Vehicle
+        where circulation_to < STR_TO_DATE(?, '%Y-%m-%d')
+        and license_plate not in (select license_plate from VehicleLookup
+            where provider = ? and fetched_at >= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))
+        order by circulation_to limit ?"#,
+        today,
+        REGCHECK,
+        refreshed_since,
+        limit
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    Ok(plates.into_iter().map(|row| row.license_plate).collect())
+}
+
+// RegCheck lookups are given up on after this long and answered with 424.
+const REGCHECK_TIMEOUT: StdDuration = StdDuration::from_secs(20);
+
+// A lookup that never got a usable answer from RegCheck.
+fn regcheck_unreachable(license_plate: &str, err: reqwest::Error) -> (StatusCode, String) {
+    let err = RegCheckError::Unreachable(err.to_string());
+    sentry::capture_message(
+        &format!("RegCheck lookup failed for {}: {}", license_plate, err),
+        sentry::Level::Error,
+    );
+    (StatusCode::FAILED_DEPENDENCY, err.to_string())
+}
+
 pub async fn get_vehicle_data_api(
     license_plate: String,
 ) -> Result<VehicleDescription, (StatusCode, String)> {
-    println!("license_plate: {}", license_plate);
-    let resp = reqwest::Client::new()
-        .get(format!("http://cl.matriculaapi.com/api/reg.asmx/CheckChile?RegistrationNumber={}&username=adminpescara", license_plate))
-        .send()
-        .await.unwrap();
+    // REGCHECK_URL points lookups elsewhere, e.g. to a fake server in tests.
+    let host = std::env::var("REGCHECK_URL")
+        .unwrap_or_else(|_| String::from("http://cl.matriculaapi.com"));
+    let url = format!("{}/api/reg.asmx/CheckChile?RegistrationNumber={}&username=adminpescara", host, license_plate);
+    let client = reqwest::Client::builder()
+        .timeout(REGCHECK_TIMEOUT)
+        .build()
+        .unwrap();
+    let resp = match client.get(&url).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            http_breadcrumb("GET", &url, None);
+            return Err(regcheck_unreachable(&license_plate, err));
+        }
+    };
 
-    println!("resp: {:?}", resp);
+    http_breadcrumb("GET", &url, Some(resp.status().as_u16()));
 
     if !resp.status().is_success() {
         let code = resp.status();
-        let text = resp.text().await.unwrap();
+        let text = resp
+            .text()
+            .await
+            .map_err(|err| regcheck_unreachable(&license_plate, err))?;
+
+        // RegCheck answers unknown plates with a SOAP fault and a 500.
+        if matches!(regcheck::parse(&text), Err(RegCheckError::NotFound)) {
+            return Err((StatusCode::FAILED_DEPENDENCY, RegCheckError::NotFound.to_string()));
+        }
+
         let err_msg = format!(
             "RegCheck API failed for license plate {}: {} - {}",
             &license_plate, code, text
@@ -147,36 +688,42 @@ pub async fn get_vehicle_data_api(
 
     // reqwest to api that returns xml.
 
-    let xml: String = resp.text().await.unwrap();
-    let mut reader = Reader::from_str(&xml);
-    println!("{}", xml);
-
-    loop {
-        match reader.read_event().unwrap() {
-            Event::Start(e) => {
-                let tag_name =
-                    String::from_utf8(e.name().local_name().into_inner().to_vec()).unwrap();
-                if &tag_name == "vehicleJson" {
-                    let content = reader.read_text(e.name()).unwrap();
-                    println!("content: {:?}", content);
-                    return Ok(serde_json::from_str(&content).unwrap());
-                }
-            }
-            Event::Eof => break,
-            _ => (),
+    let xml: String = resp
+        .text()
+        .await
+        .map_err(|err| regcheck_unreachable(&license_plate, err))?;
+    store_lookup(&license_plate, REGCHECK, &xml).await;
+
+    regcheck::parse(&xml).map_err(|err| {
+        // Plates RegCheck doesn't know are expected, everything else is worth a look.
+        if err != RegCheckError::NotFound {
+            sentry::capture_message(
+                &format!("Invalid RegCheck response for {}: {}", license_plate, err),
+                sentry::Level::Error,
+            );
         }
-    }
+        (StatusCode::FAILED_DEPENDENCY, err.to_string())
+    })
+}
 
-    //return Err("No vehicle data found".into());
-    sentry::capture_message("XML for vehicle data invalid", sentry::Level::Error);
-    return Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("Invalid response from RegCheck")));
+// Quotes and plans are only made for vehicles whose data staff approved, or
+// that never needed review. Returns why not otherwise.
+pub fn review_hold(vehicle: &Vehicle) -> Option<&'static str> {
+    if vehicle.review_status == ReviewStatus::Pending.as_str() {
+        Some("Vehicle data is waiting for staff review, please try again later")
+    } else if vehicle.review_status == ReviewStatus::Rejected.as_str() {
+        Some("Vehicle data was rejected in review, please contact support")
+    } else {
+        None
+    }
 }
 
 pub async fn check_vehicle_exists(license_plate: String) -> Option<Vehicle> {
     let mut conn = establish_connection().await;
+    db_breadcrumb("select Vehicle by license_plate");
 
     let res: Option<Vehicle> = sqlx::query_as!(Vehicle,
-        "select license_plate, vehicle_type , make, model, registration_year as year, engine_code, DATE_FORMAT(circulation_from, '%Y-%m-%dT%TZ') circulation_from, DATE_FORMAT(circulation_to, '%Y-%m-%dT%TZ') as circulation_to, description, fuel, vin from
+        "select license_plate, vehicle_type , make, model, registration_year as year, engine_code, DATE_FORMAT(circulation_from, '%Y-%m-%dT%TZ') circulation_from, DATE_FORMAT(circulation_to, '%Y-%m-%dT%TZ') as circulation_to, description, fuel, vin, source, review_status, vin_issues, image_url from
                               Vehicle where license_plate = ?",
         license_plate
     )
@@ -188,9 +735,12 @@ pub async fn check_vehicle_exists(license_plate: String) -> Option<Vehicle> {
 
 pub async fn create_new_vehicle(vehicle: Vehicle) -> Result<Vehicle, Box<dyn Error>> {
     let mut conn = establish_connection().await;
+    db_breadcrumb("insert Vehicle");
+    let review_requested_at = (vehicle.review_status == ReviewStatus::Pending.as_str())
+        .then(|| Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string());
 
     let res = sqlx::query!(
-        r#"insert into Vehicle(license_plate, vin, make, model, registration_year, engine_code, circulation_to, circulation_from,  description, fuel, vehicle_type) values(?,?,?,?,?,?,STR_TO_DATE(?, '%d-%m-%Y'),STR_TO_DATE(?, '%d-%m-%Y'),?,?,?)"#,
+        r#"insert into Vehicle(license_plate, vin, make, model, registration_year, engine_code, circulation_to, circulation_from,  description, fuel, vehicle_type, source, review_status, vin_issues, image_url, review_requested_at) values(?,?,?,?,?,?,STR_TO_DATE(?, '%d-%m-%Y'),STR_TO_DATE(?, '%d-%m-%Y'),?,?,?,?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
         vehicle.license_plate,
         vehicle.vin,
         vehicle.make,
@@ -202,6 +752,11 @@ pub async fn create_new_vehicle(vehicle: Vehicle) -> Result<Vehicle, Box<dyn Err
         vehicle.description,
         vehicle.fuel,
         vehicle.vehicle_type,
+        vehicle.source,
+        vehicle.review_status,
+        vehicle.vin_issues,
+        vehicle.image_url,
+        review_requested_at,
         )
     .execute(&mut conn)
     .await?;
@@ -209,17 +764,15 @@ pub async fn create_new_vehicle(vehicle: Vehicle) -> Result<Vehicle, Box<dyn Err
     Ok(vehicle)
 }
 
-pub async fn get_list_vehicle_types() -> Vec<String> {
-    pub struct TempList {
-        pub vehicle_type: Option<String>,
-    }
-
+pub async fn get_vehicles_pending_review() -> Vec<Vehicle> {
     let mut conn = establish_connection().await;
+    db_breadcrumb("select Vehicle pending review");
 
-    let res = sqlx::query_as!(TempList, "SELECT DISTINCT vehicle_type from Vehicle")
-        .fetch_all(&mut conn)
-        .await
-        .unwrap();
-
-    res.into_iter().map(|v| v.vehicle_type).flatten().collect()
+    sqlx::query_as!(Vehicle,
+        "select license_plate, vehicle_type , make, model, registration_year as year, engine_code, DATE_FORMAT(circulation_from, '%Y-%m-%dT%TZ') circulation_from, DATE_FORMAT(circulation_to, '%Y-%m-%dT%TZ') as circulation_to, description, fuel, vin, source, review_status, vin_issues, image_url from
+                              Vehicle where review_status = 'pending' order by review_requested_at, license_plate",
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
 }
@@ -0,0 +1,300 @@
+use chrono::{NaiveDate, Utc};
+
+use crate::api_structs::{VehicleChange, VehicleUpdate};
+use crate::helper_structs::VehicleDescription;
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::vin::VinIssues;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChangeSource {
+    // Edited by staff through the API.
+    Manual,
+    // Refreshed from a new RegCheck lookup.
+    Provider,
+}
+
+impl ChangeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSource::Manual => "manual",
+            ChangeSource::Provider => "provider",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+}
+
+// Editable Vehicle columns, with dates as YYYY-MM-DD.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VehicleFields {
+    pub vin: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub year: Option<String>,
+    pub engine_code: Option<String>,
+    pub circulation_from: Option<String>,
+    pub circulation_to: Option<String>,
+    pub description: Option<String>,
+    pub fuel: Option<String>,
+    pub vehicle_type: Option<String>,
+    pub image_url: Option<String>,
+}
+
+impl VehicleFields {
+    fn entries(&self) -> [(&'static str, &Option<String>); 11] {
+        [
+            ("vin", &self.vin),
+            ("make", &self.make),
+            ("model", &self.model),
+            ("year", &self.year),
+            ("engine_code", &self.engine_code),
+            ("circulation_from", &self.circulation_from),
+            ("circulation_to", &self.circulation_to),
+            ("description", &self.description),
+            ("fuel", &self.fuel),
+            ("vehicle_type", &self.vehicle_type),
+            ("image_url", &self.image_url),
+        ]
+    }
+
+    // Fields left out of the update keep their current value.
+    pub fn with_update(&self, update: VehicleUpdate) -> VehicleFields {
+        VehicleFields {
+            vin: update.vin.or_else(|| self.vin.clone()),
+            make: update.make.or_else(|| self.make.clone()),
+            model: update.model.or_else(|| self.model.clone()),
+            year: update.year.or_else(|| self.year.clone()),
+            engine_code: update.engine_code.or_else(|| self.engine_code.clone()),
+            circulation_from: update
+                .circulation_from
+                .or_else(|| self.circulation_from.clone()),
+            circulation_to: update
+                .circulation_to
+                .or_else(|| self.circulation_to.clone()),
+            description: update.description.or_else(|| self.description.clone()),
+            fuel: update.fuel.or_else(|| self.fuel.clone()),
+            vehicle_type: update.vehicle_type.or_else(|| self.vehicle_type.clone()),
+            image_url: self.image_url.clone(),
+        }
+    }
+
+    // RegCheck sends dates as DD-MM-YYYY; unparseable ones keep the stored value.
+    pub fn with_provider_data(&self, vehicle_data: VehicleDescription) -> VehicleFields {
+        let provider_date = |date: &str| {
+            NaiveDate::parse_from_str(date, "%d-%m-%Y")
+                .ok()
+                .map(|date| date.format("%Y-%m-%d").to_string())
+        };
+
+        VehicleFields {
+            vin: Some(vehicle_data.vin),
+            make: Some(vehicle_data.make_description.current_text_value),
+            model: Some(vehicle_data.model_description.current_text_value),
+            year: Some(vehicle_data.registration_year),
+            engine_code: Some(vehicle_data.engine_code),
+            circulation_from: provider_date(&vehicle_data.valid_since)
+                .or_else(|| self.circulation_from.clone()),
+            circulation_to: provider_date(&vehicle_data.expiry)
+                .or_else(|| self.circulation_to.clone()),
+            description: Some(vehicle_data.description),
+            fuel: Some(vehicle_data.fuel),
+            vehicle_type: Some(vehicle_data.vehicle_type),
+            image_url: Some(vehicle_data.image_url).filter(|url| !url.is_empty()),
+        }
+    }
+}
+
+pub async fn get_vehicle_fields(license_plate: &str) -> Option<VehicleFields> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Vehicle fields by license_plate");
+
+    sqlx::query_as!(
+        VehicleFields,
+        "select vin, make, model, registration_year as year, engine_code, DATE_FORMAT(circulation_from, '%Y-%m-%d') as circulation_from, DATE_FORMAT(circulation_to, '%Y-%m-%d') as circulation_to, description, fuel, vehicle_type, image_url from Vehicle where license_plate = ?",
+        license_plate
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+}
+
+// Writes the fields that differ from `current` and records one history entry
+// per changed field. Returns the recorded changes.
+pub async fn apply_vehicle_changes(
+    license_plate: &str,
+    current: &VehicleFields,
+    updated: &VehicleFields,
+    source: ChangeSource,
+    changed_by: &str,
+) -> Result<Vec<VehicleChange>, sqlx::Error> {
+    let changed_at = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    let changes: Vec<VehicleChange> = current
+        .entries()
+        .into_iter()
+        .zip(updated.entries())
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| VehicleChange {
+            field: field.to_string(),
+            old_value: old.clone(),
+            new_value: new.clone(),
+            source: source.as_str().to_string(),
+            changed_by: changed_by.to_string(),
+            changed_at: changed_at.clone(),
+        })
+        .collect();
+
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Vehicle with history");
+    let mut tx = sqlx::Connection::begin(&mut conn).await?;
+
+    sqlx::query!(
+        r#"UPDATE Vehicle SET vin=?, make=?, model=?, registration_year=?, engine_code=?, circulation_from=STR_TO_DATE(?, '%Y-%m-%d'), circulation_to=STR_TO_DATE(?, '%Y-%m-%d'), description=?, fuel=?, vehicle_type=?, image_url=? WHERE license_plate=?"#,
+        updated.vin,
+        updated.make,
+        updated.model,
+        updated.year,
+        updated.engine_code,
+        updated.circulation_from,
+        updated.circulation_to,
+        updated.description,
+        updated.fuel,
+        updated.vehicle_type,
+        updated.image_url,
+        license_plate
+    )
+    .execute(&mut tx)
+    .await?;
+
+    for change in &changes {
+        sqlx::query!(
+            r#"insert into VehicleHistory(license_plate, field, old_value, new_value, source, changed_by, changed_at)
+            values (?,?,?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+            license_plate,
+            change.field,
+            change.old_value,
+            change.new_value,
+            change.source,
+            change.changed_by,
+            change.changed_at
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(changes)
+}
+
+// Moves a vehicle out of the review queue, recording who reviewed it.
+pub async fn set_review_status(
+    license_plate: &str,
+    status: ReviewStatus,
+    changed_by: &str,
+) -> Result<(), sqlx::Error> {
+    let changed_at = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Vehicle review_status");
+    let mut tx = sqlx::Connection::begin(&mut conn).await?;
+
+    sqlx::query!(
+        "UPDATE Vehicle SET review_status=? WHERE license_plate=?",
+        status.as_str(),
+        license_plate
+    )
+    .execute(&mut tx)
+    .await?;
+
+    sqlx::query!(
+        r#"insert into VehicleHistory(license_plate, field, old_value, new_value, source, changed_by, changed_at)
+        values (?,?,?,?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        license_plate,
+        "review_status",
+        ReviewStatus::Pending.as_str(),
+        status.as_str(),
+        ChangeSource::Manual.as_str(),
+        changed_by,
+        changed_at
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Stores the VIN notes; vehicles with issues that need review go back to the
+// review queue, keeping their place if they were already in it.
+pub async fn set_vin_issues(license_plate: &str, vin_issues: Option<VinIssues>) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("update Vehicle vin_issues");
+    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    match vin_issues {
+        Some(vin_issues) if vin_issues.needs_review => sqlx::query!(
+            r#"UPDATE Vehicle SET vin_issues=?,
+            review_requested_at=if(review_status = ?, review_requested_at, STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')),
+            review_status=? WHERE license_plate=?"#,
+            vin_issues.notes,
+            ReviewStatus::Pending.as_str(),
+            timestamp,
+            ReviewStatus::Pending.as_str(),
+            license_plate
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap(),
+        Some(vin_issues) => sqlx::query!(
+            "UPDATE Vehicle SET vin_issues=? WHERE license_plate=?",
+            vin_issues.notes,
+            license_plate
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap(),
+        None => sqlx::query!(
+            "UPDATE Vehicle SET vin_issues=NULL WHERE license_plate=?",
+            license_plate
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap(),
+    };
+}
+
+pub async fn get_vehicle_history(license_plate: &str) -> Vec<VehicleChange> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select VehicleHistory by license_plate");
+
+    sqlx::query_as!(
+        VehicleChange,
+        r#"select field, old_value, new_value, source, changed_by, DATE_FORMAT(changed_at, '%Y-%m-%dT%TZ') as "changed_at!" from VehicleHistory where license_plate = ? order by changed_at desc, id desc"#,
+        license_plate
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap()
+}
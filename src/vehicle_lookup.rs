@@ -0,0 +1,55 @@
+use chrono::Utc;
+
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+
+pub const REGCHECK: &str = "regcheck";
+
+pub struct VehicleLookup {
+    pub provider: String,
+    pub payload: String,
+    pub fetched_at: String,
+}
+
+// Keeps the raw provider response. Failing to store it shouldn't fail the
+// lookup itself, so errors are only reported.
+pub async fn store_lookup(license_plate: &str, provider: &str, payload: &str) {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("insert VehicleLookup");
+    let fetched_at = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    let res = sqlx::query!(
+        r#"insert into VehicleLookup(license_plate, provider, payload, fetched_at)
+        values (?,?,?,STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+        license_plate,
+        provider,
+        payload,
+        fetched_at
+    )
+    .execute(&mut conn)
+    .await;
+
+    if let Err(err) = res {
+        sentry::capture_message(
+            &format!(
+                "Failed to store {} lookup for {}: {}",
+                provider, license_plate, err
+            ),
+            sentry::Level::Error,
+        );
+    }
+}
+
+pub async fn get_latest_lookup(license_plate: &str) -> Option<VehicleLookup> {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select latest VehicleLookup by license_plate");
+
+    sqlx::query_as!(
+        VehicleLookup,
+        r#"select provider, payload, DATE_FORMAT(fetched_at, '%Y-%m-%dT%TZ') as "fetched_at!" from VehicleLookup where license_plate = ? order by fetched_at desc, id desc limit 1"#,
+        license_plate
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap()
+}
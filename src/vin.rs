@@ -0,0 +1,441 @@
+use axum::{extract::Path, response::IntoResponse, Json};
+use chrono::Datelike;
+use http::StatusCode;
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+use crate::catalogue::normalize;
+
+const WEIGHTS: [u32; 17] = [8, 7, 6, 5, 4, 3, 2, 10, 0, 9, 8, 7, 6, 5, 4, 3, 2];
+
+// Model year codes (position 10), repeating every 30 years from 1980.
+const YEAR_CODES: &str = "ABCDEFGHJKLMNPRSTVWXY123456789";
+
+// (first character, second character range, country) per ISO 3779.
+const COUNTRIES: &[(char, char, char, &str)] = &[
+    ('A', 'A', 'H', "South Africa"),
+    ('J', 'A', 'Z', "Japan"),
+    ('K', 'L', 'R', "South Korea"),
+    ('L', 'A', 'Z', "China"),
+    ('M', 'A', 'E', "India"),
+    ('M', 'F', 'K', "Indonesia"),
+    ('M', 'L', 'R', "Thailand"),
+    ('S', 'A', 'M', "United Kingdom"),
+    ('T', 'A', 'H', "Switzerland"),
+    ('T', 'J', 'P', "Czech Republic"),
+    ('T', 'R', 'V', "Hungary"),
+    ('V', 'F', 'R', "France"),
+    ('V', 'S', 'W', "Spain"),
+    ('W', 'A', 'Z', "Germany"),
+    ('Y', 'A', 'E', "Belgium"),
+    ('Y', 'F', 'K', "Finland"),
+    ('Y', 'S', 'W', "Sweden"),
+    ('Z', 'A', 'R', "Italy"),
+    ('1', 'A', 'Z', "United States"),
+    ('2', 'A', 'Z', "Canada"),
+    ('3', 'A', 'W', "Mexico"),
+    ('4', 'A', 'Z', "United States"),
+    ('5', 'A', 'Z', "United States"),
+    ('6', 'A', 'W', "Australia"),
+    ('8', 'A', 'E', "Argentina"),
+    ('8', 'F', 'J', "Chile"),
+    ('9', 'A', 'E', "Brazil"),
+    ('9', 'F', 'J', "Colombia"),
+];
+
+// World manufacturer identifiers (or their prefixes) of the makes we see in
+// Chile. Longer prefixes are listed before shorter ones.
+const MANUFACTURERS: &[(&str, &str)] = &[
+    ("JA3", "Mitsubishi"),
+    ("JA4", "Mitsubishi"),
+    ("JAA", "Isuzu"),
+    ("JMB", "Mitsubishi"),
+    ("JT", "Toyota"),
+    ("JH", "Honda"),
+    ("JN", "Nissan"),
+    ("JM", "Mazda"),
+    ("JS", "Suzuki"),
+    ("JF", "Subaru"),
+    ("KMF", "Hyundai"),
+    ("KMH", "Hyundai"),
+    ("KM8", "Hyundai"),
+    ("KNA", "Kia"),
+    ("KND", "Kia"),
+    ("KPT", "SsangYong"),
+    ("KL", "Chevrolet"),
+    ("LVV", "Chery"),
+    ("LGW", "Great Wall"),
+    ("LSJ", "MG"),
+    ("LGX", "BYD"),
+    ("L6T", "Geely"),
+    ("LJ1", "JAC"),
+    ("MA3", "Suzuki"),
+    ("MAL", "Hyundai"),
+    ("MHF", "Toyota"),
+    ("MMB", "Mitsubishi"),
+    ("MNT", "Nissan"),
+    ("MR0", "Toyota"),
+    ("SAJ", "Jaguar"),
+    ("SAL", "Land Rover"),
+    ("TMB", "Skoda"),
+    ("VF1", "Renault"),
+    ("VF3", "Peugeot"),
+    ("VF7", "Citroen"),
+    ("VSS", "SEAT"),
+    ("WAU", "Audi"),
+    ("WBA", "BMW"),
+    ("WDB", "Mercedes-Benz"),
+    ("WDC", "Mercedes-Benz"),
+    ("WDD", "Mercedes-Benz"),
+    ("WP0", "Porsche"),
+    ("WVW", "Volkswagen"),
+    ("WV1", "Volkswagen"),
+    ("WV2", "Volkswagen"),
+    ("YV1", "Volvo"),
+    ("ZAR", "Alfa Romeo"),
+    ("ZFA", "Fiat"),
+    ("1FA", "Ford"),
+    ("1FM", "Ford"),
+    ("1FT", "Ford"),
+    ("1G1", "Chevrolet"),
+    ("1GC", "Chevrolet"),
+    ("1HG", "Honda"),
+    ("1N4", "Nissan"),
+    ("2T1", "Toyota"),
+    ("3FA", "Ford"),
+    ("3N1", "Nissan"),
+    ("4T1", "Toyota"),
+    ("5YJ", "Tesla"),
+    ("8AF", "Ford"),
+    ("8AJ", "Toyota"),
+    ("8AP", "Fiat"),
+    ("8A1", "Renault"),
+    ("93H", "Honda"),
+    ("9BG", "Chevrolet"),
+    ("9BR", "Toyota"),
+    ("9BW", "Volkswagen"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VinError {
+    Length(usize),
+    InvalidCharacter(char),
+    CheckDigit { expected: char, found: char },
+}
+
+impl fmt::Display for VinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VinError::Length(len) => write!(f, "VIN must be 17 characters long, got {}", len),
+            VinError::InvalidCharacter(c) => write!(f, "VIN contains invalid character '{}'", c),
+            VinError::CheckDigit { expected, found } => write!(
+                f,
+                "VIN check digit '{}' does not match, expected '{}'",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VinError {}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DecodedVin {
+    pub vin: String,
+    pub wmi: String,
+    pub manufacturer: Option<String>,
+    pub country: Option<String>,
+    // Possible model years, newest first; the year code repeats every 30 years.
+    pub model_years: Vec<i32>,
+    // None when the check digit isn't mandatory for the VIN's region and
+    // doesn't match, as many non North American manufacturers don't use it.
+    pub check_digit_valid: Option<bool>,
+}
+
+fn transliterate(c: char) -> u32 {
+    match c {
+        '0'..='9' => c.to_digit(10).unwrap(),
+        'A' | 'J' => 1,
+        'B' | 'K' | 'S' => 2,
+        'C' | 'L' | 'T' => 3,
+        'D' | 'M' | 'U' => 4,
+        'E' | 'N' | 'V' => 5,
+        'F' | 'W' => 6,
+        'G' | 'P' | 'X' => 7,
+        'H' | 'Y' => 8,
+        'R' | 'Z' => 9,
+        _ => 0,
+    }
+}
+
+fn check_digit(vin: &str) -> char {
+    let sum: u32 = vin
+        .chars()
+        .zip(WEIGHTS.iter())
+        .map(|(c, weight)| transliterate(c) * weight)
+        .sum();
+
+    match sum % 11 {
+        10 => 'X',
+        digit => char::from_digit(digit, 10).unwrap(),
+    }
+}
+
+// North American VINs must carry a valid check digit.
+fn check_digit_required(vin: &str) -> bool {
+    matches!(vin.chars().next(), Some('1'..='5'))
+}
+
+fn country(vin: &str) -> Option<&'static str> {
+    let mut chars = vin.chars();
+    let (first, second) = (chars.next()?, chars.next()?);
+
+    COUNTRIES
+        .iter()
+        .find(|(c, from, to, _)| *c == first && (*from..=*to).contains(&second))
+        .map(|(_, _, _, country)| *country)
+}
+
+fn manufacturer(vin: &str) -> Option<&'static str> {
+    MANUFACTURERS
+        .iter()
+        .find(|(wmi, _)| vin.starts_with(wmi))
+        .map(|(_, name)| *name)
+}
+
+fn model_years(vin: &str) -> Vec<i32> {
+    let code = vin.chars().nth(9).unwrap();
+    let offset = match YEAR_CODES.find(code) {
+        Some(offset) => offset as i32,
+        None => return vec![],
+    };
+
+    let max_year = chrono::Utc::now().year() + 1;
+    let mut years: Vec<i32> = (0..3)
+        .map(|cycle| 1980 + offset + cycle * 30)
+        .filter(|year| *year <= max_year)
+        .collect();
+    years.reverse();
+    years
+}
+
+pub fn decode(vin: &str) -> Result<DecodedVin, VinError> {
+    let vin = vin.trim().to_uppercase();
+
+    let len = vin.chars().count();
+    if len != 17 {
+        return Err(VinError::Length(len));
+    }
+
+    // I, O and Q are never used, to avoid confusion with 1 and 0.
+    if let Some(c) = vin
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() || matches!(c, 'I' | 'O' | 'Q'))
+    {
+        return Err(VinError::InvalidCharacter(c));
+    }
+
+    let expected = check_digit(&vin);
+    let found = vin.chars().nth(8).unwrap();
+    let check_digit_valid = match (expected == found, check_digit_required(&vin)) {
+        (true, _) => Some(true),
+        (false, true) => return Err(VinError::CheckDigit { expected, found }),
+        (false, false) => None,
+    };
+
+    Ok(DecodedVin {
+        wmi: vin[..3].to_string(),
+        manufacturer: manufacturer(&vin).map(String::from),
+        country: country(&vin).map(String::from),
+        model_years: model_years(&vin),
+        check_digit_valid,
+        vin,
+    })
+}
+
+// Differences between what the VIN says and the make/year stored for the
+// vehicle, as human readable notes for staff.
+pub fn vin_issues(
+    vin: &str,
+    make: Option<&str>,
+    year: Option<&str>,
+) -> Result<Vec<String>, VinError> {
+    let decoded = decode(vin)?;
+
+    let mut issues = vec![];
+
+    if let (Some(manufacturer), Some(make)) = (&decoded.manufacturer, make) {
+        let (manufacturer_key, make_key) = (normalize(manufacturer), normalize(make));
+        if !make_key.contains(&manufacturer_key) && !manufacturer_key.contains(&make_key) {
+            issues.push(format!(
+                "VIN manufacturer {} does not match make {}",
+                manufacturer, make
+            ));
+        }
+    }
+
+    // Only North American VINs are required to carry the model year in
+    // position 10; elsewhere it's often a plant or serial character.
+    // Registration can happen the year before or after the model year.
+    let year = year.filter(|_| check_digit_required(&decoded.vin));
+    if let Some(year) = year.and_then(|year| year.parse::<i32>().ok()) {
+        let matches = decoded
+            .model_years
+            .iter()
+            .any(|model_year| (model_year - year).abs() <= 1);
+        if !decoded.model_years.is_empty() && !matches {
+            issues.push(format!(
+                "VIN model year {} does not match year {}",
+                decoded.model_years[0], year
+            ));
+        }
+    }
+
+    Ok(issues)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VinIssues {
+    // The value stored in Vehicle.vin_issues.
+    pub notes: String,
+    // Only mismatches on a VIN that decodes need staff review. Provider data
+    // often has a chassis number instead of a VIN, which is just noted.
+    pub needs_review: bool,
+}
+
+// None when there's nothing to note about the VIN.
+pub fn describe_vin_issues(
+    vin: Option<&str>,
+    make: Option<&str>,
+    year: Option<&str>,
+) -> Option<VinIssues> {
+    let (issues, needs_review) =
+        match vin_issues(vin.filter(|vin| !vin.trim().is_empty())?, make, year) {
+            Ok(issues) => (issues, true),
+            Err(err) => (vec![err.to_string()], false),
+        };
+    (!issues.is_empty()).then(|| VinIssues {
+        notes: issues.join("; "),
+        needs_review,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/vin/{vin}",
+    tag = "vehicle",
+    params(("vin" = String, Path, description = "Vehicle identification number")),
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Decoded VIN", body = DecodedVin),
+        (status = 400, description = "Invalid VIN", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to decode VINs", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn decode_vin(Path(vin): Path<String>) -> impl IntoResponse {
+    match decode(&vin) {
+        Ok(decoded) => (StatusCode::OK, Json(decoded)).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, Json(err.to_string())).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_digit, decode, describe_vin_issues, model_years, vin_issues, VinError};
+
+    #[test]
+    fn computes_check_digit() {
+        assert_eq!(check_digit("1HGCM82633A004352"), '3');
+        assert_eq!(check_digit("1M8GDM9AXKP042788"), 'X');
+        assert_eq!(check_digit("11111111111111111"), '1');
+    }
+
+    #[test]
+    fn validates_check_digit_by_region() {
+        assert_eq!(
+            decode("1m8gdm9axkp042788").unwrap().check_digit_valid,
+            Some(true)
+        );
+        assert_eq!(
+            decode("1HGCM82693A004352").unwrap_err(),
+            VinError::CheckDigit {
+                expected: '3',
+                found: '9'
+            }
+        );
+        // Not mandatory outside North America.
+        assert_eq!(decode("WVWZZZ1KZ8W000001").unwrap().check_digit_valid, None);
+    }
+
+    #[test]
+    fn rejects_invalid_characters_and_length() {
+        for vin in [
+            "1HGCM82633I004352",
+            "1HGCM82633O004352",
+            "1HGCM82633Q004352",
+        ] {
+            assert!(matches!(
+                decode(vin),
+                Err(VinError::InvalidCharacter('I' | 'O' | 'Q'))
+            ));
+        }
+        assert_eq!(
+            decode("1HGCM82633A00435").unwrap_err(),
+            VinError::Length(16)
+        );
+    }
+
+    #[test]
+    fn year_codes_repeat_every_30_years() {
+        assert_eq!(model_years("1HGCM8263AA004352"), vec![2010, 1980]);
+        assert_eq!(model_years("1HGCM8263SA004352"), vec![2025, 1995]);
+        assert_eq!(model_years("1HGCM82633A004352"), vec![2003]);
+        assert_eq!(model_years("1HGCM82630A004352"), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn compares_years_only_for_north_american_vins() {
+        assert!(vin_issues("1HGCM82633A004352", Some("Honda"), Some("2004"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vin_issues("1HGCM82633A004352", Some("Honda"), Some("2010")).unwrap(),
+            vec!["VIN model year 2003 does not match year 2010"]
+        );
+        assert!(
+            vin_issues("WVWZZZ1KZ8W000001", Some("Volkswagen"), Some("2015"))
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            vin_issues("WVWZZZ1KZ8W000001", Some("Toyota"), None).unwrap(),
+            vec!["VIN manufacturer Volkswagen does not match make Toyota"]
+        );
+    }
+
+    #[test]
+    fn only_mismatches_on_decodable_vins_need_review() {
+        let issues = describe_vin_issues(Some("1HGCM82633A004352"), Some("Toyota"), None).unwrap();
+        assert!(issues.needs_review);
+
+        // Chassis numbers and short VINs are noted without holding the vehicle.
+        for vin in ["NZE1213045678", "9BWZZZ377VT00465"] {
+            let issues = describe_vin_issues(Some(vin), Some("Toyota"), Some("2015")).unwrap();
+            assert!(!issues.needs_review, "{}", vin);
+            assert!(
+                issues.notes.starts_with("VIN must be 17 characters"),
+                "{}",
+                vin
+            );
+        }
+
+        assert_eq!(describe_vin_issues(Some(" "), Some("Toyota"), None), None);
+        assert_eq!(
+            describe_vin_issues(Some("1HGCM82633A004352"), Some("Honda"), Some("2003")),
+            None
+        );
+    }
+}
@@ -0,0 +1,709 @@
+use axum::{
+    extract::{Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::America::Santiago;
+use http::StatusCode;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use sqlx::types::BigDecimal;
+use sqlx::MySqlConnection;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::sql::establish_connection;
+use crate::telemetry::db_breadcrumb;
+use crate::validation::{validate_time, ValidatedJson, ValidationRejection};
+
+// Appointment times are local to the workshops, all of them in Chile.
+pub const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Service {
+    // The periodic maintenance covered by the plan.
+    Maintenance,
+    OilChange,
+    Brakes,
+    Tires,
+    Inspection,
+    Electrical,
+}
+
+impl Service {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Service::Maintenance => "maintenance",
+            Service::OilChange => "oil_change",
+            Service::Brakes => "brakes",
+            Service::Tires => "tires",
+            Service::Inspection => "inspection",
+            Service::Electrical => "electrical",
+        }
+    }
+
+    pub fn parse(service: &str) -> Option<Service> {
+        match service {
+            "maintenance" => Some(Service::Maintenance),
+            "oil_change" => Some(Service::OilChange),
+            "brakes" => Some(Service::Brakes),
+            "tires" => Some(Service::Tires),
+            "inspection" => Some(Service::Inspection),
+            "electrical" => Some(Service::Electrical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Workshop {
+    pub id: String,
+    pub name: String,
+    pub address: String,
+    pub comuna: String,
+    pub region: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub services: Vec<Service>,
+    // Vehicles taken per slot.
+    pub capacity: i32,
+    // HH:MM, local time
+    pub opens_at: String,
+    pub closes_at: String,
+    pub slot_minutes: i32,
+    // ISO weekday numbers, 1 is Monday.
+    pub weekdays: Vec<u32>,
+    pub active: bool,
+    // Only when searching near a location.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+}
+
+fn default_slot_minutes() -> i32 {
+    60
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn validate_weekdays(weekdays: &[u32]) -> Result<(), ValidationError> {
+    match !weekdays.is_empty() && weekdays.iter().all(|day| (1..=7).contains(day)) {
+        true => Ok(()),
+        false => {
+            let mut error = ValidationError::new("weekdays");
+            error.message = Some("must list weekdays from 1 (Monday) to 7 (Sunday)".into());
+            Err(error)
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct WorkshopBody {
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub name: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub address: String,
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    pub comuna: String,
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    pub region: String,
+    #[validate(range(min = -90.0, max = 90.0, message = "must be between -90 and 90"))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0, message = "must be between -180 and 180"))]
+    pub longitude: Option<f64>,
+    #[validate(length(min = 1, message = "must list at least one service"))]
+    pub services: Vec<Service>,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub capacity: i32,
+    // HH:MM, local time
+    #[validate(custom = "validate_time")]
+    pub opens_at: String,
+    #[validate(custom = "validate_time")]
+    pub closes_at: String,
+    // Defaults to 60.
+    #[serde(default = "default_slot_minutes")]
+    #[validate(range(min = 15, max = 480, message = "must be between 15 and 480 minutes"))]
+    pub slot_minutes: i32,
+    // ISO weekday numbers, 1 is Monday.
+    #[validate(custom = "validate_weekdays")]
+    pub weekdays: Vec<u32>,
+    // Inactive workshops aren't listed and can't be booked. Defaults to true.
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WorkshopsQP {
+    pub region: Option<String>,
+    pub comuna: Option<String>,
+    pub service: Option<Service>,
+    // With both coordinates, workshops are sorted by distance to them.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // Defaults to 50.
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SlotsQP {
+    // YYYY-MM-DD
+    pub date: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Slot {
+    // YYYY-MM-DDTHH:MM:SS, local time
+    pub starts_at: String,
+    pub available: i64,
+}
+
+// When a workshop takes appointments.
+#[derive(Debug, Clone)]
+pub struct OpeningHours {
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+    pub slot_minutes: i64,
+    pub weekdays: Vec<u32>,
+}
+
+impl OpeningHours {
+    // Start of every slot on `date`; slots have to end by closing time.
+    pub fn slots(&self, date: NaiveDate) -> Vec<NaiveDateTime> {
+        if !self.weekdays.contains(&date.weekday().number_from_monday()) {
+            return vec![];
+        }
+
+        let length = Duration::minutes(self.slot_minutes);
+        let mut slots = vec![];
+        let mut start = date.and_time(self.opens_at);
+        while start + length <= date.and_time(self.closes_at) {
+            slots.push(start);
+            start += length;
+        }
+        slots
+    }
+
+    pub fn is_slot(&self, starts_at: NaiveDateTime) -> bool {
+        self.slots(starts_at.date()).contains(&starts_at)
+    }
+}
+
+// Current local time at the workshops.
+pub fn local_now() -> NaiveDateTime {
+    Utc::now().with_timezone(&Santiago).naive_local()
+}
+
+struct WorkshopRow {
+    id: String,
+    name: String,
+    address: String,
+    comuna: String,
+    region: String,
+    latitude: Option<BigDecimal>,
+    longitude: Option<BigDecimal>,
+    capacity: i32,
+    opens_at: String,
+    closes_at: String,
+    slot_minutes: i32,
+    weekdays: String,
+    active: bool,
+}
+
+impl WorkshopRow {
+    fn into_workshop(self, services: Vec<Service>) -> Workshop {
+        Workshop {
+            id: self.id,
+            name: self.name,
+            address: self.address,
+            comuna: self.comuna,
+            region: self.region,
+            latitude: self.latitude.and_then(|value| value.to_f64()),
+            longitude: self.longitude.and_then(|value| value.to_f64()),
+            services,
+            capacity: self.capacity,
+            opens_at: self.opens_at,
+            closes_at: self.closes_at,
+            slot_minutes: self.slot_minutes,
+            weekdays: parse_weekdays(&self.weekdays),
+            active: self.active,
+            distance_km: None,
+        }
+    }
+}
+
+fn parse_weekdays(weekdays: &str) -> Vec<u32> {
+    weekdays
+        .split(',')
+        .filter_map(|day| day.trim().parse().ok())
+        .collect()
+}
+
+impl Workshop {
+    pub fn opening_hours(&self) -> OpeningHours {
+        OpeningHours {
+            opens_at: NaiveTime::parse_from_str(&self.opens_at, "%H:%M").unwrap(),
+            closes_at: NaiveTime::parse_from_str(&self.closes_at, "%H:%M").unwrap(),
+            slot_minutes: self.slot_minutes as i64,
+            weekdays: self.weekdays.clone(),
+        }
+    }
+}
+
+// Great-circle distance in km.
+fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * 6371.0 * a.sqrt().asin()
+}
+
+async fn get_services(
+    conn: &mut MySqlConnection,
+    workshop_id: Option<&str>,
+) -> Vec<(String, Service)> {
+    db_breadcrumb("select WorkshopService");
+
+    sqlx::query!(
+        "select workshop_id, service from WorkshopService where ? is null or workshop_id = ? order by service",
+        workshop_id,
+        workshop_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .filter_map(|row| Some((row.workshop_id, Service::parse(&row.service)?)))
+    .collect()
+}
+
+// The workshop, locking it until the end of the transaction when
+// `for_update`, so bookings for it are made one at a time.
+pub async fn get_workshop(
+    conn: &mut MySqlConnection,
+    workshop_id: &str,
+    for_update: bool,
+) -> Option<Workshop> {
+    db_breadcrumb("select Workshop by id");
+
+    let row = match for_update {
+        true => sqlx::query_as!(
+            WorkshopRow,
+            r#"select id, name, address, comuna, region, latitude, longitude, capacity,
+            TIME_FORMAT(opens_at, '%H:%i') as "opens_at!", TIME_FORMAT(closes_at, '%H:%i') as "closes_at!",
+            slot_minutes, weekdays, active as "active: bool"
+            from Workshop where id = ? for update"#,
+            workshop_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .unwrap(),
+        false => sqlx::query_as!(
+            WorkshopRow,
+            r#"select id, name, address, comuna, region, latitude, longitude, capacity,
+            TIME_FORMAT(opens_at, '%H:%i') as "opens_at!", TIME_FORMAT(closes_at, '%H:%i') as "closes_at!",
+            slot_minutes, weekdays, active as "active: bool"
+            from Workshop where id = ?"#,
+            workshop_id
+        )
+        .fetch_optional(&mut *conn)
+        .await
+        .unwrap(),
+    }?;
+
+    let services = get_services(conn, Some(workshop_id))
+        .await
+        .into_iter()
+        .map(|(_, service)| service)
+        .collect();
+
+    Some(row.into_workshop(services))
+}
+
+// Appointments booked per slot start of the workshop between `from` and `to`.
+pub async fn count_booked(
+    conn: &mut MySqlConnection,
+    workshop_id: &str,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    excluding: Option<&str>,
+) -> Vec<(NaiveDateTime, i64)> {
+    db_breadcrumb("select booked Appointments by slot");
+
+    sqlx::query!(
+        r#"select DATE_FORMAT(starts_at, '%Y-%m-%dT%H:%i:%s') as "starts_at!", count(*) as booked
+        from Appointment where workshop_id = ? and status = ?
+        and starts_at >= STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s') and starts_at < STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s')
+        and (? is null or id <> ?)
+        group by starts_at"#,
+        workshop_id,
+        crate::appointments::BOOKED,
+        from.format(DATETIME_FORMAT).to_string(),
+        to.format(DATETIME_FORMAT).to_string(),
+        excluding,
+        excluding
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .filter_map(|row| {
+        let starts_at = NaiveDateTime::parse_from_str(&row.starts_at, DATETIME_FORMAT).ok()?;
+        Some((starts_at, row.booked))
+    })
+    .collect()
+}
+
+// Checks what the body's validations can't: that the workshop closes after it opens.
+fn check_hours(body: &WorkshopBody) -> Result<(), ValidationRejection> {
+    match body.opens_at < body.closes_at {
+        true => Ok(()),
+        false => Err(ValidationRejection::field(
+            "closes_at",
+            "time_range",
+            "must be after opens_at",
+        )),
+    }
+}
+
+async fn save_workshop(id: &str, body: &WorkshopBody, create: bool) -> bool {
+    let mut conn = establish_connection().await;
+    let mut tx = sqlx::Connection::begin(&mut conn).await.unwrap();
+    let weekdays = body
+        .weekdays
+        .iter()
+        .map(|day| day.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let res = match create {
+        true => {
+            db_breadcrumb("insert Workshop");
+            let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+            sqlx::query!(
+                r#"insert into Workshop(id, name, address, comuna, region, latitude, longitude, capacity,
+                opens_at, closes_at, slot_minutes, weekdays, active, creation_timestamp)
+                values (?, ?, ?, ?, ?, ?, ?, ?, STR_TO_DATE(?, '%H:%i'), STR_TO_DATE(?, '%H:%i'), ?, ?, ?,
+                STR_TO_DATE(?, '%Y-%m-%dT%H:%i:%s'))"#,
+                id,
+                body.name,
+                body.address,
+                body.comuna,
+                body.region,
+                body.latitude,
+                body.longitude,
+                body.capacity,
+                body.opens_at,
+                body.closes_at,
+                body.slot_minutes,
+                weekdays,
+                body.active,
+                timestamp
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap()
+        }
+        false => {
+            db_breadcrumb("update Workshop");
+            sqlx::query!(
+                r#"update Workshop set name = ?, address = ?, comuna = ?, region = ?, latitude = ?,
+                longitude = ?, capacity = ?, opens_at = STR_TO_DATE(?, '%H:%i'),
+                closes_at = STR_TO_DATE(?, '%H:%i'), slot_minutes = ?, weekdays = ?, active = ?
+                where id = ?"#,
+                body.name,
+                body.address,
+                body.comuna,
+                body.region,
+                body.latitude,
+                body.longitude,
+                body.capacity,
+                body.opens_at,
+                body.closes_at,
+                body.slot_minutes,
+                weekdays,
+                body.active,
+                id
+            )
+            .execute(&mut tx)
+            .await
+            .unwrap()
+        }
+    };
+    // MySQL doesn't count rows updated to the same values, so check existence.
+    let exists = res.rows_affected() == 1
+        || sqlx::query!("select id from Workshop where id = ?", id)
+            .fetch_optional(&mut tx)
+            .await
+            .unwrap()
+            .is_some();
+    if !exists {
+        return false;
+    }
+
+    db_breadcrumb("replace WorkshopService");
+    sqlx::query!("delete from WorkshopService where workshop_id = ?", id)
+        .execute(&mut tx)
+        .await
+        .unwrap();
+    for service in &body.services {
+        sqlx::query!(
+            "insert ignore into WorkshopService(workshop_id, service) values (?, ?)",
+            id,
+            service.as_str()
+        )
+        .execute(&mut tx)
+        .await
+        .unwrap();
+    }
+
+    tx.commit().await.unwrap();
+    true
+}
+
+#[utoipa::path(
+    post,
+    path = "/workshops",
+    tag = "workshops",
+    request_body = WorkshopBody,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 201, description = "Workshop created", body = Workshop),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to manage workshops", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn create_workshop(
+    ValidatedJson(body): ValidatedJson<WorkshopBody>,
+) -> impl IntoResponse {
+    if let Err(rejection) = check_hours(&body) {
+        return rejection.into_response();
+    }
+
+    let id = Uuid::new_v4().to_string();
+    save_workshop(&id, &body, true).await;
+
+    let mut conn = establish_connection().await;
+    let workshop = get_workshop(&mut conn, &id, false).await.unwrap();
+
+    (StatusCode::CREATED, Json(workshop)).into_response()
+}
+
+#[utoipa::path(
+    put,
+    path = "/workshops/{workshop_id}",
+    tag = "workshops",
+    params(("workshop_id" = String, Path, description = "Workshop id")),
+    request_body = WorkshopBody,
+    security(("api_key" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "Workshop updated. Booked appointments are kept even if they no longer fit the hours", body = Workshop),
+        (status = 401, description = "Missing or invalid credentials", body = String),
+        (status = 403, description = "Not allowed to manage workshops", body = String),
+        (status = 404, description = "Workshop not found", body = String),
+        (status = 422, description = "Invalid request body", body = ErrorBody),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn update_workshop(
+    Path(workshop_id): Path<String>,
+    ValidatedJson(body): ValidatedJson<WorkshopBody>,
+) -> impl IntoResponse {
+    if let Err(rejection) = check_hours(&body) {
+        return rejection.into_response();
+    }
+
+    if !save_workshop(&workshop_id, &body, false).await {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(String::from("Workshop not found")),
+        )
+            .into_response();
+    }
+
+    let mut conn = establish_connection().await;
+    let workshop = get_workshop(&mut conn, &workshop_id, false).await.unwrap();
+
+    (StatusCode::OK, Json(workshop)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/workshops",
+    tag = "workshops",
+    params(WorkshopsQP),
+    responses(
+        (status = 200, description = "Active workshops, nearest first when a location is given, otherwise by name", body = [Workshop]),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_workshops(Query(query): Query<WorkshopsQP>) -> impl IntoResponse {
+    let mut conn = establish_connection().await;
+    db_breadcrumb("select Workshops");
+    let service = query.service.map(|service| service.as_str());
+
+    let rows = sqlx::query_as!(
+        WorkshopRow,
+        r#"select id, name, address, comuna, region, latitude, longitude, capacity,
+        TIME_FORMAT(opens_at, '%H:%i') as "opens_at!", TIME_FORMAT(closes_at, '%H:%i') as "closes_at!",
+        slot_minutes, weekdays, active as "active: bool"
+        from Workshop where active = true and (? is null or region = ?) and (? is null or comuna = ?)
+        and (? is null or id in (select workshop_id from WorkshopService where service = ?))
+        order by name"#,
+        query.region,
+        query.region,
+        query.comuna,
+        query.comuna,
+        service,
+        service
+    )
+    .fetch_all(&mut conn)
+    .await
+    .unwrap();
+
+    let services = get_services(&mut conn, None).await;
+    let mut workshops: Vec<Workshop> = rows
+        .into_iter()
+        .map(|row| {
+            let offered = services
+                .iter()
+                .filter(|(workshop_id, _)| *workshop_id == row.id)
+                .map(|(_, service)| *service)
+                .collect();
+            row.into_workshop(offered)
+        })
+        .collect();
+
+    if let (Some(latitude), Some(longitude)) = (query.latitude, query.longitude) {
+        for workshop in workshops.iter_mut() {
+            workshop.distance_km = match (workshop.latitude, workshop.longitude) {
+                (Some(lat), Some(lon)) => Some(distance_km((latitude, longitude), (lat, lon))),
+                _ => None,
+            };
+        }
+        // Workshops without coordinates go last.
+        workshops.sort_by(|a, b| {
+            let distance = |w: &Workshop| w.distance_km.unwrap_or(f64::MAX);
+            distance(a).total_cmp(&distance(b))
+        });
+    }
+    workshops.truncate(query.limit.unwrap_or(50));
+
+    (StatusCode::OK, Json(workshops)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/workshops/{workshop_id}/slots",
+    tag = "workshops",
+    params(("workshop_id" = String, Path, description = "Workshop id"), SlotsQP),
+    responses(
+        (status = 200, description = "Upcoming slots of the day and how many vehicles each can still take", body = [Slot]),
+        (status = 400, description = "Invalid date", body = String),
+        (status = 404, description = "Workshop not found", body = String),
+    )
+)]
+#[axum_macros::debug_handler]
+pub async fn get_workshop_slots(
+    Path(workshop_id): Path<String>,
+    Query(query): Query<SlotsQP>,
+) -> impl IntoResponse {
+    let date = match NaiveDate::parse_from_str(&query.date, "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(String::from("date must be formatted as YYYY-MM-DD")),
+            )
+                .into_response()
+        }
+    };
+
+    let mut conn = establish_connection().await;
+    let workshop = match get_workshop(&mut conn, &workshop_id, false).await {
+        Some(workshop) if workshop.active => workshop,
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(String::from("Workshop not found")),
+            )
+                .into_response()
+        }
+    };
+
+    let day = date.and_hms_opt(0, 0, 0).unwrap();
+    let booked = count_booked(&mut conn, &workshop_id, day, day + Duration::days(1), None).await;
+    let now = local_now();
+
+    let slots: Vec<Slot> = workshop
+        .opening_hours()
+        .slots(date)
+        .into_iter()
+        .filter(|starts_at| *starts_at > now)
+        .map(|starts_at| {
+            let taken = booked
+                .iter()
+                .find(|(slot, _)| *slot == starts_at)
+                .map(|(_, count)| *count)
+                .unwrap_or(0);
+            Slot {
+                starts_at: starts_at.format(DATETIME_FORMAT).to_string(),
+                available: (workshop.capacity as i64 - taken).max(0),
+            }
+        })
+        .collect();
+
+    (StatusCode::OK, Json(slots)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance_km, OpeningHours};
+    use chrono::{NaiveDate, NaiveTime};
+
+    fn hours() -> OpeningHours {
+        OpeningHours {
+            opens_at: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            closes_at: NaiveTime::from_hms_opt(13, 30, 0).unwrap(),
+            slot_minutes: 90,
+            weekdays: vec![1, 2, 3, 4, 5],
+        }
+    }
+
+    #[test]
+    fn slots_fit_in_opening_hours() {
+        // A Tuesday
+        let date = NaiveDate::from_ymd_opt(2026, 10, 20).unwrap();
+        let slots: Vec<String> = hours()
+            .slots(date)
+            .iter()
+            .map(|slot| slot.format("%H:%M").to_string())
+            .collect();
+        assert_eq!(slots, ["09:00", "10:30", "12:00"]);
+
+        assert!(hours().is_slot(date.and_hms_opt(10, 30, 0).unwrap()));
+        assert!(!hours().is_slot(date.and_hms_opt(10, 0, 0).unwrap()));
+
+        // Closed on Saturdays
+        let saturday = NaiveDate::from_ymd_opt(2026, 10, 24).unwrap();
+        assert!(hours().slots(saturday).is_empty());
+        assert!(!hours().is_slot(saturday.and_hms_opt(9, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn measures_distance() {
+        // Plaza de Armas to Viña del Mar
+        let distance = distance_km((-33.4378, -70.6505), (-33.0245, -71.5518));
+        assert!((distance - 95.0).abs() < 5.0, "{}", distance);
+        assert_eq!(distance_km((-33.4, -70.6), (-33.4, -70.6)), 0.0);
+    }
+}
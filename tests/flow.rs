@@ -0,0 +1,821 @@
+// End to end quote flow: GET /vehicle -> POST /quote -> POST /plan -> GET /plan/:id.
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{
+    test_app, TestApp, ADMIN_API_KEY, API_KEY, CLIENT_BASE_URL, REGCHECK_VEHICLE, REVENIU_HOST,
+};
+use serde_json::{json, Value};
+
+macro_rules! app {
+    () => {
+        match test_app() {
+            Some(app) => app,
+            None => return,
+        }
+    };
+}
+
+async fn get_vehicle(app: &TestApp, license_plate: &str) -> (StatusCode, Value) {
+    let res = app
+        .client()
+        .get(app.endpoint("/vehicle"))
+        .query(&[("license_plate", license_plate)])
+        .send()
+        .await
+        .unwrap();
+
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+async fn create_quote(
+    app: &TestApp,
+    license_plate: &str,
+    client_name: &str,
+) -> (StatusCode, Value) {
+    let res = app
+        .client()
+        .post(app.endpoint("/quote"))
+        .json(&json!({
+            "license_plate": license_plate,
+            "fuel_consumption": 12.5,
+            "email": "cliente@example.com",
+            "client_name": client_name,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+async fn create_plan(app: &TestApp, path: &str, quote: &Value, body: Value) -> (StatusCode, Value) {
+    let res = app
+        .client()
+        .post(app.endpoint(path))
+        .query(&[("access_token", quote["access_token"].as_str().unwrap())])
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+async fn get_plan(app: &TestApp, path: &str, access_token: Option<&str>) -> (StatusCode, Value) {
+    let mut req = app.client().get(app.endpoint(path));
+    if let Some(access_token) = access_token {
+        req = req.query(&[("access_token", access_token)]);
+    }
+    let res = req.send().await.unwrap();
+
+    (res.status(), res.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn card_plan_gets_a_payment_link() {
+    let app = app!();
+    app.regcheck_responds("GKSB78", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, vehicle) = get_vehicle(app, "gk-sb-78").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(vehicle["license_plate"], "GKSB78");
+    assert_eq!(vehicle["make"], "TOYOTA");
+    assert_eq!(vehicle["year"], "2015");
+
+    // Known vehicles come from the database without another lookup.
+    let (status, vehicle) = get_vehicle(app, "GKSB78").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(vehicle["model"], "YARIS");
+    assert_eq!(app.regcheck_calls("GKSB78"), 1);
+
+    let (status, quote) = create_quote(app, "GKSB78", "Ana Card").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(quote["monthly_cost"].as_f64().unwrap() > 0.0);
+
+    let body = json!({"quote_id": quote["id"], "payment_method": 2, "sign_method": 0});
+    let (status, plan) = create_plan(app, "/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(plan["payment_method"], 2);
+    let payment_link = plan["payment_link"].as_str().unwrap();
+    assert!(payment_link.starts_with(&format!("{}/checkout-custom-link/", REVENIU_HOST)));
+    assert_eq!(app.reveniu_plans_for("Ana Card"), 1);
+
+    let plan_path = format!("/plan/{}", plan["id"].as_str().unwrap());
+    let (status, stored) = get_plan(app, &plan_path, plan["access_token"].as_str()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored["id"], plan["id"]);
+    assert_eq!(stored["payment_link"], payment_link);
+    assert_eq!(stored["sign_method"], 0);
+}
+
+#[tokio::test]
+async fn cash_plan_through_v2() {
+    let app = app!();
+    app.regcheck_responds("HDRT45", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, _) = get_vehicle(app, "HDRT45").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, quote) = create_quote(app, "HDRT45", "Bruno Cash").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let body = json!({"quote_id": quote["id"], "payment_method": "cash", "sign_method": "digital"});
+    let (status, plan) = create_plan(app, "/v2/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(plan["payment_method"], "cash");
+    assert_eq!(plan["payment_link"], Value::Null);
+    assert_eq!(app.reveniu_plans_for("Bruno Cash"), 0);
+
+    let plan_path = format!("/v2/plan/{}", plan["id"].as_str().unwrap());
+    let (status, stored) = get_plan(app, &plan_path, plan["access_token"].as_str()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored["sign_method"], "digital");
+
+    // The plan link needs the plan's token, a quote token doesn't do.
+    let (status, _) = get_plan(app, &plan_path, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = get_plan(app, &plan_path, quote["access_token"].as_str()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Staff can look at any plan.
+    let res = app
+        .client()
+        .get(app.endpoint(&plan_path))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn unknown_plate_requires_manual_entry() {
+    let app = app!();
+
+    let (status, _) = get_vehicle(app, "JKLP23").await;
+    assert_eq!(status, StatusCode::FAILED_DEPENDENCY);
+
+    // The failed lookup is cached instead of hitting RegCheck again.
+    let (status, _) = get_vehicle(app, "JKLP23").await;
+    assert_eq!(status, StatusCode::FAILED_DEPENDENCY);
+    assert_eq!(app.regcheck_calls("JKLP23"), 1);
+
+    let (status, _) = create_quote(app, "JKLP23", "Carla Unknown").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn regcheck_outage_requires_manual_entry() {
+    let app = app!();
+    app.regcheck_responds(
+        "FGHJ56",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "<html><body>Service Unavailable</body></html>",
+    );
+    app.regcheck_responds("FGHK56", StatusCode::OK, "<Vehicle><vehicleJson>{");
+
+    let (status, _) = get_vehicle(app, "FGHJ56").await;
+    assert_eq!(status, StatusCode::FAILED_DEPENDENCY);
+
+    let (status, _) = get_vehicle(app, "FGHK56").await;
+    assert_eq!(status, StatusCode::FAILED_DEPENDENCY);
+}
+
+#[tokio::test]
+async fn reveniu_failure_is_retried_in_background() {
+    let app = app!();
+    app.regcheck_responds("KLPR89", StatusCode::OK, REGCHECK_VEHICLE);
+    app.reveniu_fails_for("Diego Declined");
+
+    let (status, _) = get_vehicle(app, "KLPR89").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, quote) = create_quote(app, "KLPR89", "Diego Declined").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The plan is created without a payment link, and Reveniu is retried by a job.
+    let body = json!({"quote_id": quote["id"], "payment_method": 3, "sign_method": 1});
+    let (status, plan) = create_plan(app, "/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(plan["payment_link"], Value::Null);
+
+    let retry_job = || async {
+        let res = app
+            .client()
+            .get(app.endpoint("/jobs"))
+            .query(&[("kind", "create-reveniu-plan")])
+            .header("x-api-key", ADMIN_API_KEY)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<Vec<Value>>()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|job| job["payload"]["plan_id"] == plan["id"])
+            .unwrap()
+    };
+
+    // Only admins can see jobs.
+    let res = app
+        .client()
+        .get(app.endpoint("/jobs"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // Wait for the first retry to fail too, then run it again once Reveniu is back.
+    let mut job = retry_job().await;
+    for _ in 0..60 {
+        if job["attempts"] == 1 && job["status"] == "pending" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        job = retry_job().await;
+    }
+    assert_eq!(job["status"], "pending");
+    assert!(job["last_error"].as_str().unwrap().contains("Reveniu"));
+
+    app.reveniu_recovers_for("Diego Declined");
+    let res = app
+        .client()
+        .post(app.endpoint(&format!("/jobs/{}/retry", job["id"].as_str().unwrap())))
+        .header("x-api-key", ADMIN_API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    for _ in 0..60 {
+        if retry_job().await["status"] == "succeeded" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    assert_eq!(retry_job().await["status"], "succeeded");
+    assert_eq!(app.reveniu_plans_for("Diego Declined"), 1);
+
+    let plan_path = format!("/plan/{}", plan["id"].as_str().unwrap());
+    let (status, stored) = get_plan(app, &plan_path, plan["access_token"].as_str()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(stored["payment_link"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("{}/checkout-custom-link/", REVENIU_HOST)));
+}
+
+#[tokio::test]
+async fn retried_plan_is_created_once() {
+    let app = app!();
+    app.regcheck_responds("LMNP34", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, _) = get_vehicle(app, "LMNP34").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, quote) = create_quote(app, "LMNP34", "Elena Retry").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let body = json!({"quote_id": quote["id"], "payment_method": 2, "sign_method": 0});
+    let mut plans = vec![];
+    for _ in 0..2 {
+        let res = app
+            .client()
+            .post(app.endpoint("/plan"))
+            .query(&[("access_token", quote["access_token"].as_str().unwrap())])
+            .header("idempotency-key", "elena-retry-1")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        plans.push(res.json::<Value>().await.unwrap());
+    }
+
+    assert_eq!(plans[0]["id"], plans[1]["id"]);
+    assert_eq!(app.reveniu_plans_for("Elena Retry"), 1);
+
+    let res = app
+        .client()
+        .post(app.endpoint("/plan"))
+        .query(&[("access_token", quote["access_token"].as_str().unwrap())])
+        .header("idempotency-key", "elena-retry-1")
+        .json(&json!({"quote_id": quote["id"], "payment_method": 0, "sign_method": 0}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(app.reveniu_plans_for("Elena Retry"), 1);
+}
+
+#[tokio::test]
+async fn quote_and_plan_emails_are_queued() {
+    let app = app!();
+    app.regcheck_responds("MNPR56", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, _) = get_vehicle(app, "MNPR56").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let res = app
+        .client()
+        .post(app.endpoint("/quote"))
+        .json(&json!({
+            "license_plate": "MNPR56",
+            "fuel_consumption": 12.5,
+            "email": "fernanda@example.com",
+            "client_name": "Fernanda Mail",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let quote: Value = res.json().await.unwrap();
+
+    let body = json!({"quote_id": quote["id"], "payment_method": 0, "sign_method": 1});
+    let (status, plan) = create_plan(app, "/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let notifications = |app: &'static TestApp| async move {
+        let res = app
+            .client()
+            .get(app.endpoint("/notifications"))
+            .query(&[("recipient", "fernanda@example.com")])
+            .header("x-api-key", API_KEY)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<Vec<Value>>().await.unwrap()
+    };
+
+    let mut templates: Vec<String> = notifications(app)
+        .await
+        .iter()
+        .map(|notification| {
+            assert_ne!(notification["status"], "skipped");
+            notification["template"].as_str().unwrap().to_string()
+        })
+        .collect();
+    templates.sort();
+    assert_eq!(templates, ["contract_to_sign", "plan_created", "quote_summary"]);
+
+    // After opting out, notifications are recorded but not sent.
+    let res = app
+        .client()
+        .post(app.endpoint("/notifications/opt-out"))
+        .header("x-api-key", API_KEY)
+        .json(&json!({"email": "fernanda@example.com"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .client()
+        .post(app.endpoint(&format!("/plan/{}/payment-events", plan["id"].as_str().unwrap())))
+        .header("x-api-key", API_KEY)
+        .json(&json!({"outcome": "received"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    let latest = &notifications(app).await[0];
+    assert_eq!(latest["template"], "payment_received");
+    assert_eq!(latest["status"], "skipped");
+}
+
+#[tokio::test]
+async fn whatsapp_clients_get_messages() {
+    let app = app!();
+    app.regcheck_responds("PRST67", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, _) = get_vehicle(app, "PRST67").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let quote_body = |phone: Option<&str>| {
+        json!({
+            "license_plate": "PRST67",
+            "fuel_consumption": 12.5,
+            "email": "gabriel@example.com",
+            "client_name": "Gabriel Phone",
+            "phone": phone,
+            "notification_channel": "whatsapp",
+        })
+    };
+
+    // WhatsApp needs a phone number, and it has to be a Chilean mobile.
+    for phone in [None, Some("+56 2 2123 4567")] {
+        let res = app
+            .client()
+            .post(app.endpoint("/quote"))
+            .json(&quote_body(phone))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let res = app
+        .client()
+        .post(app.endpoint("/quote"))
+        .json(&quote_body(Some("9 8765 4321")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let quote: Value = res.json().await.unwrap();
+
+    // Someone else quoting with the same email doesn't redirect the first
+    // client's notifications to their phone.
+    let res = app
+        .client()
+        .post(app.endpoint("/quote"))
+        .json(&quote_body(Some("9 1111 2222")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let body = json!({"quote_id": quote["id"], "payment_method": 0, "sign_method": 0});
+    let (status, _) = create_plan(app, "/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The outbox polls every few seconds.
+    for _ in 0..60 {
+        if app.messages_to("whatsapp", "56987654321").len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+    let messages = app.messages_to("whatsapp", "56987654321");
+    assert_eq!(messages.len(), 2);
+    assert!(messages.iter().all(|message| message.contains("PRST67")));
+    // WhatsApp gets the approved templates, not free text.
+    assert!(messages.iter().any(|message| message.starts_with("quote_summary: ")));
+    assert!(messages.iter().any(|message| message.starts_with("plan_created: ")));
+    // Links point to the client app whatever Host the request came with.
+    let quote_link = format!("{}/quote/{}", CLIENT_BASE_URL, quote["id"].as_str().unwrap());
+    assert!(messages.iter().any(|message| message.contains(&quote_link)));
+    assert_eq!(app.messages_to("whatsapp", "56911112222").len(), 1);
+}
+
+#[tokio::test]
+async fn paid_plan_books_workshop_appointment() {
+    let app = app!();
+    app.regcheck_responds("TRWQ12", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let workshop_body = |opens_at: &str| {
+        json!({
+            "name": "Taller Ñuñoa",
+            "address": "Irarrázaval 1234",
+            "comuna": "Ñuñoa",
+            "region": "Metropolitana",
+            "latitude": -33.4543,
+            "longitude": -70.5962,
+            "services": ["maintenance", "brakes"],
+            "capacity": 1,
+            "opens_at": opens_at,
+            "closes_at": "12:00",
+            "weekdays": [1, 2, 3, 4, 5, 6, 7],
+        })
+    };
+    let res = app
+        .client()
+        .post(app.endpoint("/workshops"))
+        .header("x-api-key", API_KEY)
+        .json(&workshop_body("13:00"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app
+        .client()
+        .post(app.endpoint("/workshops"))
+        .header("x-api-key", API_KEY)
+        .json(&workshop_body("09:00"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let workshop: Value = res.json().await.unwrap();
+    let workshop_id = workshop["id"].as_str().unwrap();
+
+    // Listed near Plaza Ñuñoa, with its distance.
+    let res = app
+        .client()
+        .get(app.endpoint("/workshops"))
+        .query(&[
+            ("service", "brakes"),
+            ("latitude", "-33.4569"),
+            ("longitude", "-70.5978"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    let workshops: Vec<Value> = res.json().await.unwrap();
+    let listed = workshops
+        .iter()
+        .find(|listed| listed["id"] == workshop["id"])
+        .unwrap();
+    assert!(listed["distance_km"].as_f64().unwrap() < 1.0);
+
+    let (status, _) = get_vehicle(app, "TRWQ12").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, quote) = create_quote(app, "TRWQ12", "Gloria Taller").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let body = json!({"quote_id": quote["id"], "payment_method": 0, "sign_method": 0});
+    let (status, plan) = create_plan(app, "/plan", &quote, body).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let plan_id = plan["id"].as_str().unwrap();
+    let access_token = plan["access_token"].as_str().unwrap();
+
+    let date = (chrono::Utc::now().date_naive() + chrono::Duration::days(3))
+        .format("%Y-%m-%d")
+        .to_string();
+    let appointments_path = format!("/plan/{}/appointments", plan_id);
+    let book = |starts_at: &str| {
+        app.client()
+            .post(app.endpoint(&appointments_path))
+            .query(&[("access_token", access_token)])
+            .json(&json!({
+                "workshop_id": workshop_id,
+                "service": "maintenance",
+                "starts_at": format!("{}T{}", date, starts_at),
+            }))
+            .send()
+    };
+    let slots = || async {
+        let res = app
+            .client()
+            .get(app.endpoint(&format!("/workshops/{}/slots", workshop_id)))
+            .query(&[("date", date.as_str())])
+            .send()
+            .await
+            .unwrap();
+        res.json::<Vec<Value>>()
+            .await
+            .unwrap()
+            .iter()
+            .map(|slot| slot["available"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // Only paid plans can book or have a schedule.
+    assert_eq!(book("09:00:00").await.unwrap().status(), StatusCode::CONFLICT);
+    let res = app
+        .client()
+        .get(app.endpoint(&format!("/plan/{}/schedule", plan_id)))
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let res = app
+        .client()
+        .post(app.endpoint(&format!("/plan/{}/payment-events", plan_id)))
+        .header("x-api-key", API_KEY)
+        .json(&json!({"outcome": "received"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    assert_eq!(slots().await, [1, 1, 1]);
+    assert_eq!(
+        book("09:30:00").await.unwrap().status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    let res = book("09:00:00").await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let appointment: Value = res.json().await.unwrap();
+    assert_eq!(appointment["license_plate"], "TRWQ12");
+    assert_eq!(appointment["status"], "booked");
+    assert_eq!(slots().await, [0, 1, 1]);
+
+    // One upcoming appointment per plan.
+    assert_eq!(book("10:00:00").await.unwrap().status(), StatusCode::CONFLICT);
+
+    let appointment_path = format!("{}/{}", appointments_path, appointment["id"].as_str().unwrap());
+    let res = app
+        .client()
+        .put(app.endpoint(&appointment_path))
+        .query(&[("access_token", access_token)])
+        .json(&json!({
+            "workshop_id": workshop_id,
+            "service": "brakes",
+            "starts_at": format!("{}T10:00:00", date),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(slots().await, [1, 0, 1]);
+
+    let cancel = || {
+        app.client()
+            .delete(app.endpoint(&appointment_path))
+            .query(&[("access_token", access_token)])
+            .send()
+    };
+    assert_eq!(cancel().await.unwrap().status(), StatusCode::OK);
+    assert_eq!(cancel().await.unwrap().status(), StatusCode::CONFLICT);
+    assert_eq!(slots().await, [1, 1, 1]);
+
+    let res = app
+        .client()
+        .get(app.endpoint(&appointments_path))
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    let appointments: Vec<Value> = res.json().await.unwrap();
+    assert_eq!(appointments.len(), 1);
+    assert_eq!(appointments[0]["service"], "brakes");
+    assert_eq!(appointments[0]["status"], "cancelled");
+
+    // The workshop completes the next one, ticking off the plan's first
+    // maintenance.
+    let res = book("11:00:00").await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let appointment: Value = res.json().await.unwrap();
+    let complete_path = format!(
+        "{}/{}/complete",
+        appointments_path,
+        appointment["id"].as_str().unwrap()
+    );
+    let complete = || {
+        app.client()
+            .post(app.endpoint(&complete_path))
+            .header("x-api-key", API_KEY)
+            .send()
+    };
+    assert_eq!(complete().await.unwrap().status(), StatusCode::OK);
+    assert_eq!(complete().await.unwrap().status(), StatusCode::CONFLICT);
+
+    let res = app
+        .client()
+        .get(app.endpoint(&format!("/plan/{}/schedule", plan_id)))
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let schedule: Value = res.json().await.unwrap();
+    assert_eq!(schedule["license_plate"], "TRWQ12");
+    let maintenance: Vec<&Value> = schedule["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|item| item["service"] == "maintenance")
+        .collect();
+    assert_eq!(maintenance.len(), 2);
+    assert_eq!(maintenance[0]["cycle"], 6);
+    assert_eq!(maintenance[0]["status"], "done");
+    assert_eq!(maintenance[0]["appointment_id"], appointment["id"]);
+    assert_eq!(maintenance[1]["status"], "pending");
+
+    // The workshop claims the labour against the plan's coverage.
+    let coverage = quote["labour_coverage"].as_f64().unwrap() as i64;
+    let claim = |appointment_id: &Value, amount: i64| {
+        app.client()
+            .post(app.endpoint(&format!("/plan/{}/claims", plan_id)))
+            .header("x-api-key", API_KEY)
+            .json(&json!({
+                "workshop_id": workshop_id,
+                "appointment_id": appointment_id,
+                "lines": [
+                    {"service": "maintenance", "description": "Mantención 10.000 km", "labour_hours": 2, "amount": amount},
+                ],
+            }))
+            .send()
+    };
+    let res = claim(&appointment["id"], 30_000).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let approved: Value = res.json().await.unwrap();
+    assert_eq!(approved["status"], "approved");
+    assert_eq!(approved["reasons"], json!([]));
+
+    let res = claim(&appointment["id"], 30_000).await.unwrap();
+    let duplicate: Value = res.json().await.unwrap();
+    assert_eq!(duplicate["status"], "rejected");
+    assert_eq!(duplicate["reasons"][0]["code"], "duplicate_claim");
+
+    let res = claim(&Value::Null, coverage).await.unwrap();
+    let over: Value = res.json().await.unwrap();
+    assert_eq!(over["status"], "rejected");
+    assert_eq!(over["reasons"][0]["code"], "exceeds_coverage");
+
+    let res = app
+        .client()
+        .get(app.endpoint(&format!("/v2/plan/{}", plan_id)))
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    let plan: Value = res.json().await.unwrap();
+    assert_eq!(plan["coverage"]["total"].as_i64().unwrap(), coverage);
+    assert_eq!(plan["coverage"]["used"].as_i64().unwrap(), 30_000);
+    assert_eq!(plan["coverage"]["remaining"].as_i64().unwrap(), coverage - 30_000);
+
+    let res = app
+        .client()
+        .get(app.endpoint(&format!("/plan/{}/claims", plan_id)))
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    let claims: Vec<Value> = res.json().await.unwrap();
+    assert_eq!(claims.len(), 3);
+    assert!(claims.iter().all(|claim| claim["lines"].as_array().unwrap().len() == 1));
+}
+
+#[tokio::test]
+async fn manual_vehicles_are_quoted_once_reviewed() {
+    let app = app!();
+
+    let res = app
+        .client()
+        .post(app.endpoint("/vehicle/manual"))
+        .json(&json!({
+            "license_plate": "PRST12",
+            "make": "Suzuki",
+            "model": "Swift",
+            "year": "2019",
+            "vehicle_type": "hatchback",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let (status, _) = create_quote(app, "PRST12", "Ignacio Manual").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let res = app
+        .client()
+        .get(app.endpoint("/vehicle/review-queue"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    let queue: Vec<Value> = res.json().await.unwrap();
+    assert!(queue.iter().any(|vehicle| vehicle["license_plate"] == "PRST12"));
+
+    let res = app
+        .client()
+        .post(app.endpoint("/vehicle/PRST12/review"))
+        .header("x-api-key", API_KEY)
+        .json(&json!({"approved": true}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let (status, _) = create_quote(app, "PRST12", "Ignacio Manual").await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn refresh_records_long_image_urls() {
+    let app = app!();
+    app.regcheck_responds("WXYZ34", StatusCode::OK, REGCHECK_VEHICLE);
+
+    let (status, _) = get_vehicle(app, "WXYZ34").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let image_url = format!("http://cl.matriculaapi.com/image.aspx/@{}", "A".repeat(300));
+    app.regcheck_responds(
+        "WXYZ34",
+        StatusCode::OK,
+        &REGCHECK_VEHICLE.replace(
+            "http://cl.matriculaapi.com/image.aspx/@VE9ZT1RBIFlBUklT",
+            &image_url,
+        ),
+    );
+
+    let res = app
+        .client()
+        .post(app.endpoint("/vehicle/WXYZ34/refresh"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app
+        .client()
+        .get(app.endpoint("/vehicle/WXYZ34/history"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    let history: Vec<Value> = res.json().await.unwrap();
+    assert!(history.iter().any(|change| {
+        change["field"] == "image_url" && change["new_value"] == image_url.as_str()
+    }));
+}